@@ -0,0 +1,94 @@
+extern crate coinched;
+extern crate clap;
+extern crate hyper;
+extern crate rustc_serialize;
+
+use std::process;
+
+use clap::{Arg, App, SubCommand};
+use rustc_serialize::json;
+
+use coinched::server::admin::AdminToken;
+
+fn main() {
+    let matches = App::new("coinched-admin")
+                      .version(env!("CARGO_PKG_VERSION"))
+                      .author("Alexandre Bury <alexandre.bury@gmail.com>")
+                      .about("Admin console for a running coinche server")
+                      .arg(Arg::with_name("HOST")
+                               .help("Admin address of the server (defaults to localhost:3001)")
+                               .long("host")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TOKEN")
+                               .help("Admin token, as printed by the server")
+                               .long("token")
+                               .required(true)
+                               .takes_value(true))
+                      .subcommand(SubCommand::with_name("parties").about("Lists running parties"))
+                      .subcommand(SubCommand::with_name("party")
+                                      .about("Shows the full state of a party, including hands")
+                                      .arg(Arg::with_name("ID")
+                                               .help("Party ID")
+                                               .required(true)
+                                               .index(1)))
+                      .subcommand(SubCommand::with_name("events")
+                                      .about("Dumps the event log of a party")
+                                      .arg(Arg::with_name("ID")
+                                               .help("Party ID")
+                                               .required(true)
+                                               .index(1)))
+                      .subcommand(SubCommand::with_name("kick")
+                                      .about("Removes a player from his party")
+                                      .arg(Arg::with_name("ID")
+                                               .help("Player ID")
+                                               .required(true)
+                                               .index(1)))
+                      .subcommand(SubCommand::with_name("cancel")
+                                      .about("Cancels a party")
+                                      .arg(Arg::with_name("ID")
+                                               .help("Party ID")
+                                               .required(true)
+                                               .index(1)))
+                      .get_matches();
+
+    let host = matches.value_of("HOST").unwrap_or("localhost:3001");
+    let token = matches.value_of("TOKEN").unwrap().to_string();
+
+    let (method, path) = match matches.subcommand() {
+        ("parties", _) => (hyper::method::Method::Get, "parties".to_string()),
+        (command, Some(sub)) => {
+            let id = sub.value_of("ID").unwrap();
+            let method = match command {
+                "kick" | "cancel" => hyper::method::Method::Post,
+                _ => hyper::method::Method::Get,
+            };
+            (method, format!("{}/{}", command, id))
+        }
+        _ => {
+            println!("{}", matches.usage());
+            process::exit(1);
+        }
+    };
+
+    let client = hyper::Client::new();
+    let url = format!("http://{}/{}", host, path);
+    let mut response = match client.request(method, &url).header(AdminToken(token)).send() {
+        Ok(response) => response,
+        Err(err) => {
+            println!("Could not reach the server: {}", err);
+            process::exit(1);
+        }
+    };
+
+    match json::Json::from_reader(&mut response) {
+        Ok(body) => println!("{}", body.pretty()),
+        Err(err) => {
+            println!("Invalid response: {}", err);
+            process::exit(1);
+        }
+    }
+
+    if response.status != hyper::status::StatusCode::Ok {
+        process::exit(1);
+    }
+}
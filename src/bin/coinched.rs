@@ -1,39 +1,324 @@
 extern crate coinched;
+extern crate rand;
 extern crate clap;
 extern crate env_logger;
 #[macro_use]
 extern crate log;
 
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
 use std::str::FromStr;
-use clap::{Arg, App};
+use clap::{Arg, App, ArgMatches};
 
-fn main() {
-    env_logger::init().unwrap();
+use coinched::server::config::Config;
+
+// Parses the value of the given flag, if any. Exits if it is invalid.
+fn parse_flag<T>(matches: &ArgMatches, name: &str, what: &str) -> Option<T>
+    where T: FromStr,
+          T::Err: Display
+{
+    matches.value_of(name).map(|value| {
+        match T::from_str(value) {
+            Ok(value) => value,
+            Err(err) => {
+                println!("Invalid {}: `{}` ({})", what, value, err);
+                std::process::exit(1);
+            }
+        }
+    })
+}
 
+fn main() {
     let matches = App::new("coinched")
                       .version(env!("CARGO_PKG_VERSION"))
                       .author("Alexandre Bury <alexandre.bury@gmail.com>")
                       .about("A coinche server")
+                      .arg(Arg::with_name("CONFIG")
+                               .help("TOML configuration file. Flags override its settings")
+                               .short("c")
+                               .long("config")
+                               .takes_value(true))
+                      .arg(Arg::with_name("ADDRESS")
+                               .help("Address to listen to (defaults to localhost)")
+                               .long("address")
+                               .takes_value(true))
                       .arg(Arg::with_name("PORT")
                                .help("Port to listen to (defaults to 3000)")
                                .short("p")
                                .long("port")
                                .takes_value(true))
+                      .arg(Arg::with_name("TLS_CERT")
+                               .help("Serves HTTPS with this certificate (PEM file)")
+                               .long("tls-cert")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TLS_KEY")
+                               .help("Private key for the HTTPS certificate (PEM file)")
+                               .long("tls-key")
+                               .takes_value(true))
+                      .arg(Arg::with_name("THREADS")
+                               .help("Number of threads serving requests")
+                               .long("threads")
+                               .takes_value(true))
+                      .arg(Arg::with_name("LOG_LEVEL")
+                               .help("Log level: off, error, warn, info, debug or trace \
+                                      (defaults to error)")
+                               .long("log-level")
+                               .takes_value(true))
+                      .arg(Arg::with_name("QUEUE_TIMEOUT")
+                               .help("Seconds before idle players leave the matchmaking queue \
+                                      (defaults to 30)")
+                               .long("queue-timeout")
+                               .takes_value(true))
+                      .arg(Arg::with_name("SHUTDOWN_DEADLINE")
+                               .help("Seconds given to the running deals when shutting down \
+                                      (defaults to 0)")
+                               .long("shutdown-deadline")
+                               .takes_value(true))
+                      .arg(Arg::with_name("DATA_DIR")
+                               .help("Directory where the ratings are kept")
+                               .long("data-dir")
+                               .takes_value(true))
+                      .arg(Arg::with_name("DUPLICATE")
+                               .help("Runs a duplicate tournament for this many pairs (an \
+                                      even number, at least 4)")
+                               .long("duplicate")
+                               .takes_value(true))
+                      .arg(Arg::with_name("DEALS")
+                               .help("Number of deals in each round of the duplicate \
+                                      tournament (defaults to 4)")
+                               .long("deals")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TOURNAMENT")
+                               .help("Runs a tournament for this many teams")
+                               .long("tournament")
+                               .takes_value(true))
+                      .arg(Arg::with_name("FORMAT")
+                               .help("Tournament format: round-robin or knockout (defaults \
+                                      to round-robin)")
+                               .long("format")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TARGET")
+                               .help("Score to reach to win a match (defaults to 1000 in \
+                                      tournaments, and to endless parties otherwise)")
+                               .long("target")
+                               .takes_value(true))
+                      .arg(Arg::with_name("RATINGS")
+                               .help("File where the players ratings are kept (overrides \
+                                      --data-dir)")
+                               .long("ratings")
+                               .takes_value(true))
+                      .arg(Arg::with_name("ADMIN_PORT")
+                               .help("Serves the admin console on this port")
+                               .long("admin-port")
+                               .takes_value(true))
+                      .arg(Arg::with_name("ADMIN_ADDRESS")
+                               .help("Address for the admin console (defaults to localhost)")
+                               .long("admin-address")
+                               .takes_value(true))
+                      .arg(Arg::with_name("ADMIN_TOKEN")
+                               .help("Token required by the admin console (defaults to a \
+                                      random one)")
+                               .long("admin-token")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TCP_PORT")
+                               .help("Also serves the binary protocol on this port")
+                               .long("tcp-port")
+                               .takes_value(true))
+                      .arg(Arg::with_name("CORS_ORIGINS")
+                               .help("Comma-separated origins allowed to call the API from a \
+                                      browser, or `*` for any origin")
+                               .long("cors-origins")
+                               .takes_value(true))
+                      .arg(Arg::with_name("CORS_METHODS")
+                               .help("Comma-separated methods allowed for cross-origin \
+                                      requests (defaults to GET,POST,OPTIONS)")
+                               .long("cors-methods")
+                               .takes_value(true))
+                      .arg(Arg::with_name("CORS_HEADERS")
+                               .help("Comma-separated headers allowed for cross-origin \
+                                      requests (defaults to Content-Type)")
+                               .long("cors-headers")
+                               .takes_value(true))
                       .get_matches();
 
-    let port = if let Some(port) = matches.value_of("PORT") {
-        match u16::from_str(port) {
-            Ok(port) => port,
+    let mut config = match matches.value_of("CONFIG") {
+        Some(path) => {
+            match Config::load(path) {
+                Ok(config) => config,
+                Err(err) => {
+                    println!("Invalid configuration: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => Config::default(),
+    };
+
+    // Command-line flags override the configuration file.
+    if let Some(address) = matches.value_of("ADDRESS") {
+        config.address = address.to_string();
+    }
+    if let Some(port) = parse_flag(&matches, "PORT", "port") {
+        config.port = port;
+    }
+    if let Some(threads) = parse_flag(&matches, "THREADS", "number of threads") {
+        config.threads = Some(threads);
+    }
+    if let Some(tcp_port) = parse_flag(&matches, "TCP_PORT", "TCP port") {
+        config.tcp_port = Some(tcp_port);
+    }
+    if let Some(certificate) = matches.value_of("TLS_CERT") {
+        config.tls_certificate = Some(PathBuf::from(certificate));
+    }
+    if let Some(key) = matches.value_of("TLS_KEY") {
+        config.tls_key = Some(PathBuf::from(key));
+    }
+    if let Some(log_level) = matches.value_of("LOG_LEVEL") {
+        config.log_level = log_level.to_string();
+    }
+    if let Some(timeout) = parse_flag(&matches, "QUEUE_TIMEOUT", "queue timeout") {
+        config.queue_timeout = timeout;
+    }
+    if let Some(deadline) = parse_flag(&matches, "SHUTDOWN_DEADLINE", "shutdown deadline") {
+        config.shutdown_deadline = deadline;
+    }
+    if let Some(target) = parse_flag(&matches, "TARGET", "target score") {
+        config.target = Some(target);
+    }
+    if let Some(dir) = matches.value_of("DATA_DIR") {
+        config.data_dir = Some(PathBuf::from(dir));
+    }
+    if let Some(address) = matches.value_of("ADMIN_ADDRESS") {
+        config.admin_address = address.to_string();
+    }
+    if let Some(admin_port) = parse_flag(&matches, "ADMIN_PORT", "admin port") {
+        config.admin_port = Some(admin_port);
+    }
+    if let Some(token) = matches.value_of("ADMIN_TOKEN") {
+        config.admin_token = Some(token.to_string());
+    }
+
+    if let Err(err) = config.validate() {
+        println!("Invalid configuration: {}", err);
+        std::process::exit(1);
+    }
+
+    // RUST_LOG still refines the configured level.
+    let mut logger = env_logger::LogBuilder::new();
+    logger.parse(&config.log_level);
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        logger.parse(&filters);
+    }
+    logger.init().unwrap();
+
+    let mut server = coinched::server::http::Server::new(config.port)
+                         .with_address(&config.address)
+                         .with_queue_timeout(config.queue_timeout)
+                         .with_shutdown_deadline(config.shutdown_deadline)
+                         .with_http_timeouts(config.http_read_timeout, config.http_write_timeout)
+                         .with_limits(config.limits.clone());
+    if let Some(threads) = config.threads {
+        server = server.with_threads(threads);
+    }
+    if let Some(tcp_port) = config.tcp_port {
+        server = server.with_tcp(tcp_port);
+    }
+    if let (Some(certificate), Some(key)) = (config.tls_certificate.clone(),
+                                             config.tls_key.clone()) {
+        server = server.with_tls(certificate, key);
+    }
+    if let Some(target) = config.target {
+        server = server.with_match_target(target);
+    }
+
+    if let Some(ref dir) = config.data_dir {
+        if let Err(err) = fs::create_dir_all(dir) {
+            println!("Could not create `{}`: {}", dir.display(), err);
+            std::process::exit(1);
+        }
+    }
+
+    let ratings_path = matches.value_of("RATINGS").map(PathBuf::from).or(config.ratings_path());
+    if let Some(path) = ratings_path {
+        match coinched::server::rating::Ratings::load(&path) {
+            Ok(ratings) => server = server.with_ratings(ratings),
             Err(err) => {
-                println!("Invalid port: `{}` ({})", port, err);
+                println!("Could not load ratings from `{}`: {}", path.display(), err);
                 std::process::exit(1);
             }
         }
-    } else {
-        3000
-    };
+    }
+
+    if let Some(pairs) = matches.value_of("DUPLICATE") {
+        let pairs = match usize::from_str(pairs) {
+            Ok(pairs) if pairs >= 4 && pairs % 2 == 0 => pairs,
+            _ => {
+                println!("Invalid number of pairs: `{}` (must be even, and at least 4)", pairs);
+                std::process::exit(1);
+            }
+        };
+        let deals = match usize::from_str(matches.value_of("DEALS").unwrap_or("4")) {
+            Ok(deals) if deals > 0 => deals,
+            _ => {
+                println!("Invalid number of deals");
+                std::process::exit(1);
+            }
+        };
+        server = server.with_duplicate(pairs, deals);
+    }
+
+    if let Some(teams) = matches.value_of("TOURNAMENT") {
+        let teams = match usize::from_str(teams) {
+            Ok(teams) if teams >= 2 => teams,
+            _ => {
+                println!("Invalid number of teams: `{}` (must be at least 2)", teams);
+                std::process::exit(1);
+            }
+        };
+        let format = match matches.value_of("FORMAT").unwrap_or("round-robin").parse() {
+            Ok(format) => format,
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1);
+            }
+        };
+        server = server.with_tournament(format, teams, config.target.unwrap_or(1000));
+    }
+
+    if let Some(admin_port) = config.admin_port {
+        let token = match config.admin_token {
+            Some(ref token) => token.clone(),
+            None => {
+                let token = format!("{:016x}", rand::random::<u64>());
+                println!("Admin token: {}", token);
+                token
+            }
+        };
+        server = server.with_admin(&config.admin_address, admin_port, token);
+    }
 
-    let server = coinched::server::http::Server::new(port);
+    if let Some(origins) = matches.value_of("CORS_ORIGINS") {
+        let list = |s: &str| -> Vec<String> {
+            s.split(',')
+             .map(|item| item.trim().to_string())
+             .filter(|item| !item.is_empty())
+             .collect()
+        };
+        let mut cors = coinched::server::cors::Cors::default();
+        cors.origins = list(origins);
+        if let Some(methods) = matches.value_of("CORS_METHODS") {
+            cors.methods = list(methods);
+        }
+        if let Some(headers) = matches.value_of("CORS_HEADERS") {
+            cors.headers = list(headers);
+        }
+        if cors.origins.is_empty() {
+            println!("Invalid CORS origins: `{}`", origins);
+            std::process::exit(1);
+        }
+        server = server.with_cors(cors);
+    }
 
     server.run();
 }
@@ -0,0 +1,300 @@
+//! Line-based frontend.
+
+use std::fmt;
+use std::io;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use libcoinche::{bid, cards, pos};
+use coinched::{EventType, Emote, EMOTES, BidErrorKind, ErrorKind, PlayErrorKind};
+use coinched::client;
+
+pub struct CliFrontend;
+
+fn parse_bid(line: &str) -> Result<(cards::Suit, bid::Target), String> {
+    let tokens: Vec<&str> = line.trim().split(" ").collect();
+    if tokens.len() != 2 {
+        return Err("Invalid number of tokens".to_string());
+    }
+
+    let target = try!(bid::Target::from_str(tokens[0]));
+    let suit = try!(cards::Suit::from_str(tokens[1]));
+
+    Ok((suit, target))
+}
+
+// Returns the message, if the line is a `/say` command.
+fn parse_say(line: &str) -> Option<&str> {
+    if line == "/say" {
+        Some("")
+    } else if line.starts_with("/say ") {
+        Some(&line[5..])
+    } else {
+        None
+    }
+}
+
+// Returns the emote, if the line is a valid `/emote` command.
+// Emotes are named like `wellplayed`, whatever the case.
+fn parse_emote(line: &str) -> Option<Result<Emote, String>> {
+    if !line.starts_with("/emote") {
+        return None;
+    }
+    let name = line[6..].trim().to_lowercase();
+    Some(EMOTES.iter()
+               .cloned()
+               .find(|emote| emote.name().to_lowercase() == name)
+               .ok_or_else(|| format!("Unknown emote. Try one of: {}", emote_names())))
+}
+
+fn emote_names() -> String {
+    let names: Vec<String> = EMOTES.iter().map(|emote| emote.name().to_lowercase()).collect();
+    names.join(", ")
+}
+
+impl CliFrontend {
+    pub fn input() -> String {
+        let mut buffer = String::new();
+        io::stdin().read_line(&mut buffer).unwrap();
+        // Discard the `\n` at the end
+        buffer.pop().unwrap();
+        buffer
+    }
+
+    // Prints the hand, with an index under each playable card.
+    fn print_cards(hand: cards::Hand, playable: &[cards::Card]) {
+        print!("Cards: [");
+        let cards = hand.list();
+        for card in &cards {
+            print!(" {}", card.to_string());
+        }
+        println!(" ]");
+        print!("        ");
+        for (i, card) in cards.iter().enumerate() {
+            if playable.contains(card) {
+                print!("  {}", i);
+            } else {
+                print!("   ");
+            }
+        }
+        println!("");
+    }
+
+    // Prints the cards played so far in the current trick.
+    fn print_trick(state: &client::TableState) {
+        print!("Trick:");
+        for i in 0..4 {
+            let pos = pos::PlayerPos::from_n(i);
+            match state.trick[i] {
+                Some(card) => print!(" {:?}: {}", pos, card.to_string()),
+                None => print!(" {:?}: -", pos),
+            }
+        }
+        println!("");
+    }
+}
+
+impl<B: client::Backend> client::Frontend<B> for CliFrontend
+    where B::Error: fmt::Debug
+{
+    fn show_error(&mut self, error: B::Error) {
+        println!("Error: {:?}", error);
+    }
+
+    fn action_rejected(&mut self, error: &coinched::Error) {
+        let reason = match error.kind() {
+            ErrorKind::Play(PlayErrorKind::IncorrectSuit) => "you must follow suit.",
+            ErrorKind::Play(PlayErrorKind::InsufficientTrump) => "you must play a trump.",
+            ErrorKind::Play(PlayErrorKind::NonRaisedTrump) => "you must play a higher trump.",
+            ErrorKind::Play(PlayErrorKind::CardMissing) => "you don't have this card.",
+            ErrorKind::Bid(BidErrorKind::NonRaisedTarget) => "you must bid higher.",
+            ErrorKind::Bid(BidErrorKind::InvalidCoinche) => "you can only coinche opponents.",
+            ErrorKind::Play(PlayErrorKind::TurnError) |
+            ErrorKind::Bid(BidErrorKind::TurnError) => "it's not your turn.",
+            _ => &error.error[..],
+        };
+        println!("Invalid action: {}", reason);
+    }
+
+    fn unexpected_event(&mut self, event: EventType) {
+        println!("Unexpected event: {:?}", event);
+    }
+
+    fn party_cancelled(&mut self, msg: &str) {
+        println!("Party cancelled: {}", msg);
+    }
+
+    fn show_chat(&mut self, _: &client::TableState, from: pos::PlayerPos, text: &str) {
+        println!("Player {:?} says: {}", from, text);
+    }
+
+    fn show_emote(&mut self, _: &client::TableState, from: pos::PlayerPos, emote: Emote) {
+        println!("Player {:?}: {}", from, emote.text());
+    }
+
+    fn show_card_played(&mut self,
+                        _: &client::TableState,
+                        pos: pos::PlayerPos,
+                        card: cards::Card) {
+        println!("Player {:?} played {}", pos, card.to_string());
+    }
+
+    fn show_trick_over(&mut self, state: &client::TableState, winner: pos::PlayerPos) {
+        println!("{:?} gets the trick. Tricks won: {:?}",
+                 winner,
+                 state.tricks_won);
+    }
+
+    fn ask_card(&mut self,
+                state: &client::TableState,
+                legal: &[cards::Card])
+                -> client::GameAction {
+        let cards = state.hand.list();
+        // If we don't know any legal card, let the server decide.
+        let playable = if legal.is_empty() { &cards[..] } else { legal };
+
+        loop {
+            Self::print_trick(state);
+            Self::print_cards(state.hand, playable);
+            println!("What card do you play? (or `leave`, `/say MESSAGE`, `/emote {}`)",
+                     emote_names());
+            print!("> ");
+            io::stdout().flush().unwrap();
+
+            let line = Self::input();
+
+            if line == "leave" {
+                return client::GameAction::Leave;
+            } else if let Some(text) = parse_say(&line) {
+                return client::GameAction::Say(text.to_string());
+            } else if let Some(emote) = parse_emote(&line) {
+                match emote {
+                    Ok(emote) => return client::GameAction::Emote(emote),
+                    Err(msg) => println!("{}", msg),
+                }
+            } else {
+                match usize::from_str(&line) {
+                    Ok(i) if i < cards.len() && playable.contains(&cards[i]) => {
+                        return client::GameAction::PlayCard(cards[i])
+                    }
+                    Ok(i) if i < cards.len() => println!("You cannot play this card."),
+                    _ => println!("Invalid input."),
+                }
+            }
+        }
+    }
+
+    fn game_over(&mut self,
+                 _: &client::TableState,
+                 points: [i32; 2],
+                 winner: pos::Team,
+                 scores: [i32; 2]) {
+        println!("Game over!");
+        println!("{:?} won. Points were {:?} ; scores: {:?}",
+                 winner,
+                 points,
+                 scores);
+    }
+
+    fn match_over(&mut self, winner: pos::Team, scores: [i32; 2]) {
+        println!("Match over!");
+        println!("{:?} won the match. Final scores: {:?}", winner, scores);
+    }
+
+    fn show_pass(&mut self, _: &client::TableState, pos: pos::PlayerPos) {
+        println!("Player {:?} passed", pos);
+    }
+
+    fn show_coinche(&mut self, _: &client::TableState, pos: pos::PlayerPos) {
+        println!("Player {:?} coinched", pos);
+    }
+
+    fn show_bid(&mut self,
+                _: &client::TableState,
+                pos: pos::PlayerPos,
+                suit: cards::Suit,
+                target: bid::Target) {
+        println!("Player {:?} bid {} on {}",
+                 pos,
+                 target.to_string(),
+                 suit.to_string());
+    }
+
+    fn ask_bid(&mut self,
+               state: &client::TableState,
+               legal: &client::LegalBids)
+               -> client::AuctionAction {
+
+        loop {
+            println!("Your turn to bid. Commands:");
+            println!("* `leave`");
+            println!("* `pass`");
+            println!("* `/say MESSAGE`");
+            println!("* `/emote [{}]`", emote_names());
+            if legal.can_coinche {
+                println!("* `coinche`");
+            }
+            if let Some(lowest) = legal.targets.first() {
+                println!("* [{}, ... , Capot] [H,C,D,S]", lowest.to_string());
+            }
+            print!("> ");
+            io::stdout().flush().unwrap();
+
+            let line = Self::input();
+            if let Some(text) = parse_say(&line) {
+                return client::AuctionAction::Say(text.to_string());
+            }
+            match parse_emote(&line) {
+                Some(Ok(emote)) => return client::AuctionAction::Emote(emote),
+                Some(Err(msg)) => {
+                    println!("{}", msg);
+                    continue;
+                }
+                None => (),
+            }
+
+            return match line.as_ref() {
+                // Those are easy actions
+                "leave" => client::AuctionAction::Leave,
+                "pass" => client::AuctionAction::Pass,
+                "coinche" if legal.can_coinche => client::AuctionAction::Coinche,
+                "coinche" => {
+                    println!("You cannot coinche now.");
+                    continue;
+                }
+                line => {
+                    // Here we parse the bid
+                    let contract = match parse_bid(line) {
+                        Err(msg) => {
+                            println!("{}", msg);
+                            continue;
+                        }
+                        Ok(contract) => contract,
+                    };
+                    if !legal.targets.iter().any(|&t| t as usize == contract.1 as usize) {
+                        println!("You must bid higher.");
+                        continue;
+                    }
+
+                    client::AuctionAction::Bid(contract)
+                }
+            };
+        }
+    }
+
+    /// Auction cancelled, back to the start.
+    fn auction_cancelled(&mut self, _: &client::TableState) {
+        println!("Auction cancelled!");
+    }
+
+    /// Auction is complete, we can play now!
+    fn auction_over(&mut self, _: &client::TableState, contract: &bid::Contract) {
+        println!("Auction is over: {:?}", contract);
+    }
+
+    fn start_game(&mut self, _: &client::TableState, first: pos::PlayerPos, hand: cards::Hand) {
+        Self::print_cards(hand, &hand.list());
+
+
+        println!("First player: {:?}", first);
+    }
+}
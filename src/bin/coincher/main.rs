@@ -0,0 +1,233 @@
+extern crate coinched;
+extern crate libcoinche;
+extern crate clap;
+extern crate rustbox;
+
+mod cli;
+mod tui;
+
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+use coinched::{JoinBody, NewTableBody, TableInfo};
+use coinched::client;
+use clap::{Arg, App};
+
+use cli::CliFrontend;
+use tui::TuiFrontend;
+
+fn print_table(table: &TableInfo) {
+    let names: Vec<String> = table.players
+                                  .iter()
+                                  .map(|name| name.clone().unwrap_or("anonymous".to_string()))
+                                  .collect();
+    println!("table {}{}{}{}{} - {}/4 [{}]{}",
+             table.id,
+             if table.private { " (private)" } else { "" },
+             if table.ranked { " (ranked)" } else { "" },
+             table.variant.as_ref().map(|v| format!(" {}", v)).unwrap_or(String::new()),
+             table.target.map(|t| format!(" to {}", t)).unwrap_or(String::new()),
+             table.players.len(),
+             names.join(", "),
+             if table.in_progress { " - playing" } else { "" });
+}
+
+// Shows the lobby, and lets the user pick a table.
+// Leaves the options untouched to use matchmaking instead.
+fn choose_table(host: &client::http::Host, options: &mut JoinBody) {
+    loop {
+        let lobby = match client::http::lobby(host) {
+            Ok(lobby) => lobby,
+            Err(err) => {
+                println!("Could not get the lobby: {:?}", err);
+                return;
+            }
+        };
+
+        println!("Lobby: {} player(s) waiting for a party.", lobby.waiting);
+        let open: Vec<&TableInfo> = lobby.tables.iter().filter(|t| !t.in_progress).collect();
+        for (i, table) in open.iter().enumerate() {
+            print!("[{}] ", i);
+            print_table(table);
+        }
+        for table in lobby.tables.iter().filter(|t| t.in_progress) {
+            print!("    ");
+            print_table(table);
+        }
+
+        println!("Commands:");
+        println!("* [0, 1, ...] to sit at a table");
+        println!("* `new` to create a table");
+        println!("* `refresh`");
+        println!("* nothing to find a party automatically");
+        print!("> ");
+        io::stdout().flush().unwrap();
+
+        let line = CliFrontend::input();
+        match line.as_ref() {
+            "" => return,
+            "refresh" => continue,
+            "new" => {
+                print!("Password (leave empty for a public table): ");
+                io::stdout().flush().unwrap();
+                let password = CliFrontend::input();
+                let password = if password.is_empty() { None } else { Some(password) };
+                print!("Ranked, with emotes only? [y/N]: ");
+                io::stdout().flush().unwrap();
+                let ranked = CliFrontend::input() == "y";
+
+                let table = client::http::create_table(host,
+                                                       &NewTableBody {
+                                                           password: password.clone(),
+                                                           variant: options.variant.clone(),
+                                                           target: options.target,
+                                                           ranked: Some(ranked),
+                                                       });
+                match table {
+                    Ok(table) => {
+                        options.table = Some(table.id);
+                        options.password = password;
+                        return;
+                    }
+                    Err(err) => println!("Could not create the table: {:?}", err),
+                }
+            }
+            line => {
+                match usize::from_str(line) {
+                    Ok(i) if i < open.len() => {
+                        if open[i].private {
+                            print!("Password: ");
+                            io::stdout().flush().unwrap();
+                            options.password = Some(CliFrontend::input());
+                        }
+                        options.table = Some(open[i].id);
+                        return;
+                    }
+                    _ => println!("Invalid input."),
+                }
+            }
+        }
+    }
+}
+
+// Plays the party with the chosen frontend, and returns the final scores.
+fn play<B: client::Backend>(backend: B, tui: bool) -> [i32; 2]
+    where B::Error: fmt::Debug
+{
+    let client = client::Client::new(backend);
+    if tui {
+        // The terminal is restored when the frontend is dropped.
+        let mut frontend = TuiFrontend::new();
+        client.run(&mut frontend)
+    } else {
+        client.run(&mut CliFrontend)
+    }
+}
+
+fn incompatible(version: u32) -> ! {
+    println!("The server speaks protocol version {}, but this client speaks version {}.",
+             version,
+             coinched::PROTOCOL_VERSION);
+    std::process::exit(1);
+}
+
+fn join_failed<E: fmt::Debug>(err: E) -> ! {
+    println!("Could not join a party: {:?}", err);
+    std::process::exit(1);
+}
+
+fn main() {
+    let matches = App::new("coincher")
+                      .version(env!("CARGO_PKG_VERSION"))
+                      .author("Alexandre Bury <alexandre.bury@gmail.com>")
+                      .about("A client for coinched")
+                      .arg(Arg::with_name("HOST")
+                               .help("Specifies the host to connect to, like localhost:3000 or \
+                                      https://example.com")
+                               .required(true)
+                               .index(1))
+                      .arg(Arg::with_name("NAME")
+                               .help("Player name, to be rated")
+                               .short("n")
+                               .long("name")
+                               .takes_value(true))
+                      .arg(Arg::with_name("VARIANT")
+                               .help("Only play with players asking for this rule variant")
+                               .long("variant")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TARGET")
+                               .help("Only play matches to this score")
+                               .long("target")
+                               .takes_value(true))
+                      .arg(Arg::with_name("LANGUAGE")
+                               .help("Only play with players speaking this language")
+                               .long("lang")
+                               .takes_value(true))
+                      .arg(Arg::with_name("BAND")
+                               .help("Only play with players within this rating difference")
+                               .long("rating-band")
+                               .takes_value(true))
+                      .arg(Arg::with_name("CA_FILE")
+                               .help("Also trusts certificates signed by this CA, for \
+                                      self-hosted servers")
+                               .long("ca-file")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TCP")
+                               .help("Plays over the binary protocol on this address, like \
+                                      localhost:3001. The lobby still uses HOST")
+                               .long("tcp")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TUI")
+                               .help("Uses the full-screen interface")
+                               .long("tui"))
+                      .get_matches();
+    let host = matches.value_of("HOST").unwrap();
+    let host = client::http::Host::new(host).and_then(|host| {
+        match matches.value_of("CA_FILE") {
+            Some(ca_file) => host.with_ca_file(ca_file),
+            None => Ok(host),
+        }
+    });
+    let host = match host {
+        Ok(host) => host,
+        Err(err) => {
+            println!("Could not set up the connection: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut options = JoinBody {
+        name: matches.value_of("NAME").map(|s| s.to_string()),
+        variant: matches.value_of("VARIANT").map(|s| s.to_string()),
+        target: matches.value_of("TARGET").map(|s| i32::from_str(s).expect("invalid target")),
+        language: matches.value_of("LANGUAGE").map(|s| s.to_string()),
+        rating_band: matches.value_of("BAND").map(|s| f64::from_str(s).expect("invalid band")),
+        table: None,
+        password: None,
+    };
+
+    choose_table(&host, &mut options);
+
+    // TODO: allow reconnecting to an existing game
+
+    let tui = matches.is_present("TUI");
+    let scores = match matches.value_of("TCP") {
+        Some(address) => {
+            match client::tcp::TcpBackend::join(address, &options) {
+                Ok(backend) => play(backend, tui),
+                Err(client::tcp::Error::Incompatible(version)) => incompatible(version),
+                Err(err) => join_failed(err),
+            }
+        }
+        None => {
+            match client::http::HttpBackend::join(&host, &options) {
+                Ok(backend) => play(backend, tui),
+                Err(client::http::Error::Incompatible(version)) => incompatible(version),
+                Err(err) => join_failed(err),
+            }
+        }
+    };
+
+    println!("Final score: {:?}", scores);
+}
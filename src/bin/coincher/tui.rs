@@ -0,0 +1,390 @@
+//! Full-screen frontend.
+//!
+//! Shows the four seats around the table, the current trick in the middle,
+//! and the hand at the bottom. A side panel holds the contract, the scores
+//! and the auction history.
+
+use std::default::Default;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use rustbox::{self, Color, Key, RustBox};
+use libcoinche::{bid, cards, pos};
+use coinched::{self, Emote, EventType};
+use coinched::client;
+
+// Width of the side panel.
+const PANEL_WIDTH: usize = 32;
+
+const SUITS: [cards::Suit; 4] = [cards::Suit::Heart,
+                                 cards::Suit::Spade,
+                                 cards::Suit::Diamond,
+                                 cards::Suit::Club];
+
+pub struct TuiFrontend {
+    rb: RustBox,
+    // Auction history for the current game.
+    auction: Vec<String>,
+    // Last message for the player, shown on the bottom line.
+    status: String,
+}
+
+fn seat_name(pos: pos::PlayerPos, me: pos::PlayerPos) -> String {
+    if pos == me {
+        "You".to_string()
+    } else if (pos as usize) % 2 == (me as usize) % 2 {
+        format!("{:?} (partner)", pos)
+    } else {
+        format!("{:?}", pos)
+    }
+}
+
+fn card_color(card: cards::Card) -> Color {
+    match card.suit() {
+        cards::Suit::Heart | cards::Suit::Diamond => Color::Red,
+        _ => Color::Default,
+    }
+}
+
+fn suit_color(suit: cards::Suit) -> Color {
+    match suit {
+        cards::Suit::Heart | cards::Suit::Diamond => Color::Red,
+        _ => Color::Default,
+    }
+}
+
+// The hand, sorted by suit. `Hand::list` already sorts each suit by rank.
+fn sorted_hand(hand: cards::Hand) -> Vec<cards::Card> {
+    let mut cards = hand.list();
+    cards.sort_by_key(|card| card.suit() as u32);
+    cards
+}
+
+impl TuiFrontend {
+    pub fn new() -> Self {
+        TuiFrontend {
+            rb: RustBox::init(Default::default()).unwrap(),
+            auction: Vec::new(),
+            status: String::new(),
+        }
+    }
+
+    fn print(&self, x: usize, y: usize, style: rustbox::Style, fg: Color, text: &str) {
+        self.rb.print(x, y, style, fg, Color::Default, text);
+    }
+
+    fn print_centered(&self, x: usize, y: usize, style: rustbox::Style, fg: Color, text: &str) {
+        let len = text.chars().count();
+        self.print(x.saturating_sub(len / 2), y, style, fg, text);
+    }
+
+    // Redraws only the status line.
+    fn draw_status(&self) {
+        let y = self.rb.height().saturating_sub(1);
+        let blank: String = (0..self.rb.width()).map(|_| ' ').collect();
+        self.print(0, y, rustbox::RB_NORMAL, Color::Default, &blank);
+        self.print(0, y, rustbox::RB_BOLD, Color::Yellow, &self.status);
+        self.rb.present();
+    }
+
+    fn set_status<S: Into<String>>(&mut self, status: S) {
+        self.status = status.into();
+    }
+
+    // Waits until the player presses a key.
+    fn wait_key(&self) -> Key {
+        loop {
+            match self.rb.poll_event(false) {
+                Ok(rustbox::Event::KeyEvent(key)) => return key,
+                Ok(_) => (),
+                Err(_) => panic!("could not read terminal events"),
+            }
+        }
+    }
+
+    // Redraws the whole screen.
+    // `selected` is the index of the highlighted card in the sorted hand.
+    fn draw(&self, state: &client::TableState, selected: Option<usize>) {
+        self.rb.clear();
+
+        let width = self.rb.width();
+        let height = self.rb.height();
+        let table_width = width.saturating_sub(PANEL_WIDTH);
+
+        // Table center
+        let cx = table_width / 2;
+        let cy = height.saturating_sub(4) / 2;
+
+        for i in 0..4 {
+            let pos = pos::PlayerPos::from_n(i);
+            // 0 at the bottom, then clockwise.
+            let relative = (i + 4 - state.pos as usize) % 4;
+            let ((nx, ny), (tx, ty)) = match relative {
+                0 => ((cx, cy + 4), (cx, cy + 2)),
+                1 => ((cx.saturating_sub(20), cy), (cx.saturating_sub(6), cy)),
+                2 => ((cx, cy.saturating_sub(4)), (cx, cy.saturating_sub(2))),
+                _ => ((cx + 20, cy), (cx + 6, cy)),
+            };
+
+            let name = seat_name(pos, state.pos);
+            if state.turn == Some(pos) {
+                self.print_centered(nx,
+                                    ny,
+                                    rustbox::RB_BOLD,
+                                    Color::Yellow,
+                                    &format!("> {} <", name));
+            } else {
+                self.print_centered(nx, ny, rustbox::RB_NORMAL, Color::Default, &name);
+            }
+
+            if let Some(card) = state.trick[i] {
+                self.print_centered(tx,
+                                    ty,
+                                    rustbox::RB_BOLD,
+                                    card_color(card),
+                                    &card.to_string());
+            }
+        }
+
+        // The hand, at the bottom.
+        let legal = state.legal_cards();
+        let y = height.saturating_sub(3);
+        self.print(1, y, rustbox::RB_NORMAL, Color::Default, "Hand:");
+        for (i, card) in sorted_hand(state.hand).into_iter().enumerate() {
+            let style = if selected == Some(i) {
+                rustbox::RB_REVERSE
+            } else if selected.is_some() && legal.contains(&card) {
+                rustbox::RB_BOLD
+            } else {
+                rustbox::RB_NORMAL
+            };
+            self.print(8 + 5 * i, y, style, card_color(card), &card.to_string());
+        }
+
+        // Side panel
+        for y in 0..height.saturating_sub(1) {
+            self.print(table_width, y, rustbox::RB_NORMAL, Color::Default, "|");
+        }
+        let x = table_width + 2;
+        let us = state.pos as usize % 2;
+
+        match (&state.contract, state.bid) {
+            (&Some(_), Some((author, suit, target))) => {
+                self.print(x, 1, rustbox::RB_BOLD, Color::Default, "Contract:");
+                self.print(x + 10,
+                           1,
+                           rustbox::RB_NORMAL,
+                           suit_color(suit),
+                           &format!("{} {}", target.to_string(), suit.to_string()));
+                self.print(x,
+                           2,
+                           rustbox::RB_NORMAL,
+                           Color::Default,
+                           &format!("by {}{}",
+                                    seat_name(author, state.pos),
+                                    if state.coinched { ", coinched" } else { "" }));
+            }
+            _ => self.print(x, 1, rustbox::RB_BOLD, Color::Default, "Auction"),
+        }
+
+        self.print(x,
+                   4,
+                   rustbox::RB_NORMAL,
+                   Color::Default,
+                   &format!("Scores: us {} / them {}", state.scores[us], state.scores[1 - us]));
+        self.print(x,
+                   5,
+                   rustbox::RB_NORMAL,
+                   Color::Default,
+                   &format!("Tricks: us {} / them {}",
+                            state.tricks_won[us],
+                            state.tricks_won[1 - us]));
+
+        self.print(x, 7, rustbox::RB_BOLD, Color::Default, "Auction history:");
+        // Only show the last lines if they don't fit.
+        let room = height.saturating_sub(10);
+        let skip = self.auction.len().saturating_sub(room);
+        for (i, line) in self.auction.iter().skip(skip).enumerate() {
+            self.print(x, 8 + i, rustbox::RB_NORMAL, Color::Default, line);
+        }
+
+        self.draw_status();
+    }
+
+    fn log_auction(&mut self, state: &client::TableState, pos: pos::PlayerPos, action: String) {
+        self.auction.push(format!("{}: {}", seat_name(pos, state.pos), action));
+        self.draw(state, None);
+    }
+}
+
+impl<B: client::Backend> client::Frontend<B> for TuiFrontend
+    where B::Error: fmt::Debug
+{
+    fn show_error(&mut self, error: B::Error) {
+        self.set_status(format!("Error: {:?}", error));
+        self.draw_status();
+    }
+
+    fn action_rejected(&mut self, error: &coinched::Error) {
+        self.set_status(format!("Invalid action: {}", error.error));
+        self.draw_status();
+    }
+
+    fn unexpected_event(&mut self, event: EventType) {
+        self.set_status(format!("Unexpected event: {:?}", event));
+        self.draw_status();
+    }
+
+    fn party_cancelled(&mut self, msg: &str) {
+        self.set_status(format!("Party cancelled: {}. Press any key.", msg));
+        self.draw_status();
+        self.wait_key();
+    }
+
+    fn show_chat(&mut self, state: &client::TableState, from: pos::PlayerPos, text: &str) {
+        self.set_status(format!("{}: {}", seat_name(from, state.pos), text));
+        self.draw_status();
+    }
+
+    fn show_emote(&mut self, state: &client::TableState, from: pos::PlayerPos, emote: Emote) {
+        self.set_status(format!("{}: {}", seat_name(from, state.pos), emote.text()));
+        self.draw_status();
+    }
+
+    fn show_card_played(&mut self,
+                        state: &client::TableState,
+                        pos: pos::PlayerPos,
+                        card: cards::Card) {
+        self.set_status(format!("{} played {}", seat_name(pos, state.pos), card.to_string()));
+        self.draw(state, None);
+    }
+
+    fn show_trick_over(&mut self, state: &client::TableState, winner: pos::PlayerPos) {
+        // Leave some time to look at the complete trick.
+        thread::sleep(Duration::from_millis(1500));
+        self.set_status(format!("{} won the trick", seat_name(winner, state.pos)));
+        self.draw(state, None);
+    }
+
+    fn ask_card(&mut self,
+                state: &client::TableState,
+                legal: &[cards::Card])
+                -> client::GameAction {
+        let cards = sorted_hand(state.hand);
+        // Indices of the cards we can select.
+        // If we don't know any legal card, let the server decide.
+        let choices: Vec<usize> = if legal.is_empty() {
+            (0..cards.len()).collect()
+        } else {
+            (0..cards.len()).filter(|&i| legal.contains(&cards[i])).collect()
+        };
+        let mut choice = 0;
+
+        self.set_status("Your turn: Left/Right to choose a card, Enter to play it, q to leave");
+        loop {
+            self.draw(state, choices.get(choice).cloned());
+            match self.wait_key() {
+                Key::Left if choice > 0 => choice -= 1,
+                Key::Right if choice + 1 < choices.len() => choice += 1,
+                Key::Enter if choice < choices.len() => {
+                    return client::GameAction::PlayCard(cards[choices[choice]]);
+                }
+                Key::Char('q') | Key::Esc => return client::GameAction::Leave,
+                _ => (),
+            }
+        }
+    }
+
+    fn game_over(&mut self,
+                 state: &client::TableState,
+                 points: [i32; 2],
+                 winner: pos::Team,
+                 _: [i32; 2]) {
+        self.set_status(format!("Game over: {:?} won ({} - {}). Press any key.",
+                                winner,
+                                points[0],
+                                points[1]));
+        self.draw(state, None);
+        self.wait_key();
+    }
+
+    fn match_over(&mut self, winner: pos::Team, scores: [i32; 2]) {
+        self.set_status(format!("Match over: {:?} won ({} - {}). Press any key.",
+                                winner,
+                                scores[0],
+                                scores[1]));
+        self.draw_status();
+        self.wait_key();
+    }
+
+    fn show_pass(&mut self, state: &client::TableState, pos: pos::PlayerPos) {
+        self.log_auction(state, pos, "pass".to_string());
+    }
+
+    fn show_coinche(&mut self, state: &client::TableState, pos: pos::PlayerPos) {
+        self.log_auction(state, pos, "coinche".to_string());
+    }
+
+    fn show_bid(&mut self,
+                state: &client::TableState,
+                pos: pos::PlayerPos,
+                suit: cards::Suit,
+                target: bid::Target) {
+        self.log_auction(state,
+                         pos,
+                         format!("{} {}", target.to_string(), suit.to_string()));
+    }
+
+    fn ask_bid(&mut self,
+               state: &client::TableState,
+               legal: &client::LegalBids)
+               -> client::AuctionAction {
+        let mut target = 0;
+        let mut suit = 0;
+
+        loop {
+            let bid = match legal.targets.get(target) {
+                Some(target) => {
+                    format!("{} {} (Up/Down: target, Left/Right: suit, Enter: bid), ",
+                            target.to_string(),
+                            SUITS[suit].to_string())
+                }
+                None => String::new(),
+            };
+            let coinche = if legal.can_coinche { "c: coinche, " } else { "" };
+            self.set_status(format!("Your bid: {}p: pass, {}q: leave", bid, coinche));
+            self.draw(state, None);
+
+            match self.wait_key() {
+                Key::Up if target + 1 < legal.targets.len() => target += 1,
+                Key::Down if target > 0 => target -= 1,
+                Key::Left => suit = (suit + 3) % 4,
+                Key::Right => suit = (suit + 1) % 4,
+                Key::Enter if target < legal.targets.len() => {
+                    return client::AuctionAction::Bid((SUITS[suit], legal.targets[target]));
+                }
+                Key::Char('p') => return client::AuctionAction::Pass,
+                Key::Char('c') if legal.can_coinche => return client::AuctionAction::Coinche,
+                Key::Char('q') | Key::Esc => return client::AuctionAction::Leave,
+                _ => (),
+            }
+        }
+    }
+
+    fn auction_cancelled(&mut self, state: &client::TableState) {
+        self.set_status("Everybody passed: new deal");
+        self.draw(state, None);
+    }
+
+    fn auction_over(&mut self, state: &client::TableState, _: &bid::Contract) {
+        self.set_status("Auction is over");
+        self.draw(state, None);
+    }
+
+    fn start_game(&mut self, state: &client::TableState, first: pos::PlayerPos, _: cards::Hand) {
+        self.auction.clear();
+        self.set_status(format!("New game: {} speaks first", seat_name(first, state.pos)));
+        self.draw(state, None);
+    }
+}
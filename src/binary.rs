@@ -0,0 +1,183 @@
+//! Compact binary protocol, spoken over raw TCP.
+//!
+//! Every message is a frame: its length, as a big-endian `u32`, followed by
+//! the message encoded with bincode. Clients send `Request`s, and the server
+//! answers each of them with a `Response`, in order.
+//!
+//! Unknown event types are still decoded as `EventType::Unknown`: the event
+//! is always the last field of its frame, so its remaining bytes are skipped.
+
+use std::io::{self, Read, Write};
+
+use rustc_serialize::{Encodable, Decodable};
+use bincode;
+use bincode::rustc_serialize::{EncodingError, DecodingError};
+use libcoinche::{cards, pos};
+
+use {NewPartyInfo, QueueStatus, EventType, Error};
+use {ContractBody, CardBody, ChatBody, EmoteBody, JoinBody};
+
+/// Largest frame accepted, in bytes.
+pub const MAX_FRAME: u32 = 64 * 1024;
+
+/// Action sent by a client. Mirrors the HTTP API.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub enum Request {
+    /// Joins a table, like `/join`. Answered with `Response::Party`.
+    Join(JoinBody),
+    /// Enters the matchmaking queue, like `/queue`. Answered with `Response::Queue`.
+    Enqueue(JoinBody),
+    /// Checks a queue ticket. Answered with `Response::Queue`.
+    QueueStatus(u32),
+    /// Waits for an event: player ID and event ID. Answered with `Response::Event`.
+    Wait(u32, usize),
+    /// Leaves the party. Answered with `Response::Ok`.
+    Leave(u32),
+    /// Answered with `Response::Hand`.
+    Hand(u32),
+    /// Answered with `Response::Scores`.
+    Scores(u32),
+    /// Answered with `Response::Pos`.
+    Pos(u32),
+
+    // Actions are answered with the event they caused.
+    Pass(u32),
+    Coinche(u32),
+    Bid(u32, ContractBody),
+    Play(u32, CardBody),
+
+    /// Sends a chat message. Answered with `Response::Ok`.
+    Chat(u32, ChatBody),
+    /// Sends an emote. Answered with `Response::Ok`.
+    Emote(u32, EmoteBody),
+}
+
+/// Answer from the server.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub enum Response {
+    Ok,
+    Party(NewPartyInfo),
+    Queue(QueueStatus),
+    /// Event ID, and the event itself.
+    Event(usize, EventType),
+    Hand(cards::Hand),
+    Scores([i32; 2]),
+    Pos(pos::PlayerPos),
+    /// The request failed. Same as the errors returned by the HTTP API.
+    Error(Error),
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+    Io(io::Error),
+    Encoding(EncodingError),
+    Decoding(DecodingError),
+    /// The frame is larger than `MAX_FRAME`.
+    TooLarge(u32),
+}
+
+impl From<io::Error> for FrameError {
+    fn from(err: io::Error) -> Self {
+        FrameError::Io(err)
+    }
+}
+
+impl From<EncodingError> for FrameError {
+    fn from(err: EncodingError) -> Self {
+        FrameError::Encoding(err)
+    }
+}
+
+impl From<DecodingError> for FrameError {
+    fn from(err: DecodingError) -> Self {
+        FrameError::Decoding(err)
+    }
+}
+
+/// Writes a single message.
+pub fn write_frame<W: Write, T: Encodable>(writer: &mut W,
+                                           message: &T)
+                                           -> Result<(), FrameError> {
+    let limit = bincode::SizeLimit::Bounded(MAX_FRAME as u64);
+    let payload = try!(bincode::rustc_serialize::encode(message, limit));
+
+    let len = payload.len() as u32;
+    let header = [(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+    try!(writer.write_all(&header));
+    try!(writer.write_all(&payload));
+    try!(writer.flush());
+    Ok(())
+}
+
+/// Reads a single message, blocking until it is complete.
+pub fn read_frame<R: Read, T: Decodable>(reader: &mut R) -> Result<T, FrameError> {
+    let mut header = [0u8; 4];
+    try!(reader.read_exact(&mut header));
+    let len = header.iter().fold(0u32, |len, &byte| (len << 8) | byte as u32);
+    if len > MAX_FRAME {
+        return Err(FrameError::TooLarge(len));
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    try!(reader.read_exact(&mut payload));
+    let message = try!(bincode::rustc_serialize::decode(&payload));
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+
+    use ChatBody;
+    use super::{read_frame, write_frame, FrameError, Request, Response, MAX_FRAME};
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &Request::Wait(3, 12)).unwrap();
+        write_frame(&mut buffer, &Request::Chat(3, ChatBody { text: "Hi!".to_string() }))
+            .unwrap();
+        write_frame(&mut buffer, &Response::Scores([80, 0])).unwrap();
+
+        let mut reader = Cursor::new(buffer);
+        let wait: Request = read_frame(&mut reader).unwrap();
+        assert_eq!(format!("{:?}", wait), format!("{:?}", Request::Wait(3, 12)));
+        match read_frame(&mut reader).unwrap() {
+            Request::Chat(3, body) => assert_eq!(body.text, "Hi!"),
+            request => panic!("unexpected request: {:?}", request),
+        }
+        match read_frame(&mut reader).unwrap() {
+            Response::Scores(scores) => assert_eq!(scores, [80, 0]),
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[test]
+    fn length_prefix() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &Request::Leave(7)).unwrap();
+        let len = buffer[..4].iter().fold(0u32, |len, &byte| (len << 8) | byte as u32);
+        assert_eq!(len as usize, buffer.len() - 4);
+    }
+
+    #[test]
+    fn too_large() {
+        let len = MAX_FRAME + 1;
+        let header = vec![(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+        match read_frame::<_, Request>(&mut Cursor::new(header)) {
+            Err(FrameError::TooLarge(size)) => assert_eq!(size, len),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &Request::Leave(7)).unwrap();
+        buffer.pop();
+        match read_frame::<_, Request>(&mut Cursor::new(buffer)) {
+            Err(FrameError::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
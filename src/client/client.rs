@@ -1,10 +1,11 @@
 use libcoinche::{cards, pos};
 use {PlayerEvent, EventType, ContractBody, CardBody};
-use super::{Backend, AuctionAction, Frontend, GameAction};
+use super::{Backend, AuctionAction, Frontend, GameAction, ServerError};
+use super::state::TableState;
 
 pub struct Client<B: Backend> {
-    pub scores: [i32; 2],
     backend: B,
+    state: TableState,
 }
 
 enum GameError {
@@ -16,20 +17,51 @@ enum GameError {
 impl<B: Backend> Client<B> {
     pub fn new(backend: B) -> Self {
         Client {
-            scores: [0, 0],
+            state: TableState::new(backend.pos()),
             backend: backend,
         }
     }
 
-    pub fn run<F: Frontend<B>>(mut self, frontend: &mut F) -> [i32; 2] {
+    /// Returns the current table state.
+    pub fn state(&self) -> &TableState {
+        &self.state
+    }
+
+    // Keeps track of every event we receive.
+    fn track(&mut self, event: Result<EventType, B::Error>) -> Result<EventType, B::Error> {
+        if let Ok(ref event) = event {
+            self.state.update(event);
+        }
+        event
+    }
+
+    // Skips events from later protocol versions, and shows the chat messages and emotes.
+    fn wait<F: Frontend<B>>(&mut self, frontend: &mut F) -> Result<EventType, B::Error> {
         loop {
             match self.backend.wait() {
+                Ok(EventType::Unknown(kind)) => trace!("Ignoring unknown event: {}", kind),
+                Ok(EventType::Chat { from, text }) => frontend.show_chat(&self.state, from, &text),
+                Ok(EventType::FromPlayer(from, PlayerEvent::Emote(emote))) => {
+                    frontend.show_emote(&self.state, from, emote)
+                }
+                event => return self.track(event),
+            }
+        }
+    }
+
+    pub fn run<F: Frontend<B>>(mut self, frontend: &mut F) -> [i32; 2] {
+        loop {
+            match self.wait(frontend) {
                 Ok(EventType::NewGameRelative {first, hand}) => {
                     match self.run_game(frontend, first, hand) {
-                        Err(GameError::PlayerLeft) => return self.scores,
+                        Err(GameError::PlayerLeft) => return self.state.scores,
                         _ => (),
                     }
                 }
+                Ok(EventType::MatchOver { winner, scores }) => {
+                    frontend.match_over(winner, scores);
+                    return self.state.scores;
+                }
                 Ok(event) => frontend.unexpected_event(event),
                 Err(err) => frontend.show_error(err),
             }
@@ -39,7 +71,7 @@ impl<B: Backend> Client<B> {
     fn run_game<F: Frontend<B>>(&mut self, frontend: &mut F,
                                     first: pos::PlayerPos,
                                     hand: cards::Hand) -> Result<(), GameError> {
-        frontend.start_game(first, hand);
+        frontend.start_game(&self.state, first, hand);
         try!(self.run_auction(frontend));
         try!(self.run_cardgame(frontend));
         Ok(())
@@ -48,38 +80,25 @@ impl<B: Backend> Client<B> {
     // God that's an ugly type. Really, I want `F::Auction::Game`.
     fn run_auction<F: Frontend<B>>(&mut self, frontend: &mut F) -> Result<(), GameError> {
         loop {
-            let mut event = self.backend.wait();
+            let mut event = self.wait(frontend);
             match event {
-                Ok(EventType::YourTurn) => {
-                    event = match frontend.ask_bid() {
-                        AuctionAction::Leave => {
-                            frontend.party_cancelled("you left");
-                            return Err(GameError::PlayerLeft);
-                        }
-                        AuctionAction::Coinche => self.backend.coinche(),
-                        AuctionAction::Pass => self.backend.pass(),
-                        AuctionAction::Bid((suit, target)) => {
-                            self.backend.bid(ContractBody {
-                                suit: suit,
-                                target: target,
-                            })
-                        }
-                    }
-                }
+                Ok(EventType::YourTurn) => event = try!(self.ask_bid(frontend)),
                 _ => (),
             }
 
             match event {
                 Ok(EventType::FromPlayer(pos, e)) => {
                     match e {
-                        PlayerEvent::Bidded(suit, target) => frontend.show_bid(pos, suit, target),
-                        PlayerEvent::Passed => frontend.show_pass(pos),
-                        PlayerEvent::Coinched => frontend.show_coinche(pos),
+                        PlayerEvent::Bidded(suit, target) => {
+                            frontend.show_bid(&self.state, pos, suit, target)
+                        }
+                        PlayerEvent::Passed => frontend.show_pass(&self.state, pos),
+                        PlayerEvent::Coinched => frontend.show_coinche(&self.state, pos),
                         _ => frontend.unexpected_event(EventType::FromPlayer(pos, e)),
                     }
                 }
                 Ok(EventType::BidCancelled) => {
-                    frontend.auction_cancelled();
+                    frontend.auction_cancelled(&self.state);
                     return Err(GameError::NoContract);
                 }
                 Ok(EventType::PartyCancelled(msg)) => {
@@ -87,7 +106,7 @@ impl<B: Backend> Client<B> {
                     return Err(GameError::PlayerLeft);
                 }
                 Ok(EventType::BidOver(contract)) => {
-                    frontend.auction_over(&contract);
+                    frontend.auction_over(&self.state, &contract);
                     return Ok(());
                 }
                 Ok(event) => frontend.unexpected_event(event),
@@ -98,33 +117,23 @@ impl<B: Backend> Client<B> {
 
     fn run_cardgame<F: Frontend<B>>(&mut self, frontend: &mut F) -> Result<(), GameError> {
         loop {
-            let mut event = self.backend.wait();
+            let mut event = self.wait(frontend);
             match event {
-                Ok(EventType::YourTurn) => {
-                    event = match frontend.ask_card() {
-                        GameAction::Leave => {
-                            frontend.party_cancelled("you left");
-                            return Err(GameError::PlayerLeft);
-                        }
-                        GameAction::PlayCard(card) => {
-                            self.backend.play_card(CardBody { card: card })
-                        }
-                    };
-                }
+                Ok(EventType::YourTurn) => event = try!(self.ask_card(frontend)),
                 _ => (),
             }
 
             match event {
                 Ok(EventType::GameOver{points, winner, scores}) => {
-                    self.scores[0] += scores[0];
-                    self.scores[1] += scores[1];
-                    frontend.game_over(points, winner, scores);
+                    frontend.game_over(&self.state, points, winner, scores);
                     return Ok(());
                 }
-                Ok(EventType::TrickOver{winner}) => frontend.show_trick_over(winner),
+                Ok(EventType::TrickOver{winner}) => frontend.show_trick_over(&self.state, winner),
                 Ok(EventType::FromPlayer(pos, e)) => {
                     match e {
-                        PlayerEvent::CardPlayed(card) => frontend.show_card_played(pos, card),
+                        PlayerEvent::CardPlayed(card) => {
+                            frontend.show_card_played(&self.state, pos, card)
+                        }
                         _ => frontend.unexpected_event(EventType::FromPlayer(pos, e)),
                     }
                 }
@@ -137,4 +146,97 @@ impl<B: Backend> Client<B> {
             }
         }
     }
+
+    // Asks for a bid until the server accepts it, or the player leaves.
+    // Chat messages and emotes are sent on the way.
+    fn ask_bid<F: Frontend<B>>(&mut self,
+                               frontend: &mut F)
+                               -> Result<Result<EventType, B::Error>, GameError> {
+        loop {
+            let legal = self.state.legal_bids();
+            let result = match frontend.ask_bid(&self.state, &legal) {
+                AuctionAction::Leave => {
+                    frontend.party_cancelled("you left");
+                    return Err(GameError::PlayerLeft);
+                }
+                AuctionAction::Say(text) => {
+                    let result = self.backend.chat(&text);
+                    check_sent::<B, F>(frontend, result);
+                    continue;
+                }
+                AuctionAction::Emote(emote) => {
+                    let result = self.backend.emote(emote);
+                    check_sent::<B, F>(frontend, result);
+                    continue;
+                }
+                AuctionAction::Coinche => self.backend.coinche(),
+                AuctionAction::Pass => self.backend.pass(),
+                AuctionAction::Bid((suit, target)) => {
+                    self.backend.bid(ContractBody {
+                        suit: suit,
+                        target: target,
+                    })
+                }
+            };
+            if !is_rejected::<B, F>(frontend, &result) {
+                return Ok(self.track(result));
+            }
+        }
+    }
+
+    // Asks for a card until the server accepts it, or the player leaves.
+    // Chat messages and emotes are sent on the way.
+    fn ask_card<F: Frontend<B>>(&mut self,
+                                frontend: &mut F)
+                                -> Result<Result<EventType, B::Error>, GameError> {
+        loop {
+            let legal = self.state.legal_cards();
+            let result = match frontend.ask_card(&self.state, &legal) {
+                GameAction::Leave => {
+                    frontend.party_cancelled("you left");
+                    return Err(GameError::PlayerLeft);
+                }
+                GameAction::Say(text) => {
+                    let result = self.backend.chat(&text);
+                    check_sent::<B, F>(frontend, result);
+                    continue;
+                }
+                GameAction::Emote(emote) => {
+                    let result = self.backend.emote(emote);
+                    check_sent::<B, F>(frontend, result);
+                    continue;
+                }
+                GameAction::PlayCard(card) => self.backend.play_card(CardBody { card: card }),
+            };
+            if !is_rejected::<B, F>(frontend, &result) {
+                return Ok(self.track(result));
+            }
+        }
+    }
+}
+
+// If the action broke the rules, tells the frontend and returns `true`.
+fn is_rejected<B: Backend, F: Frontend<B>>(frontend: &mut F,
+                                           result: &Result<EventType, B::Error>)
+                                           -> bool {
+    if let &Err(ref err) = result {
+        if let Some(error) = err.server_error() {
+            if error.kind().is_rule_violation() {
+                frontend.action_rejected(error);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+// Tells the frontend if a chat message or an emote was not sent.
+fn check_sent<B: Backend, F: Frontend<B>>(frontend: &mut F, result: Result<(), B::Error>) {
+    if let Err(err) = result {
+        if let Some(error) = err.server_error() {
+            frontend.action_rejected(error);
+            return;
+        }
+        frontend.show_error(err);
+    }
 }
@@ -1,17 +1,123 @@
-use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use rustc_serialize::Decodable;
 use rustc_serialize::json;
 use hyper::client::IntoUrl;
-use std::io::Read;
 use libcoinche::pos;
 use hyper::header::ContentType;
+use hyper::status::StatusCode;
 use hyper::mime::{Mime, TopLevel, SubLevel, Attr, Value};
+use hyper::net::{HttpStream, HttpsConnector, Openssl, Ssl};
+use openssl::ssl::{self, SslContext, SslMethod, SslStream, SSL_VERIFY_PEER};
+use openssl::ssl::error::SslError;
+use openssl_verify;
 use url;
 use hyper;
 
-use {NewPartyInfo, Event, EventType, ContractBody, CardBody};
+use {NewPartyInfo, QueueStatus, Lobby, TableInfo, Event, EventType, Emote, PROTOCOL_VERSION};
+use {ContractBody, CardBody, ChatBody, EmoteBody, JoinBody, NewTableBody};
 
-use super::Backend;
+use super::{Backend, ServerError};
+
+/// A coinched server, and how to reach it.
+#[derive(Clone)]
+pub struct Host {
+    // Scheme and authority, like `https://example.com:3000`.
+    base: String,
+    // Used for `https://` hosts.
+    ssl: Option<Arc<SslContext>>,
+}
+
+// Builds a context checking certificates against the system CAs, and the given file if any.
+fn ssl_context(ca_file: Option<&Path>) -> Result<SslContext, SslError> {
+    let mut context = try!(SslContext::new(SslMethod::Sslv23));
+    try!(context.set_default_verify_paths());
+    if let Some(ca_file) = ca_file {
+        try!(context.set_CA_file(ca_file));
+    }
+    context.set_verify(SSL_VERIFY_PEER, None);
+    Ok(context)
+}
+
+// Like hyper's `Openssl`, but also checks that the certificate belongs to the host.
+#[derive(Clone)]
+struct VerifiedSsl {
+    context: Arc<SslContext>,
+}
+
+impl Ssl for VerifiedSsl {
+    type Stream = SslStream<HttpStream>;
+
+    fn wrap_client(&self, stream: HttpStream, host: &str) -> hyper::Result<Self::Stream> {
+        let mut ssl = try!(ssl::Ssl::new(&self.context));
+        // Server Name Indication, for servers with several certificates.
+        try!(ssl.set_hostname(host));
+        let host = host.to_string();
+        ssl.set_verify_callback(SSL_VERIFY_PEER, move |preverified, store| {
+            openssl_verify::verify_callback(&host, preverified, store)
+        });
+        SslStream::connect(ssl, stream).map_err(From::from)
+    }
+
+    fn wrap_server(&self, stream: HttpStream) -> hyper::Result<Self::Stream> {
+        Openssl { context: self.context.clone() }.wrap_server(stream)
+    }
+}
+
+impl Host {
+    /// Parses a host, like `localhost:3000` or `https://example.com`.
+    ///
+    /// Hosts without a scheme use plain HTTP.
+    pub fn new(host: &str) -> Result<Self, Error> {
+        let host = host.trim_right_matches('/');
+        if host.starts_with("https://") {
+            let context = try!(ssl_context(None));
+            Ok(Host {
+                base: host.to_string(),
+                ssl: Some(Arc::new(context)),
+            })
+        } else if host.starts_with("http://") {
+            Ok(Host {
+                base: host.to_string(),
+                ssl: None,
+            })
+        } else {
+            Ok(Host {
+                base: format!("http://{}", host),
+                ssl: None,
+            })
+        }
+    }
+
+    /// Also trusts the certificates signed by the given CA, for self-hosted servers.
+    pub fn with_ca_file<P: AsRef<Path>>(mut self, ca_file: P) -> Result<Self, Error> {
+        let context = try!(ssl_context(Some(ca_file.as_ref())));
+        self.ssl = Some(Arc::new(context));
+        Ok(self)
+    }
+
+    // Returns the URL for the given API path, like `/join`.
+    fn url(&self, path: &str) -> String {
+        format!("{}/v1{}", self.base, path)
+    }
+
+    // A new client, for a single request.
+    //
+    // Re-using a client would lead to failed requests if too
+    // long happened between two queries.
+    fn client(&self) -> hyper::Client {
+        match self.ssl {
+            Some(ref context) => {
+                hyper::Client::with_connector(HttpsConnector::new(VerifiedSsl {
+                    context: context.clone(),
+                }))
+            }
+            None => hyper::Client::new(),
+        }
+    }
+}
 
 /// HTTP coinched client.
 ///
@@ -20,19 +126,46 @@ pub struct HttpBackend {
     player_id: u32,
     pub pos: pos::PlayerPos,
 
+    // Next event to wait for.
     event_id: usize,
+    // Event caused by our last action. Already returned, so `wait` skips it.
+    answered: Option<usize>,
 
-    host: String, /* It used to include a re-usable hyper::Client,
-                   * but it would lead to failed request if too
-                   * long happened between two queries. */
+    host: Host,
 }
 
 #[derive(Debug)]
 pub enum Error {
     Url(url::ParseError),
     Hyper(hyper::Error),
+    Ssl(SslError),
     Json(json::DecoderError),
+
+    /// The request was malformed (400).
+    BadRequest(::Error),
+    /// The player, party or ticket doesn't exist (404).
+    NotFound(::Error),
+    /// The action is not possible at this point of the game (409).
+    Conflict(::Error),
+    /// The action breaks the rules (422).
+    Rule(::Error),
+    /// Any other error returned by the server.
     Coinched(::Error),
+    /// The server speaks another protocol version.
+    Incompatible(u32),
+}
+
+impl ServerError for Error {
+    fn server_error(&self) -> Option<&::Error> {
+        match self {
+            &Error::BadRequest(ref err) |
+            &Error::NotFound(ref err) |
+            &Error::Conflict(ref err) |
+            &Error::Rule(ref err) |
+            &Error::Coinched(ref err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
 impl From<url::ParseError> for Error {
@@ -41,6 +174,12 @@ impl From<url::ParseError> for Error {
     }
 }
 
+impl From<SslError> for Error {
+    fn from(err: SslError) -> Self {
+        Error::Ssl(err)
+    }
+}
+
 impl From<hyper::Error> for Error {
     fn from(err: hyper::Error) -> Self {
         Error::Hyper(err)
@@ -59,11 +198,11 @@ impl From<json::DecoderError> for Error {
     }
 }
 
-/// Helper method to decode a `T: Decodable` from a reader.
+/// Helper method to decode a `T: Decodable` from a response.
 ///
-/// (`json::decode` only works from a string)
-fn from_reader<R: Read, T: Decodable>(r: &mut R) -> Result<T, Error> {
-    let json = try!(json::Json::from_reader(r));
+/// Server errors are sorted according to the response status.
+fn from_response<T: Decodable>(response: &mut hyper::client::Response) -> Result<T, Error> {
+    let json = try!(json::Json::from_reader(response));
 
     let has_error = match json.as_object() {
         Some(obj) => obj.contains_key("error"),
@@ -73,7 +212,13 @@ fn from_reader<R: Read, T: Decodable>(r: &mut R) -> Result<T, Error> {
     if has_error {
         let mut decoder = json::Decoder::new(json);
         let err = try!(Decodable::decode(&mut decoder));
-        Err(Error::Coinched(err))
+        Err(match response.status {
+            StatusCode::BadRequest => Error::BadRequest(err),
+            StatusCode::NotFound => Error::NotFound(err),
+            StatusCode::Conflict => Error::Conflict(err),
+            StatusCode::UnprocessableEntity => Error::Rule(err),
+            _ => Error::Coinched(err),
+        })
     } else {
         let mut decoder = json::Decoder::new(json);
         let result = try!(Decodable::decode(&mut decoder));
@@ -81,44 +226,125 @@ fn from_reader<R: Read, T: Decodable>(r: &mut R) -> Result<T, Error> {
     }
 }
 
+/// Lists the open tables and running parties on the given host.
+pub fn lobby(host: &Host) -> Result<Lobby, Error> {
+    let lobby_url = host.url("/lobby");
+    let mut response = try!(host.client().get(&lobby_url).send());
+    from_response(&mut response)
+}
+
+/// Creates a new table on the given host.
+///
+/// Use `HttpBackend::join` with the table ID to sit at it.
+pub fn create_table(host: &Host, options: &NewTableBody) -> Result<TableInfo, Error> {
+    let tables_url = host.url("/tables");
+    let body = json::encode(options).unwrap();
+    let mut response = try!(host.client()
+                                .post(&tables_url)
+                                .header(ContentType(Mime(TopLevel::Application,
+                                                         SubLevel::Json,
+                                                         vec![(Attr::Charset, Value::Utf8)])))
+                                .body(&body)
+                                .send());
+    from_response(&mut response)
+}
+
 /// Leave the party on drop.
 /// TODO: handle "soft" exit with reconnection?
 impl Drop for HttpBackend {
     fn drop(&mut self) {
-        let leave_url = format!("http://{}/leave/{}", self.host, self.player_id);
-        hyper::Client::new().post(&leave_url).send().unwrap();
+        let leave_url = self.host.url(&format!("/leave/{}", self.player_id));
+        if let Err(err) = self.host.client().post(&leave_url).send() {
+            trace!("Could not leave the party: {:?}", err);
+        }
     }
 }
 
 impl HttpBackend {
     /// Creates a client to connect to the given server, once logged in.
-    fn new(host: &str, player_id: u32, pos: pos::PlayerPos) -> Self {
+    fn new(host: &Host, player_id: u32, pos: pos::PlayerPos) -> Self {
 
         HttpBackend {
             player_id: player_id,
             pos: pos,
             event_id: 0,
-            host: host.to_string(),
+            answered: None,
+            host: host.clone(),
         }
     }
 
     /// Attempt to join a game on the given host.
-    pub fn join(host: &str) -> Result<Self, Error> {
-        let client = hyper::Client::new();
+    ///
+    /// Waits in the matchmaking queue until a party is found,
+    /// logging the queue position on the way.
+    /// If a table is given, waits until the table is full instead.
+    pub fn join(host: &Host, options: &JoinBody) -> Result<Self, Error> {
+        let client = host.client();
+
+        if let Some(table) = options.table {
+            let join_url = try!(host.url("/join").into_url());
+            let body = json::encode(options).unwrap();
+            info!("Waiting for players at table {}", table);
+            let mut response = try!(client.post(join_url)
+                                          .header(ContentType(Mime(TopLevel::Application,
+                                                                   SubLevel::Json,
+                                                                   vec![(Attr::Charset,
+                                                                         Value::Utf8)])))
+                                          .body(&body)
+                                          .send());
+            let party: NewPartyInfo = try!(from_response(&mut response));
+            return HttpBackend::from_party(host, party);
+        }
+
+        let queue_url = try!(host.url("/queue").into_url());
+        let body = json::encode(options).unwrap();
+        info!("Connecting to {}", host.base);
+        let mut response = try!(client.post(queue_url)
+                                      .header(ContentType(Mime(TopLevel::Application,
+                                                               SubLevel::Json,
+                                                               vec![(Attr::Charset,
+                                                                     Value::Utf8)])))
+                                      .body(&body)
+                                      .send());
+        let mut status: QueueStatus = try!(from_response(&mut response));
+
+        let mut position = 0;
+        while status.party.is_none() {
+            if status.position != position {
+                position = status.position;
+                info!("Waiting for players (position in queue: {})", position);
+            }
+            thread::sleep(Duration::from_secs(1));
+
+            let status_url = host.url(&format!("/queue/{}", status.ticket));
+            let mut response = try!(client.get(&status_url).send());
+            status = try!(from_response(&mut response));
+        }
+        let party = status.party.unwrap();
 
-        let join_url = try!(format!("http://{}/join", host).into_url());
-        println!("Connecting to {}", host);
-        let mut response = try!(client.post(join_url).send());
-        let party: NewPartyInfo = try!(from_reader(&mut response));
+        HttpBackend::from_party(host, party)
+    }
 
-        Ok(HttpBackend::new(host, party.player_id, party.player_pos))
+    // Checks the server speaks our protocol before playing.
+    // If not, leaves the party at once, so the others don't wait for us:
+    // dropping the backend does it.
+    fn from_party(host: &Host, party: NewPartyInfo) -> Result<Self, Error> {
+        let backend = HttpBackend::new(host, party.player_id, party.player_pos);
+        let protocol = party.protocol_version();
+        if protocol != PROTOCOL_VERSION {
+            return Err(Error::Incompatible(protocol));
+        }
+        Ok(backend)
     }
 
-    /// Parse and return an event from the given reader.
-    fn read_event<R: io::Read>(&mut self, r: &mut R) -> Result<EventType, Error> {
-        let event: Event = try!(from_reader(r));
+    /// Parse and return the event caused by an action from the given response.
+    ///
+    /// The wait cursor stays put: events sent in the meantime, like chat messages,
+    /// are still returned by `wait`.
+    fn read_event(&mut self, response: &mut hyper::client::Response) -> Result<EventType, Error> {
+        let event: Event = try!(from_response(response));
 
-        self.event_id = event.id + 1;
+        self.answered = Some(event.id);
 
         Ok(event.event)
     }
@@ -127,19 +353,30 @@ impl HttpBackend {
 impl Backend for HttpBackend {
     type Error = Error;
 
+    fn pos(&self) -> pos::PlayerPos {
+        self.pos
+    }
+
     fn wait(&mut self) -> Result<EventType, Error> {
-        let wait_url = format!("http://{}/wait/{}/{}",
-                               &self.host,
-                               self.player_id,
-                               self.event_id);
-        let mut response = try!(hyper::Client::new().get(&wait_url).send());
-        self.read_event(&mut response)
+        loop {
+            let wait_url = self.host.url(&format!("/wait/{}/{}", self.player_id, self.event_id));
+            let mut response = try!(self.host.client().get(&wait_url).send());
+            let event: Event = try!(from_response(&mut response));
+
+            self.event_id = event.id + 1;
+            if Some(event.id) == self.answered {
+                // Already returned by the action that caused it.
+                self.answered = None;
+            } else {
+                return Ok(event.event);
+            }
+        }
     }
 
     fn bid(&mut self, contract: ContractBody) -> Result<EventType, Error> {
-        let bid_url = format!("http://{}/bid/{}", self.host, self.player_id);
+        let bid_url = self.host.url(&format!("/bid/{}", self.player_id));
         let body = json::encode(&contract).unwrap();
-        let mut response = try!(hyper::Client::new()
+        let mut response = try!(self.host.client()
                                     .post(&bid_url)
                                     .header(ContentType(Mime(TopLevel::Application,
                                                              SubLevel::Json,
@@ -150,21 +387,21 @@ impl Backend for HttpBackend {
     }
 
     fn pass(&mut self) -> Result<EventType, Error> {
-        let pass_url = format!("http://{}/pass/{}", self.host, self.player_id);
-        let mut response = try!(hyper::Client::new().post(&pass_url).send());
+        let pass_url = self.host.url(&format!("/pass/{}", self.player_id));
+        let mut response = try!(self.host.client().post(&pass_url).send());
         self.read_event(&mut response)
     }
 
     fn coinche(&mut self) -> Result<EventType, Error> {
-        let coinche_url = format!("http://{}/coinche/{}", self.host, self.player_id);
-        let mut response = try!(hyper::Client::new().post(&coinche_url).send());
+        let coinche_url = self.host.url(&format!("/coinche/{}", self.player_id));
+        let mut response = try!(self.host.client().post(&coinche_url).send());
         self.read_event(&mut response)
     }
 
     fn play_card(&mut self, card: CardBody) -> Result<EventType, Error> {
-        let play_url = format!("http://{}/play/{}", self.host, self.player_id);
+        let play_url = self.host.url(&format!("/play/{}", self.player_id));
         let body = json::encode(&card).unwrap();
-        let mut response = try!(hyper::Client::new()
+        let mut response = try!(self.host.client()
                                     .post(&play_url)
                                     .header(ContentType(Mime(TopLevel::Application,
                                                              SubLevel::Json,
@@ -173,4 +410,50 @@ impl Backend for HttpBackend {
                                     .send());
         self.read_event(&mut response)
     }
+
+    fn chat(&mut self, text: &str) -> Result<(), Error> {
+        let chat_url = self.host.url(&format!("/chat/{}", self.player_id));
+        let body = json::encode(&ChatBody { text: text.to_string() }).unwrap();
+        let mut response = try!(self.host.client()
+                                    .post(&chat_url)
+                                    .header(ContentType(Mime(TopLevel::Application,
+                                                             SubLevel::Json,
+                                                             vec![(Attr::Charset, Value::Utf8)])))
+                                    .body(&body)
+                                    .send());
+        // Result is "ok"
+        let _: String = try!(from_response(&mut response));
+        Ok(())
+    }
+
+    fn emote(&mut self, emote: Emote) -> Result<(), Error> {
+        let emote_url = self.host.url(&format!("/emote/{}", self.player_id));
+        let body = json::encode(&EmoteBody { emote: emote }).unwrap();
+        let mut response = try!(self.host.client()
+                                    .post(&emote_url)
+                                    .header(ContentType(Mime(TopLevel::Application,
+                                                             SubLevel::Json,
+                                                             vec![(Attr::Charset, Value::Utf8)])))
+                                    .body(&body)
+                                    .send());
+        // Result is "ok"
+        let _: String = try!(from_response(&mut response));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Host;
+
+    #[test]
+    fn hosts() {
+        let host = Host::new("localhost:3000").ok().unwrap();
+        assert_eq!(host.url("/join"), "http://localhost:3000/v1/join");
+        assert!(host.ssl.is_none());
+
+        let host = Host::new("https://example.com/").ok().unwrap();
+        assert_eq!(host.url("/join"), "https://example.com/v1/join");
+        assert!(host.ssl.is_some());
+    }
 }
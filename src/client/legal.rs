@@ -0,0 +1,215 @@
+//! Legal moves computation.
+//!
+//! Follows the event stream to know the player's hand, the current trick
+//! and the trump, so frontends can restrict or highlight the choices.
+
+use libcoinche::{bid, cards, game, pos, trick};
+use {EventType, PlayerEvent};
+
+/// Every possible target, from lowest to highest.
+pub const TARGETS: [bid::Target; 11] = [bid::Target::Target80,
+                                        bid::Target::Target90,
+                                        bid::Target::Target100,
+                                        bid::Target::Target110,
+                                        bid::Target::Target120,
+                                        bid::Target::Target130,
+                                        bid::Target::Target140,
+                                        bid::Target::Target150,
+                                        bid::Target::Target160,
+                                        bid::Target::TargetCapot,
+                                        bid::Target::TargetGenerale];
+
+/// What the player can do during the auction. Passing is always possible.
+#[derive(Clone,Debug)]
+pub struct LegalBids {
+    /// Targets higher than the current bid. Any suit can be chosen.
+    pub targets: Vec<bid::Target>,
+    /// `true` if the current bid is from an opponent and not coinched yet,
+    /// or from the player's team and coinched once: then it's a surcoinche.
+    pub can_coinche: bool,
+}
+
+/// Tracks what is needed to compute the legal moves.
+pub struct LegalMoves {
+    pos: pos::PlayerPos,
+    hand: cards::Hand,
+
+    // Player who leads the first trick.
+    first: pos::PlayerPos,
+    // Highest bid so far, with its author.
+    bid: Option<(pos::PlayerPos, bid::Target)>,
+    // Number of coinches on the highest bid: 2 means surcoinched.
+    coinches: usize,
+
+    trump: Option<cards::Suit>,
+    trick: trick::Trick,
+}
+
+/// Returns `true` if both players are partners, or the same player.
+pub fn same_team(a: pos::PlayerPos, b: pos::PlayerPos) -> bool {
+    (a as usize) % 2 == (b as usize) % 2
+}
+
+impl LegalMoves {
+    /// Starts tracking for the player at the given position.
+    pub fn new(pos: pos::PlayerPos) -> Self {
+        LegalMoves {
+            pos: pos,
+            hand: cards::Hand::new(),
+            first: pos::PlayerPos::P0,
+            bid: None,
+            coinches: 0,
+            trump: None,
+            trick: trick::Trick::new(pos::PlayerPos::P0),
+        }
+    }
+
+    /// Updates the state with a new event.
+    pub fn update(&mut self, event: &EventType) {
+        match event {
+            &EventType::NewGameRelative { first, hand } => {
+                self.hand = hand;
+                self.first = first;
+                self.bid = None;
+                self.coinches = 0;
+                self.trump = None;
+            }
+            &EventType::FromPlayer(pos, PlayerEvent::Bidded(_, target)) => {
+                self.bid = Some((pos, target));
+                self.coinches = 0;
+            }
+            &EventType::FromPlayer(_, PlayerEvent::Coinched) => self.coinches += 1,
+            &EventType::BidOver(ref contract) => {
+                self.trump = Some(contract.trump);
+                self.trick = trick::Trick::new(self.first);
+            }
+            &EventType::FromPlayer(pos, PlayerEvent::CardPlayed(card)) => {
+                if let Some(trump) = self.trump {
+                    self.trick.play_card(pos, card, trump);
+                }
+                if pos == self.pos {
+                    self.hand.remove(card);
+                }
+            }
+            &EventType::TrickOver { winner } => self.trick = trick::Trick::new(winner),
+            _ => (),
+        }
+    }
+
+    /// Returns the player's current hand.
+    pub fn hand(&self) -> cards::Hand {
+        self.hand
+    }
+
+    /// Returns the cards the player can play right now.
+    pub fn cards(&self) -> Vec<cards::Card> {
+        let trump = match self.trump {
+            Some(trump) => trump,
+            None => return Vec::new(),
+        };
+
+        self.hand
+            .list()
+            .into_iter()
+            .filter(|&card| game::can_play(self.pos, card, self.hand, &self.trick, trump).is_ok())
+            .collect()
+    }
+
+    /// Returns the bids the player can make right now.
+    pub fn bids(&self) -> LegalBids {
+        let (targets, can_coinche) = match self.bid {
+            None => (TARGETS.to_vec(), false),
+            // Once coinched, the auction can't go higher,
+            // but the bidding team can still surcoinche.
+            Some((author, _)) if self.coinches == 1 => (Vec::new(), same_team(author, self.pos)),
+            Some(_) if self.coinches > 1 => (Vec::new(), false),
+            Some((author, current)) => {
+                let targets = TARGETS.iter()
+                                     .cloned()
+                                     .filter(|&target| target as usize > current as usize)
+                                     .collect();
+                (targets, !same_team(author, self.pos))
+            }
+        };
+
+        LegalBids {
+            targets: targets,
+            can_coinche: can_coinche,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libcoinche::{bid, cards, pos};
+    use {EventType, PlayerEvent};
+    use super::{LegalMoves, TARGETS};
+
+    fn legal_after(events: &[EventType]) -> LegalMoves {
+        let mut legal = LegalMoves::new(pos::PlayerPos::P0);
+        legal.update(&EventType::NewGameRelative {
+            first: pos::PlayerPos::P0,
+            hand: cards::Hand::new(),
+        });
+        for event in events {
+            legal.update(event);
+        }
+        legal
+    }
+
+    fn bidded(pos: pos::PlayerPos, target: bid::Target) -> EventType {
+        EventType::FromPlayer(pos, PlayerEvent::Bidded(cards::Suit::Heart, target))
+    }
+
+    fn coinched(pos: pos::PlayerPos) -> EventType {
+        EventType::FromPlayer(pos, PlayerEvent::Coinched)
+    }
+
+    #[test]
+    fn first_bid() {
+        let bids = legal_after(&[]).bids();
+        assert_eq!(bids.targets.len(), TARGETS.len());
+        assert!(!bids.can_coinche);
+    }
+
+    #[test]
+    fn higher_targets_only() {
+        let bids = legal_after(&[bidded(pos::PlayerPos::P1, bid::Target::Target150)]).bids();
+        assert_eq!(bids.targets.len(), 3);
+        assert_eq!(bids.targets[0] as usize, bid::Target::Target160 as usize);
+        assert!(bids.can_coinche);
+
+        // No coinche on the partner's bid.
+        let bids = legal_after(&[bidded(pos::PlayerPos::P2, bid::Target::Target80)]).bids();
+        assert!(!bids.can_coinche);
+    }
+
+    #[test]
+    fn surcoinche() {
+        // Our partner's bid was coinched: we can surcoinche, not bid.
+        let bids = legal_after(&[bidded(pos::PlayerPos::P2, bid::Target::Target80),
+                                 coinched(pos::PlayerPos::P3)])
+                       .bids();
+        assert!(bids.targets.is_empty());
+        assert!(bids.can_coinche);
+
+        // Nothing left once surcoinched.
+        let bids = legal_after(&[bidded(pos::PlayerPos::P2, bid::Target::Target80),
+                                 coinched(pos::PlayerPos::P3),
+                                 coinched(pos::PlayerPos::P0)])
+                       .bids();
+        assert!(bids.targets.is_empty());
+        assert!(!bids.can_coinche);
+
+        // We coinched the opponents: only they can surcoinche.
+        let bids = legal_after(&[bidded(pos::PlayerPos::P1, bid::Target::Target80),
+                                 coinched(pos::PlayerPos::P0)])
+                       .bids();
+        assert!(!bids.can_coinche);
+    }
+
+    #[test]
+    fn no_cards_during_auction() {
+        assert!(legal_after(&[]).cards().is_empty());
+    }
+}
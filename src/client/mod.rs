@@ -1,52 +1,97 @@
 use libcoinche::{pos, bid, cards};
-use {EventType, ContractBody, CardBody};
+use {EventType, Emote, ContractBody, CardBody, Error};
 
 pub mod http;
+pub mod legal;
+pub mod state;
+pub mod tcp;
 mod client;
 
 pub use self::client::Client;
+pub use self::legal::LegalBids;
+pub use self::state::TableState;
 
 pub enum AuctionAction {
     Leave,
     Pass,
     Coinche,
     Bid((cards::Suit, bid::Target)),
+    /// Sends a chat message. The player will be asked again.
+    Say(String),
+    /// Sends an emote. The player will be asked again.
+    Emote(Emote),
 }
 
 pub enum GameAction {
     Leave,
     PlayCard(cards::Card),
+    /// Sends a chat message. The player will be asked again.
+    Say(String),
+    /// Sends an emote. The player will be asked again.
+    Emote(Emote),
 }
 
 /// Any frontend mush have these global callbacks
 pub trait Frontend<B: Backend> {
     fn show_error(&mut self, error: B::Error);
+    /// The server rejected the last action. The player will be asked again.
+    fn action_rejected(&mut self, error: &Error);
     fn unexpected_event(&mut self, event: EventType);
     fn party_cancelled(&mut self, msg: &str);
-
-    fn show_card_played(&mut self, pos: pos::PlayerPos, card: cards::Card);
-    fn show_trick_over(&mut self, winner: pos::PlayerPos);
-    fn ask_card(&mut self) -> GameAction;
-    fn ask_bid(&mut self) -> AuctionAction;
-    fn game_over(&mut self, points: [i32; 2], winner: pos::Team, scores: [i32; 2]);
-
-    fn show_pass(&mut self, pos: pos::PlayerPos);
-    fn show_coinche(&mut self, pos: pos::PlayerPos);
-    fn show_bid(&mut self, pos: pos::PlayerPos, suit: cards::Suit, target: bid::Target);
+    /// A player sent a chat message. Our own messages come back here too.
+    fn show_chat(&mut self, state: &TableState, from: pos::PlayerPos, text: &str);
+    /// A player sent an emote. Ours come back here too.
+    fn show_emote(&mut self, state: &TableState, from: pos::PlayerPos, emote: Emote);
+
+    // Game callbacks are given the table state, already updated with the event.
+
+    fn show_card_played(&mut self, state: &TableState, pos: pos::PlayerPos, card: cards::Card);
+    fn show_trick_over(&mut self, state: &TableState, winner: pos::PlayerPos);
+    /// Asks for a card to play, among the legal ones.
+    fn ask_card(&mut self, state: &TableState, legal: &[cards::Card]) -> GameAction;
+    /// Asks for an auction action, given the legal bids.
+    fn ask_bid(&mut self, state: &TableState, legal: &LegalBids) -> AuctionAction;
+    fn game_over(&mut self,
+                 state: &TableState,
+                 points: [i32; 2],
+                 winner: pos::Team,
+                 scores: [i32; 2]);
+    /// The match is over: one team reached the target score.
+    fn match_over(&mut self, winner: pos::Team, scores: [i32; 2]);
+
+    fn show_pass(&mut self, state: &TableState, pos: pos::PlayerPos);
+    fn show_coinche(&mut self, state: &TableState, pos: pos::PlayerPos);
+    fn show_bid(&mut self,
+                state: &TableState,
+                pos: pos::PlayerPos,
+                suit: cards::Suit,
+                target: bid::Target);
 
 
     /// Auction cancelled, back to the start.
-    fn auction_cancelled(&mut self);
+    fn auction_cancelled(&mut self, state: &TableState);
     /// Auction is complete, we can play now!
-    fn auction_over(&mut self, contract: &bid::Contract);
+    fn auction_over(&mut self, state: &TableState, contract: &bid::Contract);
 
-    fn start_game(&mut self, first: pos::PlayerPos, hand: cards::Hand);
+    fn start_game(&mut self, state: &TableState, first: pos::PlayerPos, hand: cards::Hand);
+}
+
+/// Backend errors may come from the server rejecting an action.
+pub trait ServerError {
+    /// Returns the error sent by the server, if any.
+    fn server_error(&self) -> Option<&Error>;
 }
 
 pub trait Backend {
-    type Error;
+    type Error: ServerError;
+
+    /// Returns the player's position at the table.
+    fn pos(&self) -> pos::PlayerPos;
 
     /// Wait for the next event and return it.
+    ///
+    /// Events already returned by an action are skipped, but not the ones
+    /// sent before them, like chat messages.
     fn wait(&mut self) -> Result<EventType, Self::Error>;
 
     /// Make a bid offer.
@@ -62,4 +107,15 @@ pub trait Backend {
     fn coinche(&mut self) -> Result<EventType, Self::Error>;
 
     fn play_card(&mut self, card: CardBody) -> Result<EventType, Self::Error>;
+
+    /// Send a chat message to the table.
+    ///
+    /// It comes back later as a `Chat` event, like the others' messages.
+    /// Ranked tables refuse it: use `emote` instead.
+    fn chat(&mut self, text: &str) -> Result<(), Self::Error>;
+
+    /// Send an emote to the table.
+    ///
+    /// It comes back later as a `FromPlayer` event.
+    fn emote(&mut self, emote: Emote) -> Result<(), Self::Error>;
 }
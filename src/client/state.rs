@@ -0,0 +1,139 @@
+//! Table state, as seen by a player.
+//!
+//! The `Client` updates it from the event stream, and hands it to
+//! the frontend, so frontends don't have to track the game themselves.
+
+use libcoinche::{bid, cards, pos};
+use {EventType, PlayerEvent};
+use super::legal::{LegalBids, LegalMoves};
+
+/// Everything a player knows about the table.
+pub struct TableState {
+    /// The player's position.
+    pub pos: pos::PlayerPos,
+    /// The player's remaining cards.
+    pub hand: cards::Hand,
+
+    /// Player who opened the auction, and leads the first trick.
+    pub first: pos::PlayerPos,
+    /// Highest bid so far in the auction, with its author.
+    pub bid: Option<(pos::PlayerPos, cards::Suit, bid::Target)>,
+    /// `true` if the highest bid was coinched.
+    pub coinched: bool,
+    /// Contract for the current game, once the auction is over.
+    pub contract: Option<bid::Contract>,
+
+    /// Player who led the current trick.
+    pub leader: pos::PlayerPos,
+    /// Card played by each seat in the current trick.
+    pub trick: [Option<cards::Card>; 4],
+    /// Tricks won by each team in the current game.
+    pub tricks_won: [usize; 2],
+    /// Cards played so far in the current game.
+    pub seen: Vec<cards::Card>,
+
+    /// Total scores for each team.
+    pub scores: [i32; 2],
+    /// Player expected to act, if any.
+    pub turn: Option<pos::PlayerPos>,
+
+    // Used to check the rules.
+    legal: LegalMoves,
+}
+
+impl TableState {
+    /// Creates an empty table for the player at the given position.
+    pub fn new(pos: pos::PlayerPos) -> Self {
+        TableState {
+            pos: pos,
+            hand: cards::Hand::new(),
+            first: pos::PlayerPos::P0,
+            bid: None,
+            coinched: false,
+            contract: None,
+            leader: pos::PlayerPos::P0,
+            trick: [None; 4],
+            tricks_won: [0; 2],
+            seen: Vec::new(),
+            scores: [0; 2],
+            turn: None,
+            legal: LegalMoves::new(pos),
+        }
+    }
+
+    /// Updates the state with a new event.
+    pub fn update(&mut self, event: &EventType) {
+        self.legal.update(event);
+        match event {
+            &EventType::NewGameRelative { first, hand } => {
+                self.hand = hand;
+                self.first = first;
+                self.bid = None;
+                self.coinched = false;
+                self.contract = None;
+                self.tricks_won = [0; 2];
+                self.seen.clear();
+                self.new_trick(first);
+                self.turn = Some(first);
+            }
+            &EventType::FromPlayer(pos, PlayerEvent::Bidded(suit, target)) => {
+                self.bid = Some((pos, suit, target));
+                self.coinched = false;
+                self.turn = Some(pos.next());
+            }
+            &EventType::FromPlayer(pos, PlayerEvent::Coinched) => {
+                self.coinched = true;
+                self.turn = Some(pos.next());
+            }
+            &EventType::FromPlayer(pos, PlayerEvent::Passed) => self.turn = Some(pos.next()),
+            &EventType::BidOver(ref contract) => {
+                self.contract = Some(contract.clone());
+                let first = self.first;
+                self.new_trick(first);
+                self.turn = Some(first);
+            }
+            &EventType::FromPlayer(pos, PlayerEvent::CardPlayed(card)) => {
+                self.trick[pos as usize] = Some(card);
+                self.seen.push(card);
+                if pos == self.pos {
+                    self.hand.remove(card);
+                }
+                self.turn = Some(pos.next());
+            }
+            &EventType::TrickOver { winner } => {
+                self.tricks_won[(winner as usize) % 2] += 1;
+                self.new_trick(winner);
+                self.turn = Some(winner);
+            }
+            &EventType::GameOver { scores, .. } => {
+                self.scores[0] += scores[0];
+                self.scores[1] += scores[1];
+                self.turn = None;
+            }
+            &EventType::BidCancelled |
+            &EventType::PartyCancelled(_) |
+            &EventType::MatchOver { .. } => self.turn = None,
+            _ => (),
+        }
+    }
+
+    fn new_trick(&mut self, leader: pos::PlayerPos) {
+        self.leader = leader;
+        self.trick = [None; 4];
+    }
+
+    /// Returns `true` if the player is expected to act.
+    pub fn is_my_turn(&self) -> bool {
+        self.turn == Some(self.pos)
+    }
+
+    /// Returns the cards the player can play right now.
+    pub fn legal_cards(&self) -> Vec<cards::Card> {
+        self.legal.cards()
+    }
+
+    /// Returns the bids the player can make right now.
+    pub fn legal_bids(&self) -> LegalBids {
+        self.legal.bids()
+    }
+}
@@ -0,0 +1,218 @@
+use std::io;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use libcoinche::pos;
+
+use binary::{self, Request, Response, FrameError};
+use {NewPartyInfo, EventType, Emote, PROTOCOL_VERSION};
+use {ContractBody, CardBody, ChatBody, EmoteBody, JoinBody};
+
+use super::{Backend, ServerError};
+
+/// Binary protocol coinched client.
+///
+/// Keeps a single connection open for the whole party.
+pub struct TcpBackend {
+    player_id: u32,
+    pub pos: pos::PlayerPos,
+
+    // Next event to wait for.
+    event_id: usize,
+    // Event caused by our last action. Already returned, so `wait` skips it.
+    answered: Option<usize>,
+
+    stream: TcpStream,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Frame(FrameError),
+
+    /// The server rejected the request.
+    Coinched(::Error),
+    /// The server answered with an unexpected response.
+    UnexpectedResponse(Response),
+    /// The server speaks another protocol version.
+    Incompatible(u32),
+}
+
+impl ServerError for Error {
+    fn server_error(&self) -> Option<&::Error> {
+        match self {
+            &Error::Coinched(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Self {
+        Error::Frame(err)
+    }
+}
+
+/// Sends a request, and returns the server's answer.
+///
+/// Server errors are returned as `Error::Coinched`.
+fn query(stream: &mut TcpStream, request: &Request) -> Result<Response, Error> {
+    try!(binary::write_frame(stream, request));
+    match try!(binary::read_frame(stream)) {
+        Response::Error(err) => Err(Error::Coinched(err)),
+        response => Ok(response),
+    }
+}
+
+/// Leave the party on drop.
+impl Drop for TcpBackend {
+    fn drop(&mut self) {
+        let leave = Request::Leave(self.player_id);
+        if let Err(err) = query(&mut self.stream, &leave) {
+            trace!("Could not leave the party: {:?}", err);
+        }
+    }
+}
+
+impl TcpBackend {
+    /// Attempt to join a game on the given host, like `localhost:3001`.
+    ///
+    /// Waits in the matchmaking queue until a party is found,
+    /// logging the queue position on the way.
+    /// If a table is given, waits until the table is full instead.
+    pub fn join(host: &str, options: &JoinBody) -> Result<Self, Error> {
+        let mut stream = try!(TcpStream::connect(host));
+
+        if let Some(table) = options.table {
+            info!("Waiting for players at table {}", table);
+            let response = try!(query(&mut stream, &Request::Join(options.clone())));
+            return match response {
+                Response::Party(party) => TcpBackend::from_party(stream, party),
+                response => Err(Error::UnexpectedResponse(response)),
+            };
+        }
+
+        info!("Connecting to {}", host);
+        let mut request = Request::Enqueue(options.clone());
+        let mut position = 0;
+        loop {
+            let response = try!(query(&mut stream, &request));
+            let status = match response {
+                Response::Queue(status) => status,
+                response => return Err(Error::UnexpectedResponse(response)),
+            };
+            if let Some(party) = status.party {
+                return TcpBackend::from_party(stream, party);
+            }
+
+            if status.position != position {
+                position = status.position;
+                info!("Waiting for players (position in queue: {})", position);
+            }
+            thread::sleep(Duration::from_secs(1));
+            request = Request::QueueStatus(status.ticket);
+        }
+    }
+
+    // Checks the server speaks our protocol before playing.
+    // If not, leaves the party at once, so the others don't wait for us:
+    // dropping the backend does it.
+    fn from_party(stream: TcpStream, party: NewPartyInfo) -> Result<Self, Error> {
+        let backend = TcpBackend {
+            player_id: party.player_id,
+            pos: party.player_pos,
+            event_id: 0,
+            answered: None,
+            stream: stream,
+        };
+        let protocol = party.protocol_version();
+        if protocol != PROTOCOL_VERSION {
+            return Err(Error::Incompatible(protocol));
+        }
+        Ok(backend)
+    }
+
+    /// Sends the request, and returns the event and its ID.
+    fn query_event(&mut self, request: Request) -> Result<(usize, EventType), Error> {
+        match try!(query(&mut self.stream, &request)) {
+            Response::Event(id, event) => Ok((id, event)),
+            response => Err(Error::UnexpectedResponse(response)),
+        }
+    }
+
+    /// Sends the action, and returns the event it caused.
+    ///
+    /// The wait cursor stays put: events sent in the meantime, like chat messages,
+    /// are still returned by `wait`.
+    fn act(&mut self, request: Request) -> Result<EventType, Error> {
+        let (id, event) = try!(self.query_event(request));
+        self.answered = Some(id);
+        Ok(event)
+    }
+}
+
+impl Backend for TcpBackend {
+    type Error = Error;
+
+    fn pos(&self) -> pos::PlayerPos {
+        self.pos
+    }
+
+    fn wait(&mut self) -> Result<EventType, Error> {
+        loop {
+            let request = Request::Wait(self.player_id, self.event_id);
+            let (id, event) = try!(self.query_event(request));
+
+            self.event_id = id + 1;
+            if Some(id) == self.answered {
+                // Already returned by the action that caused it.
+                self.answered = None;
+            } else {
+                return Ok(event);
+            }
+        }
+    }
+
+    fn bid(&mut self, contract: ContractBody) -> Result<EventType, Error> {
+        let request = Request::Bid(self.player_id, contract);
+        self.act(request)
+    }
+
+    fn pass(&mut self) -> Result<EventType, Error> {
+        let request = Request::Pass(self.player_id);
+        self.act(request)
+    }
+
+    fn coinche(&mut self) -> Result<EventType, Error> {
+        let request = Request::Coinche(self.player_id);
+        self.act(request)
+    }
+
+    fn play_card(&mut self, card: CardBody) -> Result<EventType, Error> {
+        let request = Request::Play(self.player_id, card);
+        self.act(request)
+    }
+
+    fn chat(&mut self, text: &str) -> Result<(), Error> {
+        let request = Request::Chat(self.player_id, ChatBody { text: text.to_string() });
+        match try!(query(&mut self.stream, &request)) {
+            Response::Ok => Ok(()),
+            response => Err(Error::UnexpectedResponse(response)),
+        }
+    }
+
+    fn emote(&mut self, emote: Emote) -> Result<(), Error> {
+        let request = Request::Emote(self.player_id, EmoteBody { emote: emote });
+        match try!(query(&mut self.stream, &request)) {
+            Response::Ok => Ok(()),
+            response => Err(Error::UnexpectedResponse(response)),
+        }
+    }
+}
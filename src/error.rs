@@ -0,0 +1,278 @@
+//! Errors returned by the server.
+//!
+//! The codes of rule violations are defined here once, for both the server
+//! and the client.
+
+use libcoinche::{bid, game};
+
+/// Error returned by the server, along with a non-2xx status code.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct Error {
+    /// Stable, machine-readable error code, like `bad_player_id`.
+    pub code: String,
+    /// Human-readable message.
+    pub error: String,
+}
+
+impl Error {
+    /// Returns the kind of error, parsed from its code.
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::from_code(&self.code)
+    }
+}
+
+/// Mirrors `libcoinche::bid::BidError`.
+#[derive(Clone,Debug,PartialEq)]
+pub enum BidErrorKind {
+    /// The auction is closed and does not accept more contracts.
+    Closed,
+    /// The bid was not higher than the current one.
+    NonRaisedTarget,
+    /// It is not the player's turn.
+    TurnError,
+    /// The player tried to coinche a contract that was not an opponent's.
+    InvalidCoinche,
+    /// The auction is still running.
+    StillRunning,
+    /// Nobody bid anything.
+    NoContract,
+    /// The contract was already coinched.
+    OverCoinche,
+    /// Unknown bid error, from a newer server.
+    Other(String),
+}
+
+impl BidErrorKind {
+    /// Every known kind, to look codes up.
+    fn known() -> [BidErrorKind; 7] {
+        [BidErrorKind::Closed,
+         BidErrorKind::NonRaisedTarget,
+         BidErrorKind::TurnError,
+         BidErrorKind::InvalidCoinche,
+         BidErrorKind::StillRunning,
+         BidErrorKind::NoContract,
+         BidErrorKind::OverCoinche]
+    }
+
+    /// Returns the code of this kind, after the `bid_` prefix.
+    pub fn code(&self) -> &str {
+        match self {
+            &BidErrorKind::Closed => "closed",
+            &BidErrorKind::NonRaisedTarget => "non_raised_target",
+            &BidErrorKind::TurnError => "turn_error",
+            &BidErrorKind::InvalidCoinche => "invalid_coinche",
+            &BidErrorKind::StillRunning => "still_running",
+            &BidErrorKind::NoContract => "no_contract",
+            &BidErrorKind::OverCoinche => "over_coinche",
+            &BidErrorKind::Other(ref code) => code,
+        }
+    }
+
+    fn from_code(code: &str) -> Self {
+        BidErrorKind::known()
+            .iter()
+            .find(|kind| kind.code() == code)
+            .cloned()
+            .unwrap_or_else(|| BidErrorKind::Other(code.to_string()))
+    }
+}
+
+impl<'a> From<&'a bid::BidError> for BidErrorKind {
+    fn from(error: &'a bid::BidError) -> Self {
+        match error {
+            &bid::BidError::Closed => BidErrorKind::Closed,
+            &bid::BidError::NonRaisedTarget => BidErrorKind::NonRaisedTarget,
+            &bid::BidError::TurnError => BidErrorKind::TurnError,
+            &bid::BidError::InvalidCoinche => BidErrorKind::InvalidCoinche,
+            &bid::BidError::StillRunning => BidErrorKind::StillRunning,
+            &bid::BidError::NoContract => BidErrorKind::NoContract,
+            &bid::BidError::OverCoinche => BidErrorKind::OverCoinche,
+        }
+    }
+}
+
+/// Mirrors `libcoinche::game::PlayError`.
+#[derive(Clone,Debug,PartialEq)]
+pub enum PlayErrorKind {
+    /// It is not the player's turn.
+    TurnError,
+    /// The player doesn't have this card.
+    CardMissing,
+    /// The player must follow suit.
+    IncorrectSuit,
+    /// The player must play a trump.
+    InsufficientTrump,
+    /// The player must play a higher trump.
+    NonRaisedTrump,
+    /// Unknown play error, from a newer server.
+    Other(String),
+}
+
+impl PlayErrorKind {
+    /// Every known kind, to look codes up.
+    fn known() -> [PlayErrorKind; 5] {
+        [PlayErrorKind::TurnError,
+         PlayErrorKind::CardMissing,
+         PlayErrorKind::IncorrectSuit,
+         PlayErrorKind::InsufficientTrump,
+         PlayErrorKind::NonRaisedTrump]
+    }
+
+    /// Returns the code of this kind, after the `play_` prefix.
+    pub fn code(&self) -> &str {
+        match self {
+            &PlayErrorKind::TurnError => "turn_error",
+            &PlayErrorKind::CardMissing => "card_missing",
+            &PlayErrorKind::IncorrectSuit => "incorrect_suit",
+            &PlayErrorKind::InsufficientTrump => "insufficient_trump",
+            &PlayErrorKind::NonRaisedTrump => "non_raised_trump",
+            &PlayErrorKind::Other(ref code) => code,
+        }
+    }
+
+    fn from_code(code: &str) -> Self {
+        PlayErrorKind::known()
+            .iter()
+            .find(|kind| kind.code() == code)
+            .cloned()
+            .unwrap_or_else(|| PlayErrorKind::Other(code.to_string()))
+    }
+}
+
+impl<'a> From<&'a game::PlayError> for PlayErrorKind {
+    fn from(error: &'a game::PlayError) -> Self {
+        match error {
+            &game::PlayError::TurnError => PlayErrorKind::TurnError,
+            &game::PlayError::CardMissing => PlayErrorKind::CardMissing,
+            &game::PlayError::IncorrectSuit => PlayErrorKind::IncorrectSuit,
+            &game::PlayError::InsufficientTrump => PlayErrorKind::InsufficientTrump,
+            &game::PlayError::NonRaisedTrump => PlayErrorKind::NonRaisedTrump,
+            // Not a rule violation: the server reports it as `no_last_trick`.
+            &game::PlayError::NoLastTrick => PlayErrorKind::Other("no_last_trick".to_string()),
+        }
+    }
+}
+
+/// Kind of error returned by the server.
+///
+/// Mirrors `server::error::Error`, plus malformed requests.
+#[derive(Clone,Debug,PartialEq)]
+pub enum ErrorKind {
+    BadPlayerId,
+    BadEventId,
+    BadPartyId,
+    BadTableId,
+    BadPassword,
+    QueueTable,
+    NoTournament,
+    TournamentFull,
+    TournamentOver,
+    BadTicket,
+    PartyOver,
+    ShuttingDown,
+    /// The chat message is empty or too long.
+    BadMessage,
+    /// Only emotes are allowed at ranked tables.
+    ChatRestricted,
+    PlayInAuction,
+    BidInGame,
+    /// No trick was completed yet, so there is no last trick to show.
+    NoLastTrick,
+    /// The auction rules were broken.
+    Bid(BidErrorKind),
+    /// The card play rules were broken.
+    Play(PlayErrorKind),
+    /// The request was malformed.
+    BadRequest,
+    /// Too many requests: try again later.
+    RateLimited,
+    /// The client was banned for a while, after guessing player IDs.
+    Banned,
+    /// Unknown error code, from a newer server.
+    Other(String),
+}
+
+impl ErrorKind {
+    /// Parses an error code, as sent by the server.
+    pub fn from_code(code: &str) -> Self {
+        match code {
+            "bad_player_id" => ErrorKind::BadPlayerId,
+            "bad_event_id" => ErrorKind::BadEventId,
+            "bad_party_id" => ErrorKind::BadPartyId,
+            "bad_table_id" => ErrorKind::BadTableId,
+            "bad_password" => ErrorKind::BadPassword,
+            "queue_table" => ErrorKind::QueueTable,
+            "no_tournament" => ErrorKind::NoTournament,
+            "tournament_full" => ErrorKind::TournamentFull,
+            "tournament_over" => ErrorKind::TournamentOver,
+            "bad_ticket" => ErrorKind::BadTicket,
+            "party_over" => ErrorKind::PartyOver,
+            "shutting_down" => ErrorKind::ShuttingDown,
+            "bad_message" => ErrorKind::BadMessage,
+            "chat_restricted" => ErrorKind::ChatRestricted,
+            "play_in_auction" => ErrorKind::PlayInAuction,
+            "bid_in_game" => ErrorKind::BidInGame,
+            "no_last_trick" => ErrorKind::NoLastTrick,
+            "invalid_id" | "bad_parameters" | "missing_body" | "invalid_body" => {
+                ErrorKind::BadRequest
+            }
+            "rate_limited" | "too_many_pending" | "chat_too_fast" | "too_many_tables" => {
+                ErrorKind::RateLimited
+            }
+            "banned" => ErrorKind::Banned,
+            _ if code.starts_with("bid_") => ErrorKind::Bid(BidErrorKind::from_code(&code[4..])),
+            _ if code.starts_with("play_") => {
+                ErrorKind::Play(PlayErrorKind::from_code(&code[5..]))
+            }
+            _ => ErrorKind::Other(code.to_string()),
+        }
+    }
+
+    /// Returns `true` if the action broke the game rules.
+    ///
+    /// The player can try another action.
+    pub fn is_rule_violation(&self) -> bool {
+        match self {
+            &ErrorKind::Bid(_) | &ErrorKind::Play(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libcoinche::{bid, game};
+    use super::{BidErrorKind, ErrorKind, PlayErrorKind};
+
+    #[test]
+    fn bid_codes_round_trip() {
+        for kind in BidErrorKind::known().iter() {
+            let code = format!("bid_{}", kind.code());
+            assert_eq!(ErrorKind::from_code(&code), ErrorKind::Bid(kind.clone()));
+        }
+        assert_eq!(BidErrorKind::from(&bid::BidError::OverCoinche).code(), "over_coinche");
+    }
+
+    #[test]
+    fn play_codes_round_trip() {
+        for kind in PlayErrorKind::known().iter() {
+            let code = format!("play_{}", kind.code());
+            assert_eq!(ErrorKind::from_code(&code), ErrorKind::Play(kind.clone()));
+        }
+        assert_eq!(PlayErrorKind::from(&game::PlayError::IncorrectSuit).code(),
+                   "incorrect_suit");
+    }
+
+    #[test]
+    fn unknown_codes() {
+        assert_eq!(ErrorKind::from_code("bid_too_shy"),
+                   ErrorKind::Bid(BidErrorKind::Other("too_shy".to_string())));
+        assert_eq!(ErrorKind::from_code("play_too_slow"),
+                   ErrorKind::Play(PlayErrorKind::Other("too_slow".to_string())));
+        assert_eq!(ErrorKind::from_code("bid_in_game"), ErrorKind::BidInGame);
+        assert_eq!(ErrorKind::from_code("play_in_auction"), ErrorKind::PlayInAuction);
+        assert_eq!(ErrorKind::from_code("no_last_trick"), ErrorKind::NoLastTrick);
+        assert!(!ErrorKind::NoLastTrick.is_rule_violation());
+        assert_eq!(ErrorKind::from_code("teapot"), ErrorKind::Other("teapot".to_string()));
+    }
+}
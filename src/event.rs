@@ -4,6 +4,79 @@ use rustc_serialize;
 use rustc_serialize::Decodable;
 use libcoinche::{cards, bid, pos};
 
+/// A predefined quick message.
+///
+/// Unlike free chat, it can't be used to tell a partner about one's hand,
+/// so it is the only way to talk at ranked tables.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Emote {
+    Hello,
+    GoodLuck,
+    WellPlayed,
+    Thanks,
+    Sorry,
+    HurryUp,
+    Goodbye,
+}
+
+/// Every emote, in the order clients should present them.
+pub const EMOTES: [Emote; 7] = [Emote::Hello,
+                                Emote::GoodLuck,
+                                Emote::WellPlayed,
+                                Emote::Thanks,
+                                Emote::Sorry,
+                                Emote::HurryUp,
+                                Emote::Goodbye];
+
+impl Emote {
+    /// Returns the name used on the network, like `WellPlayed`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Emote::Hello => "Hello",
+            &Emote::GoodLuck => "GoodLuck",
+            &Emote::WellPlayed => "WellPlayed",
+            &Emote::Thanks => "Thanks",
+            &Emote::Sorry => "Sorry",
+            &Emote::HurryUp => "HurryUp",
+            &Emote::Goodbye => "Goodbye",
+        }
+    }
+
+    /// Parses a name, as returned by `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        EMOTES.iter().cloned().find(|emote| emote.name() == name)
+    }
+
+    /// Returns the message to show for this emote.
+    pub fn text(&self) -> &'static str {
+        match self {
+            &Emote::Hello => "Hello!",
+            &Emote::GoodLuck => "Good luck!",
+            &Emote::WellPlayed => "Well played!",
+            &Emote::Thanks => "Thanks!",
+            &Emote::Sorry => "Sorry!",
+            &Emote::HurryUp => "Hurry up!",
+            &Emote::Goodbye => "Goodbye!",
+        }
+    }
+}
+
+impl rustc_serialize::Encodable for Emote {
+    fn encode<S: rustc_serialize::Encoder>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.emit_str(self.name())
+    }
+}
+
+impl rustc_serialize::Decodable for Emote {
+    fn decode<D: rustc_serialize::Decoder>(d: &mut D) -> Result<Self, D::Error> {
+        let name = try!(d.read_str());
+        match Emote::from_name(&name) {
+            Some(emote) => Ok(emote),
+            None => Err(d.error(&format!("unknown emote: `{}`", name))),
+        }
+    }
+}
+
 /// An event about a player.
 #[derive(Clone,Debug)]
 pub enum PlayerEvent {
@@ -15,6 +88,12 @@ pub enum PlayerEvent {
     Passed,
     /// A player played a card.
     CardPlayed(cards::Card),
+    /// A player sent a quick message.
+    Emote(Emote),
+    /// An event added in a later protocol version. Contains its type.
+    ///
+    /// Clients can safely ignore it.
+    Unknown(String),
 }
 
 impl rustc_serialize::Encodable for PlayerEvent {
@@ -43,6 +122,16 @@ impl rustc_serialize::Encodable for PlayerEvent {
                     Ok(())
                 })
             }
+            &PlayerEvent::Emote(emote) => {
+                s.emit_struct("PlayerEvent", 2, |s| {
+                    try!(encode_field!(s, "type", 0, "Emote"));
+                    try!(encode_field!(s, "emote", 1, emote));
+                    Ok(())
+                })
+            }
+            &PlayerEvent::Unknown(ref kind) => {
+                s.emit_struct("PlayerEvent", 1, |s| encode_field!(s, "type", 0, kind))
+            }
         }
     }
 }
@@ -50,7 +139,8 @@ impl rustc_serialize::Encodable for PlayerEvent {
 impl rustc_serialize::Decodable for PlayerEvent {
     fn decode<D: rustc_serialize::Decoder>(d: &mut D) -> Result<Self, D::Error> {
         d.read_struct("PlayerEvent", 0, |d| {
-            match try!(d.read_struct_field("type", 0, |d| d.read_str())).as_ref() {
+            let kind = try!(d.read_struct_field("type", 0, |d| d.read_str()));
+            match kind.as_ref() {
                 "Bidded" => {
                     let suit = try!(d.read_struct_field("suit", 1, |d| cards::Suit::decode(d)));
                     let target = try!(d.read_struct_field("target", 2, |d| bid::Target::decode(d)));
@@ -60,9 +150,17 @@ impl rustc_serialize::Decodable for PlayerEvent {
                     let card = try!(d.read_struct_field("card", 1, |d| cards::Card::decode(d)));
                     Ok(PlayerEvent::CardPlayed(card))
                 }
+                "Emote" => {
+                    // Emotes from later versions are unknown events.
+                    let name = try!(d.read_struct_field("emote", 1, |d| d.read_str()));
+                    match Emote::from_name(&name) {
+                        Some(emote) => Ok(PlayerEvent::Emote(emote)),
+                        None => Ok(PlayerEvent::Unknown(kind.clone())),
+                    }
+                }
                 "Passed" => Ok(PlayerEvent::Passed),
                 "Coinched" => Ok(PlayerEvent::Coinched),
-                _ => Err(d.error("unknown event type")),
+                _ => Ok(PlayerEvent::Unknown(kind.clone())),
             }
         })
     }
@@ -109,6 +207,24 @@ pub enum EventType {
         winner: pos::Team,
         scores: [i32; 2],
     },
+
+    /// Match over: a team reached the target score. Contains the total scores.
+    /// No more game will be played in this party.
+    MatchOver {
+        winner: pos::Team,
+        scores: [i32; 2],
+    },
+
+    /// Chat message from a player. Spectators see it too.
+    Chat {
+        from: pos::PlayerPos,
+        text: String,
+    },
+
+    /// An event added in a later protocol version. Contains its type.
+    ///
+    /// Clients can safely ignore it.
+    Unknown(String),
 }
 
 impl EventType {
@@ -126,6 +242,20 @@ impl EventType {
             _ => self.clone(),
         }
     }
+
+    /// Returns a version of the event for someone not playing at the table.
+    /// Same as `relativize`, except that a NewGame event hides every hand.
+    pub fn anonymize(&self) -> Self {
+        match self {
+            &EventType::NewGame { first, .. } => {
+                EventType::NewGameRelative {
+                    first: first,
+                    hand: cards::Hand::new(),
+                }
+            }
+            _ => self.clone(),
+        }
+    }
 }
 
 // Ugly serialization...
@@ -193,6 +323,25 @@ impl rustc_serialize::Encodable for EventType {
                     Ok(())
                 })
             }
+            &EventType::MatchOver { winner, scores } => {
+                s.emit_struct("Event", 3, |s| {
+                    try!(encode_field!(s, "type", 0, "MatchOver"));
+                    try!(encode_field!(s, "winner", 1, winner));
+                    try!(encode_field!(s, "scores", 2, scores));
+                    Ok(())
+                })
+            }
+            &EventType::Chat { from, ref text } => {
+                s.emit_struct("Event", 3, |s| {
+                    try!(encode_field!(s, "type", 0, "Chat"));
+                    try!(encode_field!(s, "from", 1, from));
+                    try!(encode_field!(s, "text", 2, text));
+                    Ok(())
+                })
+            }
+            &EventType::Unknown(ref kind) => {
+                s.emit_struct("Event", 1, |s| encode_field!(s, "type", 0, kind))
+            }
         }
     }
 }
@@ -200,7 +349,8 @@ impl rustc_serialize::Encodable for EventType {
 impl rustc_serialize::Decodable for EventType {
     fn decode<D: rustc_serialize::Decoder>(d: &mut D) -> Result<Self, D::Error> {
         d.read_struct("PlayerEvent", 0, |d| {
-            match try!(d.read_struct_field("type", 0, |d| d.read_str())).as_ref() {
+            let kind = try!(d.read_struct_field("type", 0, |d| d.read_str()));
+            match kind.as_ref() {
                 "BidCancelled" => Ok(EventType::BidCancelled),
                 "YourTurn" => Ok(EventType::YourTurn),
                 "BidOver" => {
@@ -230,6 +380,14 @@ impl rustc_serialize::Decodable for EventType {
                         scores: scores,
                     })
                 }
+                "MatchOver" => {
+                    let winner = try!(decode_field!(d, "winner", 1));
+                    let scores = try!(decode_field!(d, "scores", 2));
+                    Ok(EventType::MatchOver {
+                        winner: winner,
+                        scores: scores,
+                    })
+                }
                 "NewGame" => {
                     let first = try!(decode_field!(d, "first", 1));
                     let cards = try!(decode_field!(d, "cards", 2));
@@ -238,7 +396,15 @@ impl rustc_serialize::Decodable for EventType {
                         hand: cards,
                     })
                 }
-                _ => Err(d.error("unknown event type")),
+                "Chat" => {
+                    let from = try!(decode_field!(d, "from", 1));
+                    let text = try!(decode_field!(d, "text", 2));
+                    Ok(EventType::Chat {
+                        from: from,
+                        text: text,
+                    })
+                }
+                _ => Ok(EventType::Unknown(kind.clone())),
             }
         })
     }
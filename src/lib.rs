@@ -5,8 +5,15 @@ extern crate eventual;
 extern crate libcoinche;
 extern crate iron;
 extern crate url;
+#[macro_use]
 extern crate hyper;
 extern crate bodyparser;
+extern crate bincode;
+extern crate toml;
+extern crate chan_signal;
+extern crate openssl;
+extern crate openssl_verify;
+extern crate num_cpus;
 
 #[macro_use]
 extern crate log;
@@ -25,10 +32,22 @@ macro_rules! encode_field {
 }
 
 mod event;
+mod error;
+pub mod binary;
 pub mod client;
 pub mod server;
 
 pub use event::*;
+pub use error::*;
+
+/// Version of the protocol spoken by this crate.
+///
+/// Only bumped on breaking changes: new event types are decoded as
+/// `EventType::Unknown` by older clients.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Path prefix for this version of the HTTP API.
+pub const API_PREFIX: &'static str = "v1";
 
 // Structures written by the server, read by the client
 
@@ -39,11 +58,70 @@ pub struct NewPartyInfo {
     pub player_id: u32,
     /// Player position in the table.
     pub player_pos: libcoinche::pos::PlayerPos,
+    /// Protocol version spoken by the server. See `PROTOCOL_VERSION`.
+    ///
+    /// Older servers don't send it. Use `protocol_version`.
+    pub protocol: Option<u32>,
 }
 
+impl NewPartyInfo {
+    pub fn new(player_id: u32, player_pos: libcoinche::pos::PlayerPos) -> Self {
+        NewPartyInfo {
+            player_id: player_id,
+            player_pos: player_pos,
+            protocol: Some(PROTOCOL_VERSION),
+        }
+    }
+
+    /// Returns the protocol version spoken by the server.
+    ///
+    /// Servers from before protocol versions speak version 0.
+    pub fn protocol_version(&self) -> u32 {
+        self.protocol.unwrap_or(0)
+    }
+}
+
+/// Player registered in a tournament.
 #[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
-pub struct Error {
-    pub error: String,
+pub struct Registration {
+    /// Ticket ID, used to get the player's next match.
+    pub ticket: u32,
+}
+
+/// Player status in the matchmaking queue.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct QueueStatus {
+    /// Ticket ID, used to check the queue status.
+    pub ticket: u32,
+    /// Position in the queue, starting at 1. 0 once a party is found.
+    pub position: usize,
+    /// The party, once found.
+    pub party: Option<NewPartyInfo>,
+}
+
+/// A table in the lobby, either waiting for players or playing.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct TableInfo {
+    /// Table ID, used to join the table. Becomes the party ID.
+    pub id: u32,
+    /// Private tables require a password.
+    pub private: bool,
+    pub variant: Option<String>,
+    pub target: Option<i32>,
+    /// Names of the seated players, in seat order.
+    pub players: Vec<Option<String>>,
+    /// `true` once the table is full, and the party started.
+    pub in_progress: bool,
+    /// Ranked tables only allow emotes, not free chat.
+    pub ranked: bool,
+}
+
+/// What is happening on the server.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct Lobby {
+    /// Number of players in the matchmaking queue.
+    pub waiting: usize,
+    pub tables: Vec<TableInfo>,
 }
 
 
@@ -60,3 +138,78 @@ pub struct ContractBody {
 pub struct CardBody {
     pub card: libcoinche::cards::Card,
 }
+
+/// Join request. Every field is optional.
+///
+/// Players are only grouped with players whose criteria are compatible.
+#[derive(Clone,Debug,Default,RustcDecodable,RustcEncodable)]
+pub struct JoinBody {
+    /// Player name. Named players are rated after each match.
+    pub name: Option<String>,
+    /// Rule variant label.
+    pub variant: Option<String>,
+    /// Score to reach to win the match.
+    pub target: Option<i32>,
+    /// Language spoken at the table.
+    pub language: Option<String>,
+    /// Maximum rating difference with the other players.
+    pub rating_band: Option<f64>,
+    /// Table to sit at, instead of using matchmaking.
+    pub table: Option<u32>,
+    /// Password for a private table.
+    pub password: Option<String>,
+}
+
+/// New table request.
+#[derive(Clone,Debug,Default,RustcDecodable,RustcEncodable)]
+pub struct NewTableBody {
+    /// If set, the table is private: joining it requires this password.
+    pub password: Option<String>,
+    /// Rule variant label.
+    pub variant: Option<String>,
+    /// Score to reach to win the match.
+    pub target: Option<i32>,
+    /// If `true`, only emotes are allowed at the table, named players or not.
+    /// Defaults to `false`.
+    pub ranked: Option<bool>,
+}
+
+/// Chat message, sent to the whole table.
+#[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
+pub struct ChatBody {
+    pub text: String,
+}
+
+/// Quick message, sent to the whole table.
+#[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
+pub struct EmoteBody {
+    pub emote: Emote,
+}
+
+#[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
+pub struct RegisterBody {
+    /// Name of the team to join. Two players register with the same name.
+    pub team: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use libcoinche::pos;
+    use rustc_serialize::json::{self, Json};
+
+    use {NewPartyInfo, PROTOCOL_VERSION};
+
+    #[test]
+    fn protocol_versions() {
+        let info = NewPartyInfo::new(42, pos::PlayerPos::P2);
+        assert_eq!(info.protocol_version(), PROTOCOL_VERSION);
+
+        // Older servers don't send the protocol version.
+        let mut old = Json::from_str(&json::encode(&info).unwrap()).unwrap();
+        old.as_object_mut().unwrap().remove("protocol");
+        let old: NewPartyInfo = json::decode(&old.to_string()).unwrap();
+        assert_eq!(old.player_id, 42);
+        assert_eq!(old.protocol, None);
+        assert_eq!(old.protocol_version(), 0);
+    }
+}
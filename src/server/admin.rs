@@ -0,0 +1,162 @@
+//! Admin console for the live server.
+//!
+//! It is served on a separate listener, bound to localhost by default,
+//! and every request must carry the admin token in a `X-Admin-Token` header.
+
+use std::sync::Arc;
+
+use rustc_serialize::json;
+use iron::prelude::*;
+use iron;
+
+use libcoinche::{bid, cards, pos, trick};
+
+use super::game_manager::GameManager;
+use super::http::error_resp;
+use Error;
+
+header! {
+    /// Header carrying the admin token.
+    (AdminToken, "X-Admin-Token") => [String]
+}
+
+/// A player, as seen by the admin.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct AdminPlayer {
+    pub id: u32,
+    pub pos: pos::PlayerPos,
+    pub name: Option<String>,
+}
+
+/// Short description of a party.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct PartySummary {
+    pub id: u32,
+    /// Players still in the party.
+    pub players: Vec<AdminPlayer>,
+    pub variant: Option<String>,
+    pub target: Option<i32>,
+    pub scores: [i32; 2],
+    /// Number of events so far.
+    pub events: usize,
+    pub over: bool,
+}
+
+/// Full state of a party, including every hand.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct PartyState {
+    pub id: u32,
+    pub players: Vec<AdminPlayer>,
+    /// One of `auction`, `playing` or `over`.
+    pub phase: String,
+    pub next_player: Option<pos::PlayerPos>,
+    pub hands: [cards::Hand; 4],
+    pub contract: Option<bid::Contract>,
+    pub trick: Option<trick::Trick>,
+    pub scores: [i32; 2],
+}
+
+pub struct AdminRouter {
+    manager: Arc<GameManager>,
+    token: String,
+}
+
+fn json_resp(status: iron::status::Status, body: String) -> IronResult<Response> {
+    let content_type: iron::mime::Mime = "application/json".parse::<iron::mime::Mime>().unwrap();
+    Ok(Response::with((content_type, status, body)))
+}
+
+fn err_resp<S: ToString>(status: iron::status::Status,
+                          code: &str,
+                          msg: S)
+                          -> IronResult<Response> {
+    json_resp(status,
+              json::encode(&Error {
+                  code: code.to_string(),
+                  error: msg.to_string(),
+              })
+                  .unwrap())
+}
+
+macro_rules! admin_try {
+    ( $x:expr ) => {
+        {
+            match $x {
+                Err(err) => return error_resp(&err),
+                Ok(thing) => thing,
+            }
+        }
+    };
+}
+
+macro_rules! parse_admin_id {
+    ( $req:expr ) => {
+        {
+            if $req.url.path.len() != 2 {
+                return err_resp(iron::status::BadRequest,
+                                "bad_parameters",
+                                format!("incorrect parameters (Usage: /{}/[ID])",
+                                        $req.url.path[0]));
+            }
+            match $req.url.path[1].parse::<u32>() {
+                Ok(id) => id,
+                Err(e) => return err_resp(iron::status::BadRequest,
+                                          "invalid_id",
+                                          format!("invalid ID: `{}` ({})", $req.url.path[1], e)),
+            }
+        }
+    };
+}
+
+impl AdminRouter {
+    pub fn new(manager: Arc<GameManager>, token: String) -> Self {
+        AdminRouter {
+            manager: manager,
+            token: token,
+        }
+    }
+}
+
+impl iron::Handler for AdminRouter {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let authorized = match req.headers.get::<AdminToken>() {
+            Some(&AdminToken(ref token)) => *token == self.token,
+            None => false,
+        };
+        if !authorized {
+            warn!("Unauthorized admin request: {:?} {:?}", req.method, req.url.path);
+            return err_resp(iron::status::Unauthorized, "bad_admin_token", "bad admin token");
+        }
+
+        trace!("Admin request: {:?} {:?}", req.method, req.url.path);
+        let response = match (&req.method, &*req.url.path[0]) {
+            (&iron::method::Get, "parties") => json::encode(&self.manager.admin_parties()).unwrap(),
+            (&iron::method::Get, "party") => {
+                let party_id = parse_admin_id!(req);
+                json::encode(&admin_try!(self.manager.admin_party(party_id))).unwrap()
+            }
+            (&iron::method::Get, "events") => {
+                let party_id = parse_admin_id!(req);
+                json::encode(&admin_try!(self.manager.admin_events(party_id))).unwrap()
+            }
+            (&iron::method::Post, "kick") => {
+                let player_id = parse_admin_id!(req);
+                admin_try!(self.manager.kick(player_id));
+                r#""ok""#.to_string()
+            }
+            (&iron::method::Post, "cancel") => {
+                let party_id = parse_admin_id!(req);
+                admin_try!(self.manager.cancel_party(party_id, "cancelled by admin"));
+                r#""ok""#.to_string()
+            }
+            _ => {
+                return err_resp(iron::status::NotFound,
+                                "unknown_command",
+                                "unknown admin command (GET /parties, GET /party/[ID], GET \
+                                 /events/[ID], POST /kick/[PLAYER_ID], POST /cancel/[ID])")
+            }
+        };
+
+        json_resp(iron::status::Ok, response)
+    }
+}
@@ -0,0 +1,501 @@
+//! Server configuration, read from a TOML file.
+//!
+//! Every setting is optional, and command-line flags override the file.
+//! Unknown settings are refused, to catch typos:
+//!
+//! ```toml
+//! [server]
+//! address = "0.0.0.0"
+//! port = 3000
+//! threads = 8
+//! tcp_port = 3002
+//! log_level = "info"
+//!
+//! [tls]
+//! # Serves HTTPS with these PEM files.
+//! certificate = "/etc/coinched/cert.pem"
+//! key = "/etc/coinched/key.pem"
+//!
+//! [timeouts]
+//! # Seconds before idle ticket holders leave the matchmaking queue.
+//! queue = 30
+//! # Seconds given to the running deals when shutting down.
+//! shutdown = 0
+//! # Seconds to read an HTTP request, or write a response. 0 disables them.
+//! read = 30
+//! write = 30
+//!
+//! [rules]
+//! # Score to reach to win a match.
+//! target = 1000
+//!
+//! [limits]
+//! # Requests per minute. 0 disables a limit.
+//! requests_per_ip = 600
+//! requests_per_player = 300
+//! # Pending `/join` and `/wait` requests from a single IP.
+//! pending_per_ip = 16
+//! # Unknown player IDs before an IP is banned, and for how long.
+//! ban_after = 20
+//! ban_seconds = 600
+//!
+//! [persistence]
+//! # Ratings are kept in `ratings.json` there.
+//! dir = "/var/lib/coinched"
+//!
+//! [admin]
+//! address = "localhost"
+//! port = 3001
+//! token = "secret"
+//! ```
+
+use std::fs;
+use std::io::Read;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+
+use rustc_serialize::Decodable;
+use toml;
+
+use super::http::HTTP_TIMEOUT_SECONDS;
+use super::limits::Limits;
+use super::matchmaking::TICKET_TIMEOUT_SECONDS;
+
+/// Accepted values for `log_level`.
+pub const LOG_LEVELS: [&'static str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Complete server configuration.
+#[derive(Clone,Debug)]
+pub struct Config {
+    /// Address to listen on. Defaults to `localhost`.
+    pub address: String,
+    /// Defaults to 3000.
+    pub port: u16,
+    /// Number of threads serving HTTP requests. Defaults to iron's choice.
+    pub threads: Option<usize>,
+    /// Port for the binary protocol, if any.
+    pub tcp_port: Option<u16>,
+    /// One of `LOG_LEVELS`. Defaults to `error`.
+    pub log_level: String,
+
+    /// Certificate file, to serve HTTPS. Requires `tls_key`.
+    pub tls_certificate: Option<PathBuf>,
+    /// Private key file for `tls_certificate`.
+    pub tls_key: Option<PathBuf>,
+
+    /// Seconds before idle ticket holders leave the matchmaking queue.
+    pub queue_timeout: i64,
+    /// Seconds given to the running deals when shutting down. Defaults to 0.
+    pub shutdown_deadline: u64,
+    /// Seconds to read an HTTP request. 0 disables the timeout.
+    pub http_read_timeout: u64,
+    /// Seconds to write an HTTP response. 0 disables the timeout.
+    pub http_write_timeout: u64,
+
+    /// Score to reach to win a match. If `None`, parties are endless.
+    pub target: Option<i32>,
+
+    /// Limits applied to each client.
+    pub limits: Limits,
+
+    /// Directory where the server state is kept, if any.
+    pub data_dir: Option<PathBuf>,
+
+    /// Address for the admin console. Defaults to `localhost`.
+    pub admin_address: String,
+    /// Port for the admin console, if any.
+    pub admin_port: Option<u16>,
+    /// Token required by the admin console. Defaults to a random one.
+    pub admin_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "localhost".to_string(),
+            port: 3000,
+            threads: None,
+            tcp_port: None,
+            log_level: "error".to_string(),
+            tls_certificate: None,
+            tls_key: None,
+            queue_timeout: TICKET_TIMEOUT_SECONDS,
+            shutdown_deadline: 0,
+            http_read_timeout: HTTP_TIMEOUT_SECONDS,
+            http_write_timeout: HTTP_TIMEOUT_SECONDS,
+            target: None,
+            limits: Limits::default(),
+            data_dir: None,
+            admin_address: "localhost".to_string(),
+            admin_port: None,
+            admin_token: None,
+        }
+    }
+}
+
+// Sections of the configuration file.
+
+#[derive(Debug,Default,RustcDecodable)]
+struct ServerSection {
+    address: Option<String>,
+    port: Option<u16>,
+    threads: Option<usize>,
+    tcp_port: Option<u16>,
+    log_level: Option<String>,
+}
+
+#[derive(Debug,Default,RustcDecodable)]
+struct TlsSection {
+    certificate: Option<String>,
+    key: Option<String>,
+}
+
+#[derive(Debug,Default,RustcDecodable)]
+struct TimeoutsSection {
+    queue: Option<i64>,
+    shutdown: Option<u64>,
+    read: Option<u64>,
+    write: Option<u64>,
+}
+
+#[derive(Debug,Default,RustcDecodable)]
+struct RulesSection {
+    target: Option<i32>,
+}
+
+#[derive(Debug,Default,RustcDecodable)]
+struct LimitsSection {
+    requests_per_ip: Option<u32>,
+    requests_per_player: Option<u32>,
+    pending_per_ip: Option<usize>,
+    ban_after: Option<u32>,
+    ban_seconds: Option<u32>,
+}
+
+#[derive(Debug,Default,RustcDecodable)]
+struct PersistenceSection {
+    dir: Option<String>,
+}
+
+#[derive(Debug,Default,RustcDecodable)]
+struct AdminSection {
+    address: Option<String>,
+    port: Option<u16>,
+    token: Option<String>,
+}
+
+#[derive(Debug,Default,RustcDecodable)]
+struct ConfigFile {
+    server: Option<ServerSection>,
+    tls: Option<TlsSection>,
+    timeouts: Option<TimeoutsSection>,
+    rules: Option<RulesSection>,
+    limits: Option<LimitsSection>,
+    persistence: Option<PersistenceSection>,
+    admin: Option<AdminSection>,
+}
+
+// Lists the settings left over after decoding, like `server.prot`.
+fn unknown_keys(prefix: &str, value: &toml::Value, keys: &mut Vec<String>) {
+    match value {
+        &toml::Value::Table(ref table) if !table.is_empty() => {
+            for (key, value) in table {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                unknown_keys(&name, value, keys);
+            }
+        }
+        _ => keys.push(prefix.to_string()),
+    }
+}
+
+// Checks that `address:port` can be listened on.
+fn check_address(what: &str, address: &str, port: u16) -> Result<(), String> {
+    match (address, port).to_socket_addrs() {
+        Ok(_) => Ok(()),
+        Err(err) => Err(format!("invalid {} address `{}`: {}", what, address, err)),
+    }
+}
+
+impl Config {
+    /// Reads the given file, with defaults for the missing settings.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let mut content = String::new();
+        try!(fs::File::open(path)
+                 .and_then(|mut file| file.read_to_string(&mut content))
+                 .map_err(|err| format!("cannot read `{}`: {}", path.display(), err)));
+
+        let mut parser = toml::Parser::new(&content);
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let errors: Vec<String> = parser.errors
+                                                .iter()
+                                                .map(|err| {
+                                                    let (line, col) = parser.to_linecol(err.lo);
+                                                    format!("{}:{}:{}: {}",
+                                                            path.display(),
+                                                            line + 1,
+                                                            col + 1,
+                                                            err.desc)
+                                                })
+                                                .collect();
+                return Err(errors.join("\n"));
+            }
+        };
+
+        let mut decoder = toml::Decoder::new(toml::Value::Table(table));
+        let file = try!(ConfigFile::decode(&mut decoder)
+                            .map_err(|err| format!("{}: {}", path.display(), err)));
+        // The decoder leaves what it didn't use.
+        if let Some(ref leftover) = decoder.toml {
+            let mut keys = Vec::new();
+            unknown_keys("", leftover, &mut keys);
+            return Err(format!("{}: unknown settings: {}", path.display(), keys.join(", ")));
+        }
+
+        let mut config = Config::default();
+        config.apply(file);
+        Ok(config)
+    }
+
+    // Overrides the defaults with the file settings.
+    fn apply(&mut self, file: ConfigFile) {
+        let server = file.server.unwrap_or_else(Default::default);
+        if let Some(address) = server.address {
+            self.address = address;
+        }
+        if let Some(port) = server.port {
+            self.port = port;
+        }
+        self.threads = server.threads.or(self.threads);
+        self.tcp_port = server.tcp_port.or(self.tcp_port);
+        if let Some(log_level) = server.log_level {
+            self.log_level = log_level;
+        }
+
+        let tls = file.tls.unwrap_or_else(Default::default);
+        if let Some(certificate) = tls.certificate {
+            self.tls_certificate = Some(PathBuf::from(certificate));
+        }
+        if let Some(key) = tls.key {
+            self.tls_key = Some(PathBuf::from(key));
+        }
+
+        let timeouts = file.timeouts.unwrap_or_else(Default::default);
+        if let Some(queue) = timeouts.queue {
+            self.queue_timeout = queue;
+        }
+        if let Some(shutdown) = timeouts.shutdown {
+            self.shutdown_deadline = shutdown;
+        }
+        if let Some(read) = timeouts.read {
+            self.http_read_timeout = read;
+        }
+        if let Some(write) = timeouts.write {
+            self.http_write_timeout = write;
+        }
+
+        self.target = file.rules.unwrap_or_else(Default::default).target.or(self.target);
+
+        let limits = file.limits.unwrap_or_else(Default::default);
+        if let Some(requests) = limits.requests_per_ip {
+            self.limits.requests_per_ip = requests;
+        }
+        if let Some(requests) = limits.requests_per_player {
+            self.limits.requests_per_player = requests;
+        }
+        if let Some(pending) = limits.pending_per_ip {
+            self.limits.pending_per_ip = pending;
+        }
+        if let Some(ban_after) = limits.ban_after {
+            self.limits.ban_after = ban_after;
+        }
+        if let Some(ban_seconds) = limits.ban_seconds {
+            self.limits.ban_seconds = ban_seconds;
+        }
+
+        if let Some(dir) = file.persistence.unwrap_or_else(Default::default).dir {
+            self.data_dir = Some(PathBuf::from(dir));
+        }
+
+        let admin = file.admin.unwrap_or_else(Default::default);
+        if let Some(address) = admin.address {
+            self.admin_address = address;
+        }
+        self.admin_port = admin.port.or(self.admin_port);
+        self.admin_token = admin.token.or(self.admin_token.take());
+    }
+
+    /// Checks every setting, and explains the first invalid one.
+    pub fn validate(&self) -> Result<(), String> {
+        try!(check_address("bind", &self.address, self.port));
+
+        if self.threads == Some(0) {
+            return Err("the number of threads must be at least 1".to_string());
+        }
+        if self.tcp_port == Some(self.port) {
+            return Err(format!("the binary protocol can't use the HTTP port ({})", self.port));
+        }
+        if !LOG_LEVELS.iter().any(|&level| level == self.log_level) {
+            return Err(format!("invalid log level `{}` (expected one of: {})",
+                               self.log_level,
+                               LOG_LEVELS.join(", ")));
+        }
+
+        match (&self.tls_certificate, &self.tls_key) {
+            (&Some(ref certificate), &Some(ref key)) => {
+                for file in [certificate, key].iter() {
+                    if !file.is_file() {
+                        return Err(format!("TLS file not found: `{}`", file.display()));
+                    }
+                }
+            }
+            (&None, &None) => (),
+            _ => return Err("TLS needs both a certificate and a key".to_string()),
+        }
+
+        if self.queue_timeout <= 0 {
+            return Err(format!("invalid queue timeout: {} (must be positive)",
+                               self.queue_timeout));
+        }
+
+        if let Some(target) = self.target {
+            if target <= 0 {
+                return Err(format!("invalid target score: {} (must be positive)", target));
+            }
+        }
+
+        if self.limits.ban_after != 0 && self.limits.ban_seconds == 0 {
+            return Err("bans need a duration: set ban_seconds, or ban_after to 0".to_string());
+        }
+
+        if let Some(ref dir) = self.data_dir {
+            if dir.exists() && !dir.is_dir() {
+                return Err(format!("`{}` is not a directory", dir.display()));
+            }
+        }
+
+        match self.admin_port {
+            Some(admin_port) => {
+                try!(check_address("admin", &self.admin_address, admin_port));
+                if admin_port == self.port || Some(admin_port) == self.tcp_port {
+                    return Err(format!("the admin console needs its own port ({} is taken)",
+                                       admin_port));
+                }
+            }
+            None if self.admin_token.is_some() => {
+                return Err("an admin token was given without an admin port".to_string());
+            }
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    /// File where the players ratings are kept, if persistence is enabled.
+    pub fn ratings_path(&self) -> Option<PathBuf> {
+        self.data_dir.as_ref().map(|dir| dir.join("ratings.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::Config;
+
+    // Writes the content to a new temporary file.
+    fn config_file(content: &str) -> PathBuf {
+        let file = format!("coinched-config-{}.toml", ::rand::random::<u32>());
+        let path = env::temp_dir().join(file);
+        fs::File::create(&path).unwrap().write_all(content.as_bytes()).unwrap();
+        path
+    }
+
+    fn load(content: &str) -> Result<Config, String> {
+        let path = config_file(content);
+        let config = Config::load(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn load_settings() {
+        let config = load("[server]\nport = 4000\nlog_level = \"info\"\n\n\
+                           [timeouts]\nqueue = 10\nread = 5\nwrite = 0\n\n\
+                           [limits]\nban_after = 0\n")
+                         .unwrap();
+        assert_eq!(config.port, 4000);
+        assert_eq!(config.log_level, "info");
+        assert_eq!(config.queue_timeout, 10);
+        assert_eq!(config.http_read_timeout, 5);
+        assert_eq!(config.http_write_timeout, 0);
+        assert_eq!(config.limits.ban_after, 0);
+        // Missing settings keep their default.
+        assert_eq!(config.address, "localhost");
+        assert_eq!(config.target, None);
+        assert!(config.validate().is_ok());
+
+        assert!(load("").unwrap().validate().is_ok());
+    }
+
+    #[test]
+    fn unknown_settings() {
+        let err = load("[server]\nprot = 4000\n").unwrap_err();
+        assert!(err.contains("server.prot"), "{}", err);
+
+        let err = load("[server]\nport = 4000\n\n[colors]\nred = true\n").unwrap_err();
+        assert!(err.contains("colors.red"), "{}", err);
+
+        let err = load("[rules]\n\n[extra]\n").unwrap_err();
+        assert!(err.contains("extra"), "{}", err);
+    }
+
+    #[test]
+    fn bad_files() {
+        let err = load("[server]\nport = \n").unwrap_err();
+        assert!(err.contains(":2:"), "{}", err);
+
+        assert!(load("[server]\nport = \"3000\"\n").is_err());
+        assert!(Config::load(env::temp_dir().join("coinched-missing.toml")).is_err());
+    }
+
+    #[test]
+    fn validate() {
+        assert!(Config::default().validate().is_ok());
+
+        fn invalid<F: Fn(&mut Config)>(change: F) -> bool {
+            let mut config = Config::default();
+            change(&mut config);
+            config.validate().is_err()
+        }
+        assert!(invalid(|config| config.threads = Some(0)));
+        assert!(invalid(|config| config.tcp_port = Some(3000)));
+        assert!(invalid(|config| config.log_level = "loud".to_string()));
+        assert!(invalid(|config| config.tls_key = Some(PathBuf::from("key.pem"))));
+        assert!(invalid(|config| {
+            config.tls_certificate = Some(PathBuf::from("/nonexistent/cert.pem"));
+            config.tls_key = Some(PathBuf::from("/nonexistent/key.pem"));
+        }));
+        assert!(invalid(|config| config.queue_timeout = 0));
+        assert!(invalid(|config| config.target = Some(0)));
+        assert!(invalid(|config| config.limits.ban_seconds = 0));
+        assert!(invalid(|config| config.admin_token = Some("secret".to_string())));
+        assert!(invalid(|config| config.admin_port = Some(3000)));
+
+        let mut config = Config::default();
+        config.limits.ban_after = 0;
+        config.limits.ban_seconds = 0;
+        config.admin_port = Some(3001);
+        config.admin_token = Some("secret".to_string());
+        assert!(config.validate().is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+//! Cross-origin resource sharing.
+//!
+//! Lets web clients hosted on another origin call the API.
+
+use iron::prelude::*;
+use iron;
+
+header!{(Origin, "Origin") => [String]}
+header!{(AllowOrigin, "Access-Control-Allow-Origin") => [String]}
+header!{(AllowMethods, "Access-Control-Allow-Methods") => [String]}
+header!{(AllowHeaders, "Access-Control-Allow-Headers") => [String]}
+header!{(MaxAge, "Access-Control-Max-Age") => [u32]}
+header!{(Vary, "Vary") => [String]}
+
+/// Which cross-origin requests are allowed.
+#[derive(Clone,Debug)]
+pub struct Cors {
+    /// Allowed origins, like `http://example.com`. `*` allows any origin.
+    pub origins: Vec<String>,
+    /// Allowed methods, sent in answer to preflight requests.
+    pub methods: Vec<String>,
+    /// Allowed request headers, sent in answer to preflight requests.
+    pub headers: Vec<String>,
+    /// How long browsers may cache a preflight answer, in seconds.
+    pub max_age: u32,
+}
+
+impl Default for Cors {
+    /// Allows any origin to use the API.
+    fn default() -> Self {
+        Cors {
+            origins: vec!["*".to_string()],
+            methods: vec!["GET".to_string(), "POST".to_string(), "OPTIONS".to_string()],
+            headers: vec!["Content-Type".to_string()],
+            max_age: 3600,
+        }
+    }
+}
+
+impl Cors {
+    // Returns the value for `Access-Control-Allow-Origin`, if the origin is allowed.
+    fn allowed_origin(&self, origin: &str) -> Option<String> {
+        if self.origins.iter().any(|o| o == "*") {
+            Some("*".to_string())
+        } else if self.origins.iter().any(|o| o == origin) {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Adds the CORS headers to the response for the given request.
+    ///
+    /// Requests from origins not allowed get no header: the browser will block them.
+    pub fn apply(&self, req: &Request, res: &mut Response) {
+        let origin = match req.headers.get::<Origin>() {
+            Some(&Origin(ref origin)) => origin.clone(),
+            // Not a cross-origin request.
+            None => return,
+        };
+
+        let preflight = req.method == iron::method::Options;
+        self.set_headers(&origin, preflight, &mut res.headers);
+    }
+
+    // Sets the headers answering a request from the given origin.
+    fn set_headers(&self, origin: &str, preflight: bool, headers: &mut iron::Headers) {
+        let allowed = match self.allowed_origin(origin) {
+            Some(allowed) => allowed,
+            None => {
+                trace!("Rejected origin: {}", origin);
+                return;
+            }
+        };
+
+        if allowed != "*" {
+            // The answer depends on the origin: caches must know.
+            headers.set(Vary("Origin".to_string()));
+        }
+        headers.set(AllowOrigin(allowed));
+
+        if preflight {
+            headers.set(AllowMethods(self.methods.join(", ")));
+            headers.set(AllowHeaders(self.headers.join(", ")));
+            headers.set(MaxAge(self.max_age));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iron;
+
+    use super::{AllowHeaders, AllowMethods, AllowOrigin, Cors, MaxAge, Vary};
+
+    fn answer(cors: &Cors, origin: &str, preflight: bool) -> iron::Headers {
+        let mut headers = iron::Headers::new();
+        cors.set_headers(origin, preflight, &mut headers);
+        headers
+    }
+
+    fn listed() -> Cors {
+        Cors { origins: vec!["http://example.com".to_string()], ..Cors::default() }
+    }
+
+    #[test]
+    fn any_origin() {
+        let headers = answer(&Cors::default(), "http://example.com", false);
+        assert_eq!(headers.get::<AllowOrigin>(), Some(&AllowOrigin("*".to_string())));
+        // Same answer for everyone.
+        assert!(headers.get::<Vary>().is_none());
+        assert!(headers.get::<AllowMethods>().is_none());
+    }
+
+    #[test]
+    fn allowed_origin() {
+        let headers = answer(&listed(), "http://example.com", false);
+        assert_eq!(headers.get::<AllowOrigin>(),
+                   Some(&AllowOrigin("http://example.com".to_string())));
+        assert_eq!(headers.get::<Vary>(), Some(&Vary("Origin".to_string())));
+    }
+
+    #[test]
+    fn denied_origin() {
+        assert_eq!(answer(&listed(), "http://evil.com", false).len(), 0);
+        assert_eq!(answer(&listed(), "http://evil.com", true).len(), 0);
+    }
+
+    #[test]
+    fn preflight() {
+        let headers = answer(&listed(), "http://example.com", true);
+        assert_eq!(headers.get::<AllowMethods>(),
+                   Some(&AllowMethods("GET, POST, OPTIONS".to_string())));
+        assert_eq!(headers.get::<AllowHeaders>(),
+                   Some(&AllowHeaders("Content-Type".to_string())));
+        assert_eq!(headers.get::<MaxAge>(), Some(&MaxAge(3600)));
+        assert_eq!(headers.get::<Vary>(), Some(&Vary("Origin".to_string())));
+    }
+}
@@ -4,17 +4,55 @@ use std::convert::From;
 use libcoinche::bid;
 use libcoinche::game;
 
+use {BidErrorKind, PlayErrorKind};
+
+use super::game_manager::{MAX_CHAT_LENGTH, MAX_TABLES_PER_CLIENT};
+
 /// A possible error.
 pub enum Error {
     /// The given player ID is not associated with an actual game
     BadPlayerId,
     /// The given event ID is not associated with an actual event
     BadEventId,
+    /// The given party ID is not associated with an actual party
+    BadPartyId,
+    /// The given table ID is not associated with an open table
+    BadTableId,
+    /// The table password is missing or wrong.
+    BadPassword,
+    /// Tables can only be joined with a blocking join.
+    QueueTable,
+    /// The client already created too many open tables.
+    TooManyTables,
+
+    /// No tournament is running on this server.
+    NoTournament,
+    /// The tournament already has all its players.
+    TournamentFull,
+    /// The tournament is over, or the player was eliminated.
+    TournamentOver,
+    /// The given ticket is not associated with a registered player.
+    BadTicket,
+
+    /// The party is over: it was cancelled, or the match was won.
+    PartyOver,
+
+    /// The server is shutting down, and doesn't accept new players.
+    ShuttingDown,
+
+    /// The chat message is empty or too long.
+    BadMessage,
+    /// The player sent too many chat messages recently.
+    ChatTooFast,
+    /// Only emotes are allowed at ranked tables.
+    ChatRestricted,
 
     /// Player tried to play a card during auction.
     PlayInAuction,
     /// Player tried to bid during card play.
     BidInGame,
+    /// No trick was completed yet, so there is no last trick to show.
+    NoLastTrick,
 
     /// An error occured during bidding.
     Bid(bid::BidError),
@@ -27,21 +65,103 @@ impl fmt::Display for Error {
         match self {
             &Error::BadPlayerId => write!(f, "player not found"),
             &Error::BadEventId => write!(f, "event not found"),
+            &Error::BadPartyId => write!(f, "party not found"),
+            &Error::BadTableId => write!(f, "table not found"),
+            &Error::BadPassword => write!(f, "wrong table password"),
+            &Error::QueueTable => write!(f, "use /join to sit at a table"),
+            &Error::TooManyTables => {
+                write!(f, "at most {} open tables per client", MAX_TABLES_PER_CLIENT)
+            }
+            &Error::NoTournament => write!(f, "no tournament running"),
+            &Error::TournamentFull => write!(f, "tournament is full"),
+            &Error::TournamentOver => write!(f, "no more match to play"),
+            &Error::BadTicket => write!(f, "ticket not found"),
+            &Error::PartyOver => write!(f, "party is over"),
+            &Error::ShuttingDown => write!(f, "server shutting down"),
+            &Error::BadMessage => {
+                write!(f, "message must be 1 to {} characters long", MAX_CHAT_LENGTH)
+            }
+            &Error::ChatTooFast => write!(f, "too many messages, slow down"),
+            &Error::ChatRestricted => write!(f, "only emotes are allowed at ranked tables"),
             &Error::PlayInAuction => write!(f, "cannot play during auction"),
             &Error::BidInGame => write!(f, "cannot bid during card play"),
+            &Error::NoLastTrick => write!(f, "no trick completed yet"),
             &Error::Bid(ref error) => write!(f, "{}", error),
             &Error::Play(ref error) => write!(f, "{}", error),
         }
     }
 }
 
+impl Error {
+    /// Returns a stable, machine-readable code for this error.
+    ///
+    /// Rule violations are prefixed with `bid_` or `play_`, followed
+    /// by the libcoinche error, like `play_incorrect_suit`.
+    pub fn code(&self) -> String {
+        match self {
+            &Error::BadPlayerId => "bad_player_id".to_string(),
+            &Error::BadEventId => "bad_event_id".to_string(),
+            &Error::BadPartyId => "bad_party_id".to_string(),
+            &Error::BadTableId => "bad_table_id".to_string(),
+            &Error::BadPassword => "bad_password".to_string(),
+            &Error::QueueTable => "queue_table".to_string(),
+            &Error::TooManyTables => "too_many_tables".to_string(),
+            &Error::NoTournament => "no_tournament".to_string(),
+            &Error::TournamentFull => "tournament_full".to_string(),
+            &Error::TournamentOver => "tournament_over".to_string(),
+            &Error::BadTicket => "bad_ticket".to_string(),
+            &Error::PartyOver => "party_over".to_string(),
+            &Error::ShuttingDown => "shutting_down".to_string(),
+            &Error::BadMessage => "bad_message".to_string(),
+            &Error::ChatTooFast => "chat_too_fast".to_string(),
+            &Error::ChatRestricted => "chat_restricted".to_string(),
+            &Error::PlayInAuction => "play_in_auction".to_string(),
+            &Error::BidInGame => "bid_in_game".to_string(),
+            &Error::NoLastTrick => "no_last_trick".to_string(),
+            &Error::Bid(ref error) => format!("bid_{}", BidErrorKind::from(error).code()),
+            &Error::Play(ref error) => format!("play_{}", PlayErrorKind::from(error).code()),
+        }
+    }
+}
+
 impl From<bid::BidError> for Error {
     fn from(err: bid::BidError) -> Error {
         Error::Bid(err)
     }
 }
+/// `NoLastTrick` is not a rule violation: it gets its own error.
 impl From<game::PlayError> for Error {
     fn from(err: game::PlayError) -> Error {
-        Error::Play(err)
+        match err {
+            game::PlayError::NoLastTrick => Error::NoLastTrick,
+            err => Error::Play(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libcoinche::{bid, game};
+    use super::Error;
+    use super::super::game_manager::MAX_CHAT_LENGTH;
+
+    #[test]
+    fn codes() {
+        assert_eq!(Error::BadPlayerId.code(), "bad_player_id");
+        assert_eq!(Error::TooManyTables.code(), "too_many_tables");
+        assert_eq!(Error::Bid(bid::BidError::NonRaisedTarget).code(),
+                   "bid_non_raised_target");
+        assert_eq!(Error::Bid(bid::BidError::OverCoinche).code(), "bid_over_coinche");
+        assert_eq!(Error::Play(game::PlayError::IncorrectSuit).code(),
+                   "play_incorrect_suit");
+        assert_eq!(Error::from(game::PlayError::NoLastTrick).code(), "no_last_trick");
+        assert_eq!(Error::from(game::PlayError::TurnError).code(), "play_turn_error");
+    }
+
+    #[test]
+    fn messages() {
+        assert_eq!(Error::BadTicket.to_string(), "ticket not found");
+        assert_eq!(Error::BadMessage.to_string(),
+                   format!("message must be 1 to {} characters long", MAX_CHAT_LENGTH));
     }
 }
@@ -3,16 +3,24 @@
 use rand::{thread_rng, Rng};
 use time;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::IpAddr;
 use std::sync::{Arc, RwLock, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use eventual::{Future, Complete, Async};
 
 use libcoinche::{bid, cards, pos, game, trick};
-use {Event, EventType, PlayerEvent};
-use {NewPartyInfo, ContractBody, CardBody};
+use {Event, EventType, PlayerEvent, Emote};
+use {NewPartyInfo, QueueStatus, Lobby, TableInfo};
+use {ContractBody, CardBody, ChatBody, EmoteBody, JoinBody, NewTableBody};
 
 use super::error::Error;
+use super::rating::{self, Ratings};
+use super::metrics::Metrics;
+use super::matchmaking::{Criteria, Queue, Waiter};
+use super::admin::{AdminPlayer, PartyState, PartySummary};
 
 use self::FutureResult::{Ready, Waiting};
 
@@ -26,6 +34,73 @@ type JoinResult = FutureResult<NewPartyInfo>;
 
 pub type ManagerResult<T> = Result<T, Error>;
 
+/// Message sent to the parties cancelled by a shutdown.
+pub const SHUTDOWN_MESSAGE: &'static str = "server shutting down";
+
+// Reasons given when a player leaves a party, followed by his seat.
+// Missing players never took the seat found for them in the queue.
+const LEAVE_REASONS: [&'static str; 3] = ["player left", "player kicked", "player missing"];
+
+/// Open tables a single client can have at once.
+pub const MAX_TABLES_PER_CLIENT: usize = 5;
+/// Tables nobody sat at are closed after this many seconds.
+pub const EMPTY_TABLE_SECONDS: i64 = 300;
+
+/// Finished parties are forgotten after this many seconds,
+/// once their players had time to see the last events.
+pub const ENDED_PARTY_SECONDS: i64 = 60;
+
+/// Longest chat message accepted, in characters.
+pub const MAX_CHAT_LENGTH: usize = 200;
+/// Chat messages and emotes a player can send every `CHAT_PERIOD_SECONDS`.
+pub const CHAT_BURST: usize = 5;
+pub const CHAT_PERIOD_SECONDS: i64 = 10;
+
+/// A set of hands, one for each player.
+pub type Deal = [cards::Hand; 4];
+
+/// Settings for a new party.
+#[derive(Clone,Default)]
+pub struct PartySettings {
+    /// Deals to play, in order. The party ends once they are all played.
+    /// If empty, new hands are shuffled for each game.
+    pub deals: Vec<Deal>,
+    /// Score a team must reach to win the match.
+    /// If `None`, the party goes on until someone leaves.
+    pub target: Option<i32>,
+    /// Names of the players at each seat, if they gave one.
+    pub names: [Option<String>; 4],
+    /// Rule variant label, if the players asked for one.
+    pub variant: Option<String>,
+    /// If true, the party was created from a password-protected table.
+    pub private: bool,
+    /// If true, players can only send emotes, not free chat.
+    pub ranked: bool,
+}
+
+// A table waiting for players.
+struct OpenTable {
+    password: Option<String>,
+    variant: Option<String>,
+    target: Option<i32>,
+    ranked: bool,
+    // Players already seated, in order.
+    seats: Vec<(Complete<NewPartyInfo, ()>, Option<String>)>,
+    // Who created the table, and when (in seconds).
+    creator: IpAddr,
+    created: i64,
+}
+
+// Closes the tables nobody sat at for a while.
+fn remove_empty_tables(tables: &mut HashMap<u32, OpenTable>) {
+    let deadline = time::get_time().sec - EMPTY_TABLE_SECONDS;
+    tables.retain(|_, table| !table.seats.is_empty() || table.created >= deadline);
+}
+
+// Seat given to each player of a new party, from best to worst rated.
+// The best and the worst players are partners, to balance the teams.
+const BALANCED_SEATS: [usize; 4] = [0, 1, 3, 2];
+
 
 /// Base class for managing matchmaking.
 ///
@@ -34,7 +109,35 @@ pub type ManagerResult<T> = Result<T, Error>;
 pub struct GameManager {
     party_list: RwLock<PlayerList>,
 
-    waiting_list: Mutex<Vec<Complete<NewPartyInfo, ()>>>,
+    waiting_list: Mutex<Queue>,
+    // Tables waiting for players. Their ID becomes the party ID.
+    tables: Mutex<HashMap<u32, OpenTable>>,
+
+    ratings: Ratings,
+    // Score to reach to win a match, for parties created by `join`.
+    match_target: Option<i32>,
+    // Set once the server is shutting down.
+    shutting_down: AtomicBool,
+
+    metrics: Arc<Metrics>,
+}
+
+// Describes a table for the lobby.
+// Who sits at a private table is nobody else's business.
+fn table_info(table_id: u32, table: &OpenTable) -> TableInfo {
+    let private = table.password.is_some();
+    TableInfo {
+        id: table_id,
+        private: private,
+        variant: table.variant.clone(),
+        target: table.target,
+        players: table.seats
+                      .iter()
+                      .map(|&(_, ref name)| if private { None } else { name.clone() })
+                      .collect(),
+        in_progress: false,
+        ranked: table.ranked,
+    }
 }
 
 /// Describe a single game.
@@ -55,9 +158,13 @@ impl Game {
 }
 
 // Creates a new game, starting with an auction.
+// Uses the given deal if any, or shuffles new hands.
 // Also returns a NewGame Event with the players cards.
-fn make_game(first: pos::PlayerPos) -> (bid::Auction, EventType) {
-    let auction = bid::Auction::new(first);
+fn make_game(first: pos::PlayerPos, deal: Option<Deal>) -> (bid::Auction, EventType) {
+    let auction = match deal {
+        Some(hands) => bid::Auction::new_with_hands(first, hands),
+        None => bid::Auction::new(first),
+    };
     let hands = auction.hands();
 
     let event = EventType::NewGame {
@@ -75,19 +182,59 @@ struct Party {
 
     scores: [i32; 2],
 
+    // Deals left to play, for a party with fixed deals.
+    deals: VecDeque<Deal>,
+    // If true, the party ends once the deals are all played.
+    // Otherwise, new hands are shuffled for each game.
+    fixed_deals: bool,
+    // Score to reach to win the match, if any.
+    target: Option<i32>,
+    // Set once the party is over, either cancelled or won.
+    over: bool,
+    // When the party ended, in seconds.
+    ended: Option<i64>,
+    // If true, the party ends after the current deal.
+    last_deal: bool,
+    // Players names, for ratings.
+    names: [Option<String>; 4],
+    // Rule variant label.
+    variant: Option<String>,
+    // Hidden behind a password?
+    private: bool,
+    // Only emotes allowed?
+    ranked: bool,
+
     events: Vec<EventType>,
     observers: Mutex<Vec<Complete<Event, ()>>>,
+
+    metrics: Arc<Metrics>,
 }
 
 impl Party {
-    fn new(first: pos::PlayerPos) -> Self {
-        let (auction, event) = make_game(first);
+    fn new(first: pos::PlayerPos, settings: PartySettings, metrics: Arc<Metrics>) -> Self {
+        let fixed_deals = !settings.deals.is_empty();
+        let mut deals: VecDeque<Deal> = settings.deals.into_iter().collect();
+
+        let (auction, event) = make_game(first, deals.pop_front());
+        metrics.party_started();
+        metrics.count_event(&event);
         Party {
             first: first,
             game: Game::Bidding(auction),
             scores: [0; 2],
+            deals: deals,
+            fixed_deals: fixed_deals,
+            target: settings.target,
+            over: false,
+            ended: None,
+            last_deal: false,
+            names: settings.names,
+            variant: settings.variant,
+            private: settings.private,
+            ranked: settings.ranked,
             events: vec![event],
             observers: Mutex::new(Vec::new()),
+            metrics: metrics,
         }
     }
 
@@ -97,7 +244,9 @@ impl Party {
             event: event.clone(),
             id: self.events.len(),
         };
+        self.metrics.count_event(&event);
         let mut observers = self.observers.lock().unwrap();
+        self.metrics.remove_observers(observers.len());
         for promise in observers.drain(..) {
             // TODO: handle cancelled wait?
             promise.complete(ev.clone());
@@ -107,7 +256,16 @@ impl Party {
         ev
     }
 
+    fn check_running(&self) -> ManagerResult<()> {
+        if self.over {
+            Err(Error::PartyOver)
+        } else {
+            Ok(())
+        }
+    }
+
     fn get_auction_mut(&mut self) -> ManagerResult<&mut bid::Auction> {
+        try!(self.check_running());
         match self.game {
             Game::Bidding(ref mut auction) => Ok(auction),
             Game::Playing(_) => Err(Error::BidInGame),
@@ -122,6 +280,7 @@ impl Party {
     }
 
     fn get_game_mut(&mut self) -> ManagerResult<&mut game::GameState> {
+        try!(self.check_running());
         match self.game {
             Game::Bidding(_) => Err(Error::PlayInAuction),
             Game::Playing(ref mut game) => Ok(game),
@@ -131,17 +290,70 @@ impl Party {
     fn next_game(&mut self) {
         // TODO: Maybe keep the current game in the history?
 
-        let (auction, event) = make_game(self.first);
+        if self.last_deal {
+            self.cancel(SHUTDOWN_MESSAGE.to_string());
+            return;
+        }
+
+        let deal = self.deals.pop_front();
+        if self.fixed_deals && deal.is_none() {
+            // We're out of deals: the party is over.
+            self.cancel("all deals played".to_string());
+            return;
+        }
+
+        let (auction, event) = make_game(self.first, deal);
 
         self.first = self.first.next();
         self.game = Game::Bidding(auction);
         self.add_event(event);
     }
 
+    fn chat(&mut self, pos: pos::PlayerPos, text: String) -> ManagerResult<Event> {
+        try!(self.check_running());
+        Ok(self.add_event(EventType::Chat {
+            from: pos,
+            text: text,
+        }))
+    }
+
+    fn emote(&mut self, pos: pos::PlayerPos, emote: Emote) -> ManagerResult<Event> {
+        try!(self.check_running());
+        Ok(self.add_event(EventType::FromPlayer(pos, PlayerEvent::Emote(emote))))
+    }
+
     fn cancel(&mut self, msg: String) {
+        self.end();
         self.add_event(EventType::PartyCancelled(msg));
     }
 
+    fn end(&mut self) {
+        if !self.over {
+            self.metrics.party_ended();
+        }
+        self.over = true;
+        self.ended = Some(time::get_time().sec);
+    }
+
+    // Returns the winning team if the match target was reached.
+    fn match_winner(&self) -> Option<pos::Team> {
+        let target = match self.target {
+            Some(target) => target,
+            None => return None,
+        };
+
+        if self.scores[0] < target && self.scores[1] < target {
+            None
+        } else if self.scores[0] > self.scores[1] {
+            Some(pos::Team::T02)
+        } else if self.scores[1] > self.scores[0] {
+            Some(pos::Team::T13)
+        } else {
+            // Perfect tie: play another game.
+            None
+        }
+    }
+
     fn bid(&mut self,
            pos: pos::PlayerPos,
            trump: cards::Suit,
@@ -214,7 +426,13 @@ impl Party {
         self.game = Game::Playing(game);
     }
 
-    fn play_card(&mut self, pos: pos::PlayerPos, card: cards::Card) -> Result<Event, Error> {
+    // Also returns the winning team to rate, if the card ended the match.
+    // Parties without target are rated after each game instead.
+    fn play_card(&mut self,
+                 pos: pos::PlayerPos,
+                 card: cards::Card)
+                 -> Result<(Event, Option<pos::Team>), Error> {
+        let mut rated = None;
         let result = {
             let game = try!(self.get_game_mut());
             try!(game.play_card(pos, card))
@@ -233,18 +451,32 @@ impl Party {
                         for i in 0..2 {
                             self.scores[i] += scores[i];
                         }
+                        if self.target.is_none() {
+                            rated = Some(winners);
+                        }
                         self.add_event(EventType::GameOver {
                             points: points,
                             winner: winners,
                             scores: scores,
                         });
-                        self.next_game();
+                        match self.match_winner() {
+                            Some(winner) => {
+                                rated = Some(winner);
+                                self.end();
+                                let scores = self.scores;
+                                self.add_event(EventType::MatchOver {
+                                    winner: winner,
+                                    scores: scores,
+                                });
+                            }
+                            None => self.next_game(),
+                        }
                     }
                 }
             }
         }
 
-        Ok(main_event)
+        Ok((main_event, rated))
     }
 }
 
@@ -252,21 +484,91 @@ impl Party {
 struct PlayerInfo {
     // The party he's playing in
     pub party: Arc<RwLock<Party>>,
+    // The ID of this party
+    pub party_id: u32,
     // His position in the table
     pub pos: pos::PlayerPos,
     // Last time we received something from him
     // (to detect inactivity, and disconnect him)
     pub last_time: Mutex<time::Tm>,
+    // When he sent his last chat messages, in seconds
+    pub chat_times: Mutex<VecDeque<i64>>,
+}
+
+impl PlayerInfo {
+    // Counts a chat message or an emote, unless he sent too many recently.
+    fn check_chat_rate(&self) -> ManagerResult<()> {
+        let now = time::get_time().sec;
+        let mut sent = self.chat_times.lock().unwrap();
+        while sent.front().map_or(false, |&time| time <= now - CHAT_PERIOD_SECONDS) {
+            sent.pop_front();
+        }
+        if sent.len() >= CHAT_BURST {
+            return Err(Error::ChatTooFast);
+        }
+        sent.push_back(now);
+        Ok(())
+    }
+}
+
+impl Drop for Party {
+    fn drop(&mut self) {
+        if !self.over {
+            self.metrics.party_ended();
+        }
+        // Remaining observers are aborted.
+        self.metrics.remove_observers(self.observers.lock().unwrap().len());
+    }
 }
 
 // Maps player IDs to PlayerInfo
 struct PlayerList {
     pub player_map: HashMap<u32, PlayerInfo>,
+    // Maps party IDs to the parties, for spectators
+    pub party_map: HashMap<u32, Arc<RwLock<Party>>>,
 }
 
 impl PlayerList {
     fn new() -> PlayerList {
-        PlayerList { player_map: HashMap::new() }
+        PlayerList {
+            player_map: HashMap::new(),
+            party_map: HashMap::new(),
+        }
+    }
+
+    fn get_party(&self, party_id: u32) -> Result<&Arc<RwLock<Party>>, Error> {
+        match self.party_map.get(&party_id) {
+            None => Err(Error::BadPartyId),
+            Some(party) => Ok(party),
+        }
+    }
+
+    // Lists the players of a party, for the admin console.
+    fn admin_players(&self, party_id: u32, party: &Party) -> Vec<AdminPlayer> {
+        let mut players: Vec<AdminPlayer> = self.player_map
+                                                .iter()
+                                                .filter(|&(_, info)| info.party_id == party_id)
+                                                .map(|(&player_id, info)| {
+                                                    AdminPlayer {
+                                                        id: player_id,
+                                                        pos: info.pos,
+                                                        name: party.names[info.pos as usize]
+                                                                  .clone(),
+                                                    }
+                                                })
+                                                .collect();
+        players.sort_by(|a, b| (a.pos as usize).cmp(&(b.pos as usize)));
+        players
+    }
+
+    // Creates a random party ID, not currently in use.
+    fn make_party_id(&self) -> u32 {
+        loop {
+            let id = thread_rng().next_u32();
+            if !self.party_map.contains_key(&id) {
+                return id;
+            }
+        }
     }
 
     fn get_player_info(&self, player_id: u32) -> Result<&PlayerInfo, Error> {
@@ -311,16 +613,57 @@ impl PlayerList {
         result
     }
 
-    fn remove(&mut self, player_id: u32) -> Result<(), Error> {
-        {
+    // Removes a player, cancelling his party with the given reason.
+    fn remove(&mut self, player_id: u32, reason: &str) -> Result<(), Error> {
+        let party_id = {
             let info = try!(self.get_player_info(player_id));
-            let pos = info.pos;
-            info.party.write().unwrap().cancel(format!("player left: {}", pos as usize));
-        }
+            let mut party = info.party.write().unwrap();
+            if !party.over {
+                party.cancel(format!("{}: {}", reason, info.pos as usize));
+            }
+            info.party_id
+        };
         self.player_map.remove(&player_id);
 
+        // Forget about the party once everyone left
+        if !self.player_map.values().any(|info| info.party_id == party_id) {
+            self.party_map.remove(&party_id);
+        }
+
         Ok(())
     }
+
+    // Forgets the parties which ended a while ago, and their players.
+    fn remove_ended(&mut self) {
+        let deadline = time::get_time().sec - ENDED_PARTY_SECONDS;
+        let ended: Vec<u32> = self.party_map
+                                  .iter()
+                                  .filter(|&(_, party)| {
+                                      party.read().unwrap().ended.map_or(false, |t| t < deadline)
+                                  })
+                                  .map(|(&party_id, _)| party_id)
+                                  .collect();
+        if ended.is_empty() {
+            return;
+        }
+
+        trace!("Removing ended parties: {:?}", ended);
+        self.player_map.retain(|_, info| !ended.contains(&info.party_id));
+        for party_id in ended {
+            self.party_map.remove(&party_id);
+        }
+    }
+}
+
+/// Returns the seat of the player who left, if a party was cancelled because of him.
+pub fn leaving_seat(msg: &str) -> Option<pos::PlayerPos> {
+    let mut parts = msg.splitn(2, ": ");
+    match (parts.next(), parts.next().and_then(|seat| seat.parse::<usize>().ok())) {
+        (Some(reason), Some(seat)) if LEAVE_REASONS.contains(&reason) && seat < 4 => {
+            Some(pos::PlayerPos::from_n(seat))
+        }
+        _ => None,
+    }
 }
 
 
@@ -328,90 +671,497 @@ impl GameManager {
     pub fn new() -> GameManager {
         GameManager {
             party_list: RwLock::new(PlayerList::new()),
-            waiting_list: Mutex::new(Vec::new()),
+            waiting_list: Mutex::new(Queue::new()),
+            tables: Mutex::new(HashMap::new()),
+            ratings: Ratings::new(),
+            match_target: None,
+            shutting_down: AtomicBool::new(false),
+            metrics: Arc::new(Metrics::new()),
         }
     }
 
+    /// Uses the given ratings for matchmaking.
+    pub fn with_ratings(mut self, ratings: Ratings) -> Self {
+        self.ratings = ratings;
+        self
+    }
+
+    /// Parties created by `join` end once a team reaches `target` points.
+    pub fn with_match_target(mut self, target: Option<i32>) -> Self {
+        self.match_target = target;
+        self
+    }
+
+    /// Removes ticket holders from the queue after `seconds` without checking their status.
+    pub fn with_queue_timeout(mut self, seconds: i64) -> Self {
+        self.waiting_list = Mutex::new(Queue::with_timeout(seconds));
+        self
+    }
+
+    /// Returns the players ratings.
+    pub fn ratings(&self) -> &Ratings {
+        &self.ratings
+    }
+
     /// Attempts to join a new party. Blocks until a party is available.
-    pub fn join(&self) -> ManagerResult<NewPartyInfo> {
-        trace!("Join");
-        match self.get_join_result() {
+    ///
+    /// Players are grouped in order of arrival, with players matching their criteria.
+    pub fn join(&self, body: JoinBody) -> ManagerResult<NewPartyInfo> {
+        trace!("Join: {:?}", body);
+        try!(self.check_accepting());
+        if let Some(table_id) = body.table {
+            return self.join_table(table_id, body);
+        }
+
+        match try!(self.get_join_result(body)) {
             // TODO: add a timeout (max: 20s)
-            // TODO: handle cancelled join?
             Ready(info) => Ok(info),
-            Waiting(future) => Ok(future.await().unwrap()),
+            // Waiters are only failed when the server shuts down.
+            Waiting(future) => future.await().map_err(|_| Error::ShuttingDown),
         }
     }
 
-    fn get_join_result(&self) -> JoinResult {
-        let mut waiters = self.waiting_list.lock().unwrap();
-        // println!("Waiters: {}", waiters.len());
-        if waiters.len() >= 3 {
-            // It's a PARTEY!
-            let info = self.make_party(vec![
-               waiters.pop().unwrap(),
-               waiters.pop().unwrap(),
-               waiters.pop().unwrap(),
-            ]);
-            return Ready(info);
+    /// Enters the matchmaking queue, without waiting for a party.
+    ///
+    /// Use `queue_status` with the returned ticket to follow the queue.
+    pub fn enqueue(&self, body: JoinBody) -> ManagerResult<QueueStatus> {
+        trace!("Enqueue: {:?}", body);
+        if body.table.is_some() {
+            return Err(Error::QueueTable);
+        }
+
+        let mut queue = self.waiting_list.lock().unwrap();
+        // Checked with the lock, so `begin_shutdown` can't miss us.
+        try!(self.check_accepting());
+        self.prune_queue(&mut queue);
+
+        let newcomer = self.make_waiter(&queue, body);
+        let ticket = newcomer.ticket;
+        match queue.find_group(&newcomer) {
+            Some(mut players) => {
+                players.push(newcomer);
+                let info = self.make_party(&mut queue, players, Some(ticket)).unwrap();
+                self.metrics.set_waiting(queue.len());
+                Ok(QueueStatus {
+                    ticket: ticket,
+                    position: 0,
+                    party: Some(info),
+                })
+            }
+            None => {
+                queue.push(newcomer);
+                self.metrics.set_waiting(queue.len());
+                Ok(QueueStatus {
+                    ticket: ticket,
+                    position: queue.len(),
+                    party: None,
+                })
+            }
+        }
+    }
+
+    /// Returns the position of a ticket in the queue, or its party once found.
+    pub fn queue_status(&self, ticket: u32) -> ManagerResult<QueueStatus> {
+        let mut queue = self.waiting_list.lock().unwrap();
+        self.prune_queue(&mut queue);
+        self.metrics.set_waiting(queue.len());
+
+        if let Some(info) = queue.take_match(ticket) {
+            return Ok(QueueStatus {
+                ticket: ticket,
+                position: 0,
+                party: Some(info),
+            });
+        }
+        // The queue was emptied.
+        try!(self.check_accepting());
+
+        match queue.position(ticket) {
+            Some(position) => {
+                Ok(QueueStatus {
+                    ticket: ticket,
+                    position: position,
+                    party: None,
+                })
+            }
+            None => Err(Error::BadTicket),
+        }
+    }
+
+    // New players are refused once the server is shutting down.
+    fn check_accepting(&self) -> ManagerResult<()> {
+        if self.shutting_down.load(Ordering::SeqCst) {
+            Err(Error::ShuttingDown)
         } else {
+            Ok(())
+        }
+    }
+
+    /// Stops accepting new players, before shutting down.
+    ///
+    /// Players waiting for a party, in the queue or at an open table, get a
+    /// `ShuttingDown` error. Running parties end after their current deal,
+    /// with a `PartyCancelled` event.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+
+        let waiters = {
+            let mut queue = self.waiting_list.lock().unwrap();
+            self.metrics.set_waiting(0);
+            queue.drain()
+        };
+        for waiter in waiters {
+            if let Some(promise) = waiter.promise {
+                promise.fail(());
+            }
+        }
+
+        let tables: Vec<OpenTable> = {
+            let mut tables = self.tables.lock().unwrap();
+            tables.drain().map(|(_, table)| table).collect()
+        };
+        for table in tables {
+            for (promise, _) in table.seats {
+                promise.fail(());
+            }
+        }
+
+        let list = self.party_list.read().unwrap();
+        for party in list.party_map.values() {
+            party.write().unwrap().last_deal = true;
+        }
+    }
+
+    /// Returns the number of parties still being played.
+    pub fn running_parties(&self) -> usize {
+        let list = self.party_list.read().unwrap();
+        list.party_map.values().filter(|party| !party.read().unwrap().over).count()
+    }
+
+    /// Cancels every running party. Players get a `PartyCancelled` event with the given message.
+    pub fn cancel_all(&self, msg: &str) {
+        let list = self.party_list.read().unwrap();
+        for party in list.party_map.values() {
+            let mut party = party.write().unwrap();
+            if !party.over {
+                party.cancel(msg.to_string());
+            }
+        }
+    }
+
+    /// Writes the state kept on disk, if any.
+    pub fn persist(&self) -> io::Result<()> {
+        self.ratings.save()
+    }
+
+    /// Returns the metrics collected by this manager.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Returns the number of players waiting for a party.
+    pub fn waiting_count(&self) -> usize {
+        self.waiting_list.lock().unwrap().len()
+    }
+
+    /// Creates a new table, waiting for players to join it.
+    ///
+    /// Each client can have `MAX_TABLES_PER_CLIENT` open tables at once.
+    pub fn create_table(&self, body: NewTableBody, client: IpAddr) -> ManagerResult<TableInfo> {
+        let mut tables = self.tables.lock().unwrap();
+        // Checked with the lock, so `begin_shutdown` can't miss the table.
+        try!(self.check_accepting());
+        remove_empty_tables(&mut tables);
+        if tables.values().filter(|table| table.creator == client).count() >=
+           MAX_TABLES_PER_CLIENT {
+            return Err(Error::TooManyTables);
+        }
+
+        // Table IDs become party IDs: avoid both.
+        let table_id = {
+            let list = self.party_list.read().unwrap();
+            let mut table_id = list.make_party_id();
+            while tables.contains_key(&table_id) {
+                table_id = list.make_party_id();
+            }
+            table_id
+        };
+
+        let table = OpenTable {
+            password: body.password,
+            variant: body.variant,
+            target: body.target,
+            ranked: body.ranked.unwrap_or(false),
+            seats: Vec::new(),
+            creator: client,
+            created: time::get_time().sec,
+        };
+        let info = table_info(table_id, &table);
+        tables.insert(table_id, table);
+
+        trace!("New table: {}", table_id);
+        Ok(info)
+    }
+
+    // Sits at the given table. Blocks until the table is full.
+    fn join_table(&self, table_id: u32, body: JoinBody) -> ManagerResult<NewPartyInfo> {
+        let future = {
+            let mut tables = self.tables.lock().unwrap();
+            try!(self.check_accepting());
+            let seats_taken = match tables.get(&table_id) {
+                None => return Err(Error::BadTableId),
+                Some(table) => {
+                    if table.password.is_some() && table.password != body.password {
+                        return Err(Error::BadPassword);
+                    }
+                    table.seats.len()
+                }
+            };
+
+            if seats_taken == 3 {
+                // Last seat: let's play!
+                let table = tables.remove(&table_id).unwrap();
+                return Ok(self.start_table(table_id, table, body.name));
+            }
+
             let (promise, future) = Future::pair();
-            waiters.push(promise);
-            return Waiting(future);
+            tables.get_mut(&table_id).unwrap().seats.push((promise, body.name));
+            future
+        };
+
+        // Seats are only failed when the server shuts down.
+        future.await().map_err(|_| Error::ShuttingDown)
+    }
+
+    // Starts a party for a full table. Returns the last player's info.
+    fn start_table(&self, table_id: u32, table: OpenTable, name: Option<String>) -> NewPartyInfo {
+        let mut names: [Option<String>; 4] = Default::default();
+        for (i, &(_, ref seat_name)) in table.seats.iter().enumerate() {
+            names[i] = seat_name.clone();
+        }
+        names[3] = name;
+
+        let (_, ids) = self.new_party(Some(table_id),
+                                      PartySettings {
+                                          target: table.target.or(self.match_target),
+                                          variant: table.variant,
+                                          private: table.password.is_some(),
+                                          ranked: table.ranked,
+                                          names: names,
+                                          ..Default::default()
+                                      });
+
+        // Players sit in order of arrival.
+        for (i, (promise, _)) in table.seats.into_iter().enumerate() {
+            promise.complete(NewPartyInfo::new(ids[i], pos::PlayerPos::from_n(i)));
+        }
+
+        NewPartyInfo::new(ids[3], pos::PlayerPos::P3)
+    }
+
+    /// Lists the open tables and the running parties.
+    pub fn lobby(&self) -> Lobby {
+        let mut tables: Vec<TableInfo> = {
+            let mut open_tables = self.tables.lock().unwrap();
+            remove_empty_tables(&mut open_tables);
+            open_tables.iter().map(|(&id, table)| table_info(id, table)).collect()
+        };
+
+        {
+            let list = self.party_list.read().unwrap();
+            for (&id, party) in list.party_map.iter() {
+                let party = party.read().unwrap();
+                if party.over {
+                    continue;
+                }
+                tables.push(TableInfo {
+                    id: id,
+                    private: party.private,
+                    variant: party.variant.clone(),
+                    target: party.target,
+                    players: if party.private {
+                        vec![None; 4]
+                    } else {
+                        party.names.to_vec()
+                    },
+                    in_progress: true,
+                    ranked: party.ranked,
+                });
+            }
+        }
+
+        Lobby {
+            waiting: self.waiting_count(),
+            tables: tables,
+        }
+    }
+
+    fn make_waiter(&self, queue: &Queue, body: JoinBody) -> Waiter {
+        Waiter {
+            ticket: queue.make_ticket(),
+            rating: self.ratings.get(body.name.as_ref().map(|name| &name[..])),
+            criteria: Criteria::from(&body),
+            name: body.name,
+            promise: None,
+            last_time: time::now(),
+        }
+    }
+
+    fn get_join_result(&self, body: JoinBody) -> ManagerResult<JoinResult> {
+        let mut queue = self.waiting_list.lock().unwrap();
+        // Checked with the lock, so `begin_shutdown` can't miss us.
+        try!(self.check_accepting());
+        self.prune_queue(&mut queue);
+
+        let mut newcomer = self.make_waiter(&queue, body);
+        match queue.find_group(&newcomer) {
+            Some(mut players) => {
+                // It's a PARTEY!
+                let ticket = newcomer.ticket;
+                players.push(newcomer);
+                let info = self.make_party(&mut queue, players, Some(ticket)).unwrap();
+                self.metrics.set_waiting(queue.len());
+                Ok(Ready(info))
+            }
+            None => {
+                let (promise, future) = Future::pair();
+                newcomer.promise = Some(promise);
+                queue.push(newcomer);
+                self.metrics.set_waiting(queue.len());
+                Ok(Waiting(future))
+            }
         }
     }
 
-    fn make_party(&self, others: Vec<Complete<NewPartyInfo, ()>>) -> NewPartyInfo {
+    // Forgets about idle ticket holders, and cancels the parties of those who
+    // never took their seat. Then groups the waiters who were missed so far.
+    fn prune_queue(&self, queue: &mut Queue) {
+        let missing = queue.prune();
+        if !missing.is_empty() {
+            let mut list = self.party_list.write().unwrap();
+            for info in missing {
+                trace!("Player {} never showed up", info.player_id);
+                // The others may have left already.
+                let _ = list.remove(info.player_id, LEAVE_REASONS[2]);
+            }
+        }
+
+        for players in queue.find_groups() {
+            self.make_party(queue, players, None);
+        }
+    }
+
+    // Creates a party for the given players, and tells them where to sit.
+    // The newcomer with the given ticket, if any, gets his seat in return instead.
+    fn make_party(&self,
+                  queue: &mut Queue,
+                  mut players: Vec<Waiter>,
+                  newcomer: Option<u32>)
+                  -> Option<NewPartyInfo> {
+        players.sort_by(|a, b| b.rating.partial_cmp(&a.rating).unwrap());
+
+        let mut names: [Option<String>; 4] = Default::default();
+        for (i, player) in players.iter().enumerate() {
+            names[BALANCED_SEATS[i]] = player.name.clone();
+        }
+
+        // Every player agreed on the criteria they specified.
+        let target = players.iter().filter_map(|p| p.criteria.target).next();
+        let variant = players.iter().filter_map(|p| p.criteria.variant.clone()).next();
+
+        // Strangers playing for their rating: keep it fair.
+        let ranked = rating::is_rated(&names);
+        let (_, ids) = self.new_party(None,
+                                      PartySettings {
+                                          target: target.or(self.match_target),
+                                          variant: variant,
+                                          names: names,
+                                          ranked: ranked,
+                                          ..Default::default()
+                                      });
+
+        // Tell everyone. They'll love it.
+        // TODO: handle cancelled channels (?)
+        let mut result = None;
+        for (i, player) in players.into_iter().enumerate() {
+            let seat = BALANCED_SEATS[i];
+            let info = NewPartyInfo::new(ids[seat], pos::PlayerPos::from_n(seat));
+            if Some(player.ticket) == newcomer {
+                // Even you, weird 4th dude.
+                result = Some(info);
+            } else {
+                queue.notify(player, info);
+            }
+        }
+
+        result
+    }
+
+    /// Creates a party with the given settings, skipping matchmaking.
+    ///
+    /// Returns the party ID, and the info for each seat.
+    pub fn create_party(&self, settings: PartySettings) -> (u32, [NewPartyInfo; 4]) {
+        let (party_id, ids) = self.new_party(None, settings);
+
+        let infos = [NewPartyInfo::new(ids[0], pos::PlayerPos::P0),
+                     NewPartyInfo::new(ids[1], pos::PlayerPos::P1),
+                     NewPartyInfo::new(ids[2], pos::PlayerPos::P2),
+                     NewPartyInfo::new(ids[3], pos::PlayerPos::P3)];
+
+        (party_id, infos)
+    }
+
+    // Registers a new party and its players, using the given party ID if any.
+    // Returns the party ID and the player IDs.
+    fn new_party(&self, party_id: Option<u32>, settings: PartySettings) -> (u32, [u32; 4]) {
         let mut list = self.party_list.write().unwrap();
+        list.remove_ended();
 
         // Generate 4 new IDS
         let ids = list.make_ids();
+        let party_id = party_id.unwrap_or_else(|| list.make_party_id());
 
-        // println!("IDS: {:?}", ids);
-
-        let party = Arc::new(RwLock::new(Party::new(pos::PlayerPos::P0)));
         // Kickstart it with a new game!
+        let mut party = Party::new(pos::PlayerPos::P0, settings, self.metrics.clone());
+        party.last_deal = self.shutting_down.load(Ordering::SeqCst);
+        let party = Arc::new(RwLock::new(party));
 
         // Prepare the players info
         for i in 0..4 {
             list.player_map.insert(ids[i],
                                    PlayerInfo {
                                        party: party.clone(),
+                                       party_id: party_id,
                                        pos: pos::PlayerPos::from_n(i),
                                        last_time: Mutex::new(time::now()),
+                                       chat_times: Mutex::new(VecDeque::new()),
                                    });
         }
+        list.party_map.insert(party_id, party);
 
-        trace!("Party ready: {:?}", ids);
-
-        // Tell everyone. They'll love it.
-        // TODO: handle cancelled channels (?)
-        // println!("Waking them up!");
-        for (i, promise) in others.into_iter().enumerate() {
-            promise.complete(NewPartyInfo {
-                player_id: ids[i],
-                player_pos: pos::PlayerPos::from_n(i),
-            });
-        }
-
-        // println!("Almost ready!");
+        trace!("Party {} ready: {:?}", party_id, ids);
 
-        // Even you, weird 4th dude.
-        NewPartyInfo {
-            player_id: ids[3],
-            player_pos: pos::PlayerPos::P3,
-        }
+        (party_id, ids)
     }
 
     // Play a card in the current game
     pub fn play_card(&self, player_id: u32, card: CardBody) -> ManagerResult<Event> {
-        let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let (event, rated) = {
+            let list = self.party_list.read().unwrap();
+            let info = try!(list.get_player_info(player_id));
 
+            let mut party = info.party.write().unwrap();
+            let (event, winner) = try!(party.play_card(info.pos, card.card));
+            (event, winner.map(|winner| (party.names.clone(), winner)))
+        };
 
-        let mut party = info.party.write().unwrap();
-        party.play_card(info.pos, card.card)
+        // Did this card end the match? Saving the ratings may take a while:
+        // don't hold the locks meanwhile.
+        if let Some((names, winner)) = rated {
+            self.ratings.record_match(&names, winner);
+        }
 
+        Ok(event)
     }
 
     pub fn bid(&self, player_id: u32, contract: ContractBody) -> ManagerResult<Event> {
@@ -438,6 +1188,43 @@ impl GameManager {
         party.coinche(info.pos)
     }
 
+    /// Sends a chat message to the table, as a `Chat` event.
+    ///
+    /// Control characters are removed. Messages must be 1 to `MAX_CHAT_LENGTH`
+    /// characters long, and at most `CHAT_BURST` are accepted every `CHAT_PERIOD_SECONDS`.
+    /// Ranked parties refuse free chat: use `emote` instead.
+    pub fn chat(&self, player_id: u32, body: ChatBody) -> ManagerResult<()> {
+        let text: String = body.text.chars().filter(|c| !c.is_control()).collect();
+        let text = text.trim();
+        if text.is_empty() || text.chars().count() > MAX_CHAT_LENGTH {
+            return Err(Error::BadMessage);
+        }
+
+        let list = self.party_list.read().unwrap();
+        let info = try!(list.get_player_info(player_id));
+
+        let mut party = info.party.write().unwrap();
+        if party.ranked {
+            return Err(Error::ChatRestricted);
+        }
+        try!(info.check_chat_rate());
+        try!(party.chat(info.pos, text.to_string()));
+        Ok(())
+    }
+
+    /// Sends an emote to the table, as a `FromPlayer` event.
+    ///
+    /// Allowed in every party, within the same rate limit as the chat.
+    pub fn emote(&self, player_id: u32, body: EmoteBody) -> ManagerResult<()> {
+        let list = self.party_list.read().unwrap();
+        let info = try!(list.get_player_info(player_id));
+        try!(info.check_chat_rate());
+
+        let mut party = info.party.write().unwrap();
+        try!(party.emote(info.pos, body.emote));
+        Ok(())
+    }
+
     pub fn see_hand(&self, player_id: u32) -> ManagerResult<cards::Hand> {
         let list = self.party_list.read().unwrap();
         let info = try!(list.get_player_info(player_id));
@@ -490,7 +1277,97 @@ impl GameManager {
 
         trace!("Player leaving: {}", player_id);
 
-        try!(list.remove(player_id));
+        try!(list.remove(player_id, LEAVE_REASONS[0]));
+        list.remove_ended();
+
+        Ok(())
+    }
+
+    /// Lists every party, for the admin console.
+    pub fn admin_parties(&self) -> Vec<PartySummary> {
+        let list = self.party_list.read().unwrap();
+
+        list.party_map
+            .iter()
+            .map(|(&party_id, party)| {
+                let party = party.read().unwrap();
+                PartySummary {
+                    id: party_id,
+                    players: list.admin_players(party_id, &party),
+                    variant: party.variant.clone(),
+                    target: party.target,
+                    scores: party.scores,
+                    events: party.events.len(),
+                    over: party.over,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the full state of a party, including every hand.
+    pub fn admin_party(&self, party_id: u32) -> ManagerResult<PartyState> {
+        let list = self.party_list.read().unwrap();
+        let party = try!(list.get_party(party_id)).read().unwrap();
+
+        let (phase, hands, contract, trick) = match party.game {
+            Game::Bidding(ref auction) => ("auction", auction.hands(), None, None),
+            Game::Playing(ref game) => {
+                ("playing",
+                 game.hands(),
+                 Some(game.contract().clone()),
+                 Some(game.current_trick().clone()))
+            }
+        };
+
+        Ok(PartyState {
+            id: party_id,
+            players: list.admin_players(party_id, &party),
+            phase: (if party.over { "over" } else { phase }).to_string(),
+            next_player: if party.over { None } else { Some(party.game.next_player()) },
+            hands: hands,
+            contract: contract,
+            trick: trick,
+            scores: party.scores,
+        })
+    }
+
+    /// Returns every event of a party, as they happened (not relativized).
+    pub fn admin_events(&self, party_id: u32) -> ManagerResult<Vec<Event>> {
+        let list = self.party_list.read().unwrap();
+        let party = try!(list.get_party(party_id)).read().unwrap();
+
+        Ok(party.events
+                .iter()
+                .enumerate()
+                .map(|(id, event)| {
+                    Event {
+                        event: event.clone(),
+                        id: id,
+                    }
+                })
+                .collect())
+    }
+
+    /// Removes a player, cancelling his party.
+    pub fn kick(&self, player_id: u32) -> ManagerResult<()> {
+        let mut list = self.party_list.write().unwrap();
+
+        trace!("Kicking player: {}", player_id);
+
+        list.remove(player_id, LEAVE_REASONS[1])
+    }
+
+    /// Cancels a party. Players get a `PartyCancelled` event with the given message.
+    pub fn cancel_party(&self, party_id: u32, msg: &str) -> ManagerResult<()> {
+        let list = self.party_list.read().unwrap();
+        let mut party = try!(list.get_party(party_id)).write().unwrap();
+
+        trace!("Cancelling party: {}", party_id);
+
+        if party.over {
+            return Err(Error::PartyOver);
+        }
+        party.cancel(msg.to_string());
 
         Ok(())
     }
@@ -503,12 +1380,57 @@ impl GameManager {
 
         match res {
             Ready(event) => Ok(event),
-            // TODO: handle case where the wait is cancelled
-            // (don't unwrap, return an error instead?)
-            Waiting(future) => Ok(future.await().unwrap()),
+            // Observers are dropped with the party, once it is over and forgotten.
+            Waiting(future) => future.await().map_err(|_| Error::PartyOver),
         }
     }
 
+    /// Waits until the given event_id happens in the given party.
+    ///
+    /// This is meant for spectators: players hands are hidden, and
+    /// it never returns `YourTurn`. Private parties can't be watched.
+    pub fn watch(&self, party_id: u32, event_id: usize) -> ManagerResult<Event> {
+        let res = try!(self.get_watch_result(party_id, event_id));
+
+        match res {
+            Ready(event) => Ok(event),
+            Waiting(future) => {
+                // Observers are dropped with the party, once it is over and forgotten.
+                let event = try!(future.await().map_err(|_| Error::PartyOver));
+                Ok(Event {
+                    event: event.event.anonymize(),
+                    id: event.id,
+                })
+            }
+        }
+    }
+
+    fn get_watch_result(&self, party_id: u32, event_id: usize) -> ManagerResult<WaitResult> {
+        let list = self.party_list.read().unwrap();
+        let party = try!(list.get_party(party_id)).read().unwrap();
+        if party.private {
+            return Err(Error::BadPartyId);
+        }
+
+        if party.events.len() > event_id {
+            return Ok(Ready(Event {
+                event: party.events[event_id].anonymize(),
+                id: event_id,
+            }));
+        } else if event_id > party.events.len() {
+            return Err(Error::BadEventId);
+        } else if party.over {
+            // No more events will come.
+            return Err(Error::PartyOver);
+        }
+
+        let (promise, future) = Future::pair();
+        party.observers.lock().unwrap().push(promise);
+        self.metrics.add_observer();
+
+        Ok(Waiting(future))
+    }
+
     // Check if the event ID is already available.
     // If not, returns a channel that will produce it one
     // day, so that we don't keep the locks while waiting.
@@ -526,11 +1448,14 @@ impl GameManager {
         } else if event_id > party.events.len() {
             // We are too ambitious! One event at a time!
             return Err(Error::BadEventId);
+        } else if party.over {
+            // No more events will come.
+            return Err(Error::PartyOver);
         }
 
         // Ok, so we'll have to wait a bit.
         // ... maybe?
-        if info.pos == party.game.next_player() {
+        if !party.over && info.pos == party.game.next_player() {
             // If we're actually waiting for this guy, tell him!
             return Ok(Ready(Event {
                 event: EventType::YourTurn,
@@ -540,7 +1465,270 @@ impl GameManager {
 
         let (promise, future) = Future::pair();
         party.observers.lock().unwrap().push(promise);
+        self.metrics.add_observer();
 
         Ok(Waiting(future))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use eventual::Future;
+    use libcoinche::pos;
+    use time;
+
+    use {ChatBody, EventType, JoinBody, NewPartyInfo, NewTableBody};
+    use super::super::error::Error;
+    use super::{leaving_seat, remove_empty_tables, table_info, GameManager, OpenTable,
+                PartySettings, EMPTY_TABLE_SECONDS, MAX_TABLES_PER_CLIENT, SHUTDOWN_MESSAGE};
+
+    #[test]
+    fn leaving_seat_from_message() {
+        assert_eq!(leaving_seat("player left: 2"), Some(pos::PlayerPos::P2));
+        assert_eq!(leaving_seat("player kicked: 1"), Some(pos::PlayerPos::P1));
+        assert_eq!(leaving_seat("player missing: 3"), Some(pos::PlayerPos::P3));
+        assert_eq!(leaving_seat("player left: 4"), None);
+        assert_eq!(leaving_seat("closing time: 1"), None);
+        assert_eq!(leaving_seat(SHUTDOWN_MESSAGE), None);
+        assert_eq!(leaving_seat("all deals played"), None);
+    }
+
+    fn table(seats: usize, creator: IpAddr, created: i64) -> OpenTable {
+        OpenTable {
+            password: None,
+            variant: None,
+            target: None,
+            ranked: false,
+            seats: (0..seats).map(|_| (Future::pair().0, Some("alice".to_string()))).collect(),
+            creator: creator,
+            created: created,
+        }
+    }
+
+    #[test]
+    fn empty_tables_expire() {
+        let now = time::get_time().sec;
+        let client = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+
+        let mut tables = HashMap::new();
+        tables.insert(1, table(0, client, now));
+        tables.insert(2, table(0, client, now - EMPTY_TABLE_SECONDS - 1));
+        tables.insert(3, table(1, client, now - EMPTY_TABLE_SECONDS - 1));
+        remove_empty_tables(&mut tables);
+
+        let mut ids: Vec<u32> = tables.keys().cloned().collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[test]
+    fn private_tables_hide_players() {
+        let client = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let mut open = table(2, client, 0);
+        assert_eq!(table_info(1, &open).players,
+                   vec![Some("alice".to_string()), Some("alice".to_string())]);
+
+        open.password = Some("secret".to_string());
+        let info = table_info(1, &open);
+        assert!(info.private);
+        assert_eq!(info.players, vec![None, None]);
+    }
+
+    #[test]
+    fn tables_per_client() {
+        let manager = GameManager::new();
+        let alice = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let bob = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        for _ in 0..MAX_TABLES_PER_CLIENT {
+            assert!(manager.create_table(NewTableBody::default(), alice).is_ok());
+        }
+        match manager.create_table(NewTableBody::default(), alice) {
+            Err(Error::TooManyTables) => (),
+            _ => panic!("expected TooManyTables"),
+        }
+        assert!(manager.create_table(NewTableBody::default(), bob).is_ok());
+        assert_eq!(manager.lobby().tables.len(), MAX_TABLES_PER_CLIENT + 1);
+    }
+
+    fn is_shutting_down<T>(result: Result<T, Error>) -> bool {
+        match result {
+            Err(Error::ShuttingDown) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn shutdown_fails_waiters() {
+        let manager = Arc::new(GameManager::new());
+        let client = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let table = manager.create_table(NewTableBody::default(), client).ok().unwrap();
+
+        let at_table = {
+            let manager = manager.clone();
+            thread::spawn(move || {
+                manager.join(JoinBody { table: Some(table.id), ..Default::default() })
+            })
+        };
+        let in_queue = {
+            let manager = manager.clone();
+            thread::spawn(move || manager.join(JoinBody::default()))
+        };
+        let ticket = manager.enqueue(JoinBody::default()).ok().unwrap().ticket;
+
+        // Wait until everyone is seated or queued.
+        while manager.waiting_count() < 2 ||
+              manager.lobby().tables.iter().all(|table| table.players.is_empty()) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        manager.begin_shutdown();
+        assert!(is_shutting_down(at_table.join().unwrap()));
+        assert!(is_shutting_down(in_queue.join().unwrap()));
+        assert!(is_shutting_down(manager.queue_status(ticket)));
+        assert_eq!(manager.waiting_count(), 0);
+        assert!(manager.lobby().tables.is_empty());
+
+        // Newcomers are refused.
+        assert!(is_shutting_down(manager.join(JoinBody::default())));
+        assert!(is_shutting_down(manager.enqueue(JoinBody::default())));
+        assert!(is_shutting_down(manager.create_table(NewTableBody::default(), client)));
+    }
+
+    #[test]
+    fn spectators() {
+        let manager = GameManager::new();
+        let (party_id, ids) = manager.new_party(None, PartySettings::default());
+        match manager.watch(party_id, 0).ok().unwrap().event {
+            EventType::NewGameRelative { .. } => (),
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        manager.chat(ids[1], ChatBody { text: "Hello!".to_string() }).ok().unwrap();
+        let last = manager.admin_events(party_id).ok().unwrap().len() - 1;
+        match manager.watch(party_id, last).ok().unwrap().event {
+            EventType::Chat { from, text } => {
+                assert_eq!(from, pos::PlayerPos::P1);
+                assert_eq!(text, "Hello!");
+            }
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        let settings = PartySettings { private: true, ..Default::default() };
+        let (private_id, _) = manager.new_party(None, settings);
+        assert!(match manager.watch(private_id, 0) {
+            Err(Error::BadPartyId) => true,
+            _ => false,
+        });
+    }
+
+    fn can_chat(manager: &GameManager, player_id: u32) -> bool {
+        manager.chat(player_id, ChatBody { text: "Hi!".to_string() }).is_ok()
+    }
+
+    // Enqueues the given players. Returns the party of the last one.
+    fn matched(manager: &GameManager, players: Vec<JoinBody>) -> NewPartyInfo {
+        let mut last = None;
+        for body in players {
+            last = manager.enqueue(body).ok().unwrap().party;
+        }
+        last.unwrap()
+    }
+
+    #[test]
+    fn rated_parties_are_ranked() {
+        let manager = GameManager::new();
+        let anonymous = matched(&manager, vec![JoinBody::default(); 4]);
+        assert!(can_chat(&manager, anonymous.player_id));
+
+        let mut players = vec![JoinBody::default(); 4];
+        players[0].name = Some("alice".to_string());
+        let rated = matched(&manager, players);
+        assert!(!can_chat(&manager, rated.player_id));
+
+        // Tournaments ask for it.
+        let (_, infos) = manager.create_party(PartySettings { ranked: true, ..Default::default() });
+        assert!(!can_chat(&manager, infos[0].player_id));
+
+        // Tables follow what their creator asked for, named players or not.
+        let names = [Some("alice".to_string()), None, None, None];
+        let settings = PartySettings { names: names, ..Default::default() };
+        let (_, infos) = manager.create_party(settings);
+        assert!(can_chat(&manager, infos[0].player_id));
+        let client = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        assert!(!table_info(1, &table(1, client, 0)).ranked);
+    }
+
+    fn is_party_over<T>(result: Result<T, Error>) -> bool {
+        match result {
+            Err(Error::PartyOver) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn waiting_on_ended_parties() {
+        let manager = Arc::new(GameManager::new());
+        let (party_id, infos) = manager.create_party(PartySettings::default());
+        let next = manager.admin_events(party_id).ok().unwrap().len();
+
+        // P1 is not the first to play: it waits for the next event.
+        let waiter = {
+            let manager = manager.clone();
+            let player_id = infos[1].player_id;
+            thread::spawn(move || manager.wait(player_id, next))
+        };
+        let party = manager.party_list.read().unwrap().party_map[&party_id].clone();
+        while party.read().unwrap().observers.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The party ends without a last event, and is forgotten.
+        party.write().unwrap().end();
+        party.write().unwrap().ended = Some(0);
+        drop(party);
+        manager.party_list.write().unwrap().remove_ended();
+        assert!(is_party_over(waiter.join().unwrap()));
+
+        // Once over, no more events will come.
+        let (party_id, infos) = manager.create_party(PartySettings::default());
+        manager.cancel_all("closing time");
+        let next = manager.admin_events(party_id).ok().unwrap().len();
+        assert!(is_party_over(manager.wait(infos[1].player_id, next)));
+    }
+
+    #[test]
+    fn watching_ended_parties() {
+        let manager = Arc::new(GameManager::new());
+        let (party_id, _) = manager.create_party(PartySettings::default());
+        let next = manager.admin_events(party_id).ok().unwrap().len();
+
+        let spectator = {
+            let manager = manager.clone();
+            thread::spawn(move || manager.watch(party_id, next))
+        };
+        let party = manager.party_list.read().unwrap().party_map[&party_id].clone();
+        while party.read().unwrap().observers.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The party ends without a last event, and is forgotten.
+        party.write().unwrap().end();
+        party.write().unwrap().ended = Some(0);
+        drop(party);
+        manager.party_list.write().unwrap().remove_ended();
+        assert!(is_party_over(spectator.join().unwrap()));
+
+        // Once over, no more events will come.
+        let (party_id, _) = manager.create_party(PartySettings::default());
+        manager.cancel_all("closing time");
+        let next = manager.admin_events(party_id).ok().unwrap().len();
+        assert!(is_party_over(manager.watch(party_id, next)));
+    }
+}
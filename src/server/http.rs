@@ -1,16 +1,42 @@
-use super::game_manager::GameManager;
-use {ContractBody, CardBody, Error};
+use super::game_manager::{GameManager, SHUTDOWN_MESSAGE};
+use super::admin::AdminRouter;
+use super::cors::Cors;
+use super::limits::{Limiter, Limits, Refusal};
+use super::metrics::Metrics;
+use super::openapi;
+use super::routes::{self, Lookup, Route, JSON};
+use super::rating::Ratings;
+use super::readable;
+use super::tournament::DuplicateTournament;
+use super::scheduler::{Format, Scheduler};
+use super::tcp;
+use super::error;
+use super::web;
+use {ContractBody, CardBody, ChatBody, EmoteBody, JoinBody, NewTableBody, RegisterBody};
+use {Error, API_PREFIX};
 
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 
-use rustc_serialize::json;
+use chan_signal::{self, Signal};
+use num_cpus;
+use rustc_serialize::{json, Decodable, Encodable};
+use time;
 use iron::prelude::*;
 use iron;
 use bodyparser;
 
-struct Router {
+/// Serves the API. See `ROUTES`.
+pub struct Router {
     manager: Arc<GameManager>,
+    metrics: Arc<Metrics>,
+    duplicate: Option<Arc<DuplicateTournament>>,
+    scheduler: Option<Arc<Scheduler>>,
+    cors: Option<Cors>,
+    limiter: Arc<Limiter>,
 }
 
 #[derive(RustcEncodable)]
@@ -23,163 +49,554 @@ struct HelpAction {
 #[derive(RustcEncodable)]
 struct HelpMessage {
     title: &'static str,
+    /// Prefix for every action, like `/v1/join`.
+    prefix: String,
     actions: Vec<HelpAction>,
 }
 
 pub struct Server {
+    address: String,
     port: u16,
-    manager: Arc<GameManager>,
+    // Number of threads serving requests, if not iron's default
+    threads: Option<usize>,
+    // Certificate and key files, to serve HTTPS
+    tls: Option<(PathBuf, PathBuf)>,
+    manager: GameManager,
+    // Number of pairs and deals
+    duplicate: Option<(usize, usize)>,
+    // Format, number of teams and target score
+    tournament: Option<(Format, usize, i32)>,
+    // Admin address, port and token
+    admin: Option<(String, u16, String)>,
+    // Port for the binary protocol
+    tcp_port: Option<u16>,
+    // Time given to the running deals on shutdown, in seconds
+    shutdown_deadline: u64,
+    // Time to read a request, and to write a response, in seconds. 0 disables them
+    http_timeouts: (u64, u64),
+    cors: Option<Cors>,
+    limits: Limits,
 }
 
+/// Default time to read a request, or write a response, in seconds.
+pub const HTTP_TIMEOUT_SECONDS: u64 = 30;
+
+/// Every route served by the API.
+pub static ROUTES: [Route; 31] = [
+    Route {
+        method: "GET",
+        href: "/",
+        help: "Play in the browser.",
+        tag: "Public",
+        body: None,
+        content_type: "text/html",
+        response: None,
+        blocking: false,
+        handler: Router::index,
+    },
+    Route {
+        method: "GET",
+        href: "/static/[FILE]",
+        help: "Get a file used by the web client.",
+        tag: "Public",
+        body: None,
+        content_type: "*/*",
+        response: None,
+        blocking: false,
+        handler: Router::static_file,
+    },
+    Route {
+        method: "GET",
+        href: "/help",
+        help: "Get this help message.",
+        tag: "Public",
+        body: None,
+        content_type: JSON,
+        response: Some("HelpMessage"),
+        blocking: false,
+        handler: Router::help,
+    },
+    Route {
+        method: "GET",
+        href: "/openapi.json",
+        help: "Get the OpenAPI description of this API.",
+        tag: "Public",
+        body: None,
+        content_type: JSON,
+        response: None,
+        blocking: false,
+        handler: Router::openapi,
+    },
+    Route {
+        method: "POST",
+        href: "/join",
+        help: "Join a new game.",
+        tag: "Public",
+        body: Some("JoinBody"),
+        content_type: JSON,
+        response: Some("NewPartyInfo"),
+        blocking: true,
+        handler: Router::join,
+    },
+    Route {
+        method: "GET",
+        href: "/lobby",
+        help: "List the open tables and running parties.",
+        tag: "Public",
+        body: None,
+        content_type: JSON,
+        response: Some("Lobby"),
+        blocking: false,
+        handler: Router::lobby,
+    },
+    Route {
+        method: "GET",
+        href: "/watch/[PARTY_ID]/[EVENT_ID]",
+        help: "Watch a party: wait until the next event, without the players' hands.",
+        tag: "Public",
+        body: None,
+        content_type: JSON,
+        response: Some("Event"),
+        blocking: true,
+        handler: Router::watch,
+    },
+    Route {
+        method: "POST",
+        href: "/tables",
+        help: "Create a new table.",
+        tag: "Public",
+        body: Some("NewTableBody"),
+        content_type: JSON,
+        response: Some("TableInfo"),
+        blocking: false,
+        handler: Router::create_table,
+    },
+    Route {
+        method: "POST",
+        href: "/queue",
+        help: "Enter the matchmaking queue without waiting.",
+        tag: "Public",
+        body: Some("JoinBody"),
+        content_type: JSON,
+        response: Some("QueueStatus"),
+        blocking: false,
+        handler: Router::enqueue,
+    },
+    Route {
+        method: "GET",
+        href: "/queue/[TICKET]",
+        help: "Get the queue position, or the party once found.",
+        tag: "Public",
+        body: None,
+        content_type: JSON,
+        response: Some("QueueStatus"),
+        blocking: false,
+        handler: Router::queue_status,
+    },
+    Route {
+        method: "GET",
+        href: "/ratings",
+        help: "Get the players ratings.",
+        tag: "Public",
+        body: None,
+        content_type: JSON,
+        response: Some("Ratings"),
+        blocking: false,
+        handler: Router::ratings,
+    },
+    Route {
+        method: "GET",
+        href: "/metrics",
+        help: "Get the server metrics, in the Prometheus text format.",
+        tag: "Public",
+        body: None,
+        content_type: "text/plain; version=0.0.4",
+        response: None,
+        blocking: false,
+        handler: Router::metrics,
+    },
+    Route {
+        method: "GET",
+        href: "/wait/[PLAYER_ID]/[EVENT_ID]",
+        help: "Wait until the next event, or return it if it already happened.",
+        tag: "General",
+        body: None,
+        content_type: JSON,
+        response: Some("Event"),
+        blocking: true,
+        handler: Router::wait,
+    },
+    Route {
+        method: "POST",
+        href: "/leave/[PLAYER_ID]",
+        help: "Leave the current game.",
+        tag: "General",
+        body: None,
+        content_type: JSON,
+        response: Some("Ok"),
+        blocking: false,
+        handler: Router::leave,
+    },
+    Route {
+        method: "GET",
+        href: "/hand/[PLAYER_ID]",
+        help: "Checks the current hand.",
+        tag: "General",
+        body: None,
+        content_type: JSON,
+        response: Some("Hand"),
+        blocking: false,
+        handler: Router::hand,
+    },
+    Route {
+        method: "GET",
+        href: "/scores/[PLAYER_ID]",
+        help: "Get the current scores.",
+        tag: "General",
+        body: None,
+        content_type: JSON,
+        response: Some("Scores"),
+        blocking: false,
+        handler: Router::scores,
+    },
+    Route {
+        method: "GET",
+        href: "/pos/[PLAYER_ID]",
+        help: "Get the player's position on the table.",
+        tag: "General",
+        body: None,
+        content_type: JSON,
+        response: Some("PlayerPos"),
+        blocking: false,
+        handler: Router::pos,
+    },
+    Route {
+        method: "POST",
+        href: "/chat/[PLAYER_ID]",
+        help: "Send a message to the table.",
+        tag: "General",
+        body: Some("ChatBody"),
+        content_type: JSON,
+        response: Some("Ok"),
+        blocking: false,
+        handler: Router::chat,
+    },
+    Route {
+        method: "POST",
+        href: "/emote/[PLAYER_ID]",
+        help: "Send a quick message to the table.",
+        tag: "General",
+        body: Some("EmoteBody"),
+        content_type: JSON,
+        response: Some("Ok"),
+        blocking: false,
+        handler: Router::emote,
+    },
+    Route {
+        method: "POST",
+        href: "/pass/[PLAYER_ID]",
+        help: "Pass during auction.",
+        tag: "Auction",
+        body: None,
+        content_type: JSON,
+        response: Some("Event"),
+        blocking: false,
+        handler: Router::pass,
+    },
+    Route {
+        method: "POST",
+        href: "/coinche/[PLAYER_ID]",
+        help: "Coinche the opponent's bid during auction.",
+        tag: "Auction",
+        body: None,
+        content_type: JSON,
+        response: Some("Event"),
+        blocking: false,
+        handler: Router::coinche,
+    },
+    Route {
+        method: "POST",
+        href: "/bid/[PLAYER_ID]",
+        help: "Bid a contract during auction.",
+        tag: "Auction",
+        body: Some("ContractBody"),
+        content_type: JSON,
+        response: Some("Event"),
+        blocking: false,
+        handler: Router::bid,
+    },
+    Route {
+        method: "POST",
+        href: "/play/[PLAYER_ID]",
+        help: "Play a card.",
+        tag: "Game",
+        body: Some("CardBody"),
+        content_type: JSON,
+        response: Some("Event"),
+        blocking: false,
+        handler: Router::play,
+    },
+    Route {
+        method: "GET",
+        href: "/trick/[PLAYER_ID]",
+        help: "Checks the current trick.",
+        tag: "Game",
+        body: None,
+        content_type: JSON,
+        response: Some("Trick"),
+        blocking: false,
+        handler: Router::trick,
+    },
+    Route {
+        method: "GET",
+        href: "/last_trick/[PLAYER_ID]",
+        help: "Checks the last complete trick.",
+        tag: "Game",
+        body: None,
+        content_type: JSON,
+        response: Some("Trick"),
+        blocking: false,
+        handler: Router::last_trick,
+    },
+    Route {
+        method: "POST",
+        href: "/duplicate/join",
+        help: "Join the duplicate tournament.",
+        tag: "Duplicate",
+        body: None,
+        content_type: JSON,
+        response: Some("Registration"),
+        blocking: false,
+        handler: Router::duplicate_join,
+    },
+    Route {
+        method: "POST",
+        href: "/duplicate/next/[TICKET]",
+        help: "Wait for the next duplicate round.",
+        tag: "Duplicate",
+        body: None,
+        content_type: JSON,
+        response: Some("NewPartyInfo"),
+        blocking: true,
+        handler: Router::duplicate_next,
+    },
+    Route {
+        method: "GET",
+        href: "/duplicate/leaderboard",
+        help: "Get the duplicate tournament leaderboard.",
+        tag: "Duplicate",
+        body: None,
+        content_type: JSON,
+        response: Some("Leaderboard"),
+        blocking: false,
+        handler: Router::leaderboard,
+    },
+    Route {
+        method: "POST",
+        href: "/tournament/register",
+        help: "Register in a tournament team.",
+        tag: "Tournament",
+        body: Some("RegisterBody"),
+        content_type: JSON,
+        response: Some("Registration"),
+        blocking: false,
+        handler: Router::register,
+    },
+    Route {
+        method: "POST",
+        href: "/tournament/next/[TICKET]",
+        help: "Wait for the next tournament match.",
+        tag: "Tournament",
+        body: None,
+        content_type: JSON,
+        response: Some("NewPartyInfo"),
+        blocking: true,
+        handler: Router::next_match,
+    },
+    Route {
+        method: "GET",
+        href: "/tournament/standings",
+        help: "Get the tournament standings.",
+        tag: "Tournament",
+        body: None,
+        content_type: JSON,
+        response: Some("Standings"),
+        blocking: false,
+        handler: Router::standings,
+    },
+];
+
 fn help_message() -> String {
 
     json::encode(&HelpMessage {
         title: "Help Page",
-        actions: vec![
-            HelpAction {
-                href: "/join",
-                method: "POST",
-                help: "Join a new game.",
-            },
-            HelpAction {
-                href: "/leave/[PLAYER_ID]",
-                method: "POST",
-                help: "Leave the current game.",
-            },
-            HelpAction {
-                href: "/pass/[PLAYER_ID]",
-                method: "POST",
-                help: "Pass during auction.",
-            },
-            HelpAction {
-                href: "/coinche/[PLAYER_ID]",
-                method: "POST",
-                help: "Coinche the opponent's bid during auction.",
-            },
-            HelpAction {
-                href: "/bid/[PLAYER_ID]",
-                method: "POST",
-                help: "Bid a contract during auction.",
-            },
-            HelpAction {
-                href: "/play/[PLAYER_ID]",
-                method: "POST",
-                help: "Play a card.",
-            },
-            HelpAction {
-                href: "/hand/[PLAYER_ID]",
-                method: "GET",
-                help: "Checks the current hand.",
-            },
-            HelpAction {
-                href: "/trick/[PLAYER_ID]",
-                method: "GET",
-                help: "Checks the current trick.",
-            },
-            HelpAction {
-                href: "/last_trick/[PLAYER_ID]",
-                method: "GET",
-                help: "Checks the last complete trick.",
-            },
-            HelpAction {
-                href: "/scores/[PLAYER_ID]",
-                method: "GET",
-                help: "Get the current scores.",
-            },
-            HelpAction {
-                href: "/pos/[PLAYER_ID]",
-                method: "GET",
-                help: "Get the player's position on the table.",
-            },
-            HelpAction {
-                href: "/wait/[PLAYER_ID]/[EVENT_ID]",
-                method: "GET",
-                help: "Wait until the next event, or return it if it already happened.",
-            },
-        ],
+        prefix: format!("/{}", API_PREFIX),
+        actions: ROUTES.iter()
+                       .map(|route| {
+                           HelpAction {
+                               href: route.href,
+                               method: route.method,
+                               help: route.help,
+                           }
+                       })
+                       .collect(),
     })
         .unwrap()
 }
 
+fn content_type(mime: &str) -> iron::mime::Mime {
+    mime.parse::<iron::mime::Mime>().unwrap()
+}
 
+// Unknown routes get the help message, with a 404.
 fn help_resp() -> IronResult<Response> {
-    let content_type: iron::mime::Mime = "application/json".parse::<iron::mime::Mime>().unwrap();
-    return Ok(Response::with((content_type, iron::status::NotFound, help_message())));
+    Ok(Response::with((content_type(JSON), iron::status::NotFound, help_message())))
 }
 
-fn err_resp<S: ToString>(msg: S) -> IronResult<Response> {
-    let content_type: iron::mime::Mime = "application/json".parse::<iron::mime::Mime>().unwrap();
+fn json_resp<T: Encodable>(value: &T) -> IronResult<Response> {
+    Ok(Response::with((content_type(JSON), iron::status::Ok, json::encode(value).unwrap())))
+}
 
-    return Ok(Response::with((content_type,
-                              iron::status::Ok,
-                              json::encode(&Error { error: msg.to_string() }).unwrap())));
+fn err_resp<S: ToString>(status: iron::status::Status,
+                          code: &str,
+                          msg: S)
+                          -> IronResult<Response> {
+    Ok(Response::with((content_type(JSON),
+                       status,
+                       json::encode(&Error {
+                           code: code.to_string(),
+                           error: msg.to_string(),
+                       })
+                           .unwrap())))
 }
 
-macro_rules! parse_id {
-    ( $name:expr, $value:expr ) => {
-        {
-            match u32::from_str($value) {
-                Ok(id) => id,
-                Err(e) => return err_resp(format!("invalid {} ID: `{}` ({})", $name, $value, e)),
-            }
-        }
+// Requests over the limits: banned clients get a 403, others a 429.
+fn refusal_resp(refusal: Refusal) -> IronResult<Response> {
+    let status = match refusal {
+        Refusal::Banned(_) => iron::status::Forbidden,
+        _ => iron::status::TooManyRequests,
     };
+    err_resp(status, refusal.code(), refusal)
 }
 
-macro_rules! check_len {
-    ( $path:expr, 1 ) => {
-        {
-            if $path.len() != 1 {
-                return err_resp(format!("incorrect parameters (Usage: /{})", $path[0]));
-            }
-        }
-    };
-    ( $path:expr, 2 ) => {
-        {
-            if $path.len() != 2 {
-                return err_resp(format!("incorrect parameters (Usage: /{}/[PID])", $path[0]));
-            }
-        }
+// Malformed requests: bad IDs, wrong number of parameters, invalid bodies...
+fn bad_request<S: ToString>(code: &str, msg: S) -> IronResult<Response> {
+    err_resp(iron::status::BadRequest, code, msg)
+}
+
+/// Returns the HTTP status matching the given error.
+pub fn error_status(err: &error::Error) -> iron::status::Status {
+    match err {
+        &error::Error::BadPlayerId |
+        &error::Error::BadEventId |
+        &error::Error::BadPartyId |
+        &error::Error::BadTableId |
+        &error::Error::BadTicket |
+        &error::Error::NoTournament => iron::status::NotFound,
+        &error::Error::BadPassword |
+        &error::Error::ChatRestricted => iron::status::Forbidden,
+        &error::Error::QueueTable |
+        &error::Error::BadMessage => iron::status::BadRequest,
+        &error::Error::TournamentFull |
+        &error::Error::TournamentOver |
+        &error::Error::PartyOver |
+        &error::Error::PlayInAuction |
+        &error::Error::BidInGame |
+        &error::Error::NoLastTrick => iron::status::Conflict,
+        &error::Error::Bid(_) |
+        &error::Error::Play(_) => iron::status::UnprocessableEntity,
+        &error::Error::TooManyTables |
+        &error::Error::ChatTooFast => iron::status::TooManyRequests,
+        &error::Error::ShuttingDown => iron::status::ServiceUnavailable,
+    }
+}
+
+/// Returns the given error, with the matching status and code.
+pub fn error_resp(err: &error::Error) -> IronResult<Response> {
+    err_resp(error_status(err), &err.code(), err)
+}
+
+// Reads the JSON body. Cards and suits may use the readable encoding.
+fn parse_body<T: Decodable>(req: &mut Request) -> Result<Option<T>, String> {
+    let json = match req.get::<bodyparser::Json>() {
+        Ok(Some(json)) => json,
+        Ok(None) => return Ok(None),
+        Err(err) => return Err(format!("{:?}", err)),
     };
-    ( $path:expr, 3 ) => {
+    let json = readable::from_readable(None, json);
+    match Decodable::decode(&mut json::Decoder::new(json)) {
+        Ok(body) => Ok(Some(body)),
+        Err(err) => Err(format!("{:?}", err)),
+    }
+}
+
+macro_rules! read_body {
+    ( $x:expr, $name:expr ) => {
         {
-            if $path.len() != 3 {
-                return err_resp(format!(
-                        "incorrect parameters (Usage: /{}/[PID]/[EID])",
-                        $path[0]));
+            match $x {
+                Ok(Some(thing)) => thing,
+                Ok(None) => return bad_request("missing_body",
+                                               format!("body expected: {}", $name)),
+                Err(err) => return bad_request("invalid_body",
+                                               format!("Error parsing {}: {}", $name, err)),
             }
         }
     };
 }
 
-macro_rules! my_try {
-    ( $x:expr ) => {
-
+// Same as read_body, but uses the default value if the body is missing.
+macro_rules! read_optional_body {
+    ( $x:expr, $name:expr ) => {
         {
             match $x {
-                Err(err) => return err_resp(format!("{}", err)),
-                Ok(thing) => thing,
+                Ok(Some(thing)) => thing,
+                Ok(None) => Default::default(),
+                Err(err) => return bad_request("invalid_body",
+                                               format!("Error parsing {}: {}", $name, err)),
             }
         }
     };
 }
 
-macro_rules! try_manager {
-    ( $call:expr ) => {
-        json::encode(&my_try!($call)).unwrap()
-    };
+// Removes the version prefix from the path.
+//
+// Unversioned paths are still served, for older clients.
+// Returns the version if it is not supported.
+fn strip_version(path: &mut Vec<String>) -> Result<(), String> {
+    if path[0] == API_PREFIX {
+        path.remove(0);
+        if path.is_empty() {
+            // Like iron does for `/`.
+            path.push(String::new());
+        }
+        Ok(())
+    } else if path[0].starts_with('v') && u32::from_str(&path[0][1..]).is_ok() {
+        Err(path[0].clone())
+    } else {
+        Ok(())
+    }
 }
 
-macro_rules! read_body {
-    ( $x:expr, $name:expr ) => {
-        {
-            match $x {
-                Ok(Some(thing)) => thing,
-                Ok(None) => return err_resp(format!("body expected: {}", $name)),
-                Err(err) => return err_resp(format!("Error parsing {}: {:?}", $name, err)),
+// Parses the IDs in the path, in order.
+fn parse_ids(route: &Route, path: &[String]) -> Result<Vec<u32>, IronResult<Response>> {
+    let mut ids = Vec::new();
+    let values = path.iter().skip(path.len() - route.params().len());
+    for (param, value) in route.params().into_iter().zip(values) {
+        if !routes::is_id(param) {
+            continue;
+        }
+        match u32::from_str(value) {
+            Ok(id) => ids.push(id),
+            Err(e) => {
+                return Err(bad_request("invalid_id",
+                                       format!("invalid {}: `{}` ({})",
+                                               param.to_lowercase(),
+                                               value,
+                                               e)))
             }
         }
-    };
+    }
+    Ok(ids)
 }
 
 impl iron::Handler for Router {
@@ -191,165 +608,565 @@ impl iron::Handler for Router {
             panic!("Empty request path should never happen.");
         }
 
+        trace!("Request: {:?}", req);
+        let start = time::precise_time_ns();
+        let ip = req.remote_addr.ip();
+        let (name, mut response) = if let Err(refusal) = self.limiter.check_ip(ip) {
+            ("other".to_string(), refusal_resp(refusal))
+        } else if let Err(version) = strip_version(&mut req.url.path) {
+            let msg = format!("unsupported API version: `{}` (this server speaks {})",
+                              version,
+                              API_PREFIX);
+            ("other".to_string(),
+             err_resp(iron::status::NotFound, "unsupported_version", msg))
+        } else if req.method == iron::method::Options {
+            ("OPTIONS".to_string(), self.options(req))
+        } else {
+            match routes::lookup(&ROUTES, &req.method.to_string(), &req.url.path) {
+                Lookup::Found(route) => {
+                    let response = match parse_ids(route, &req.url.path) {
+                        Ok(ids) => self.call(route, req, &ids),
+                        Err(response) => response,
+                    };
+                    (route.name(), response)
+                }
+                Lookup::BadParameters(route) => {
+                    let msg = format!("incorrect parameters (Usage: {})", route.href);
+                    (route.name(), bad_request("bad_parameters", msg))
+                }
+                Lookup::NotFound => {
+                    trace!("Requesting invalid path: {} {:?}", req.method, &req.url.path);
+                    ("other".to_string(), help_resp())
+                }
+            }
+        };
+        let micros = (time::precise_time_ns() - start) / 1000;
+        self.metrics.record_request(&name, micros as usize);
 
-        // Weird deref trick to go from &String to &str
+        if let Some(ref cors) = self.cors {
+            match response {
+                Ok(ref mut res) => cors.apply(req, res),
+                Err(ref mut err) => cors.apply(req, &mut err.response),
+            }
+        }
 
-        trace!("Building ContentType");
-        let content_type: iron::mime::Mime = "application/json"
-                                                 .parse::<iron::mime::Mime>()
-                                                 .unwrap();
+        response
+    }
+}
 
-        trace!("Request: {:?}", req);
-        match req.method {
-            iron::method::Options => {
-                let action = &*req.url.path[0];
-                if ["hand", "trick", "last_trick", "scores", "pos"].contains(&action) {
-                    Ok(Response::with((iron::modifiers::Header(iron::headers::Allow(vec![
-                                                   iron::method::Get,
-                                                   iron::method::Options])),
-                                       iron::status::Ok)))
-                } else if ["pass", "coinche", "bid", "play", "join", "leave"].contains(&action) {
-                    Ok(Response::with((iron::modifiers::Header(iron::headers::Allow(vec![
-                                                   iron::method::Post,
-                                                   iron::method::Options])),
-                                       iron::status::Ok)))
-                } else {
-                    help_resp()
-                }
+impl Router {
+    // Lists the methods allowed on the path.
+    fn options(&self, req: &mut Request) -> IronResult<Response> {
+        let mut methods: Vec<iron::method::Method> =
+            routes::allowed(&ROUTES, &req.url.path)
+                .into_iter()
+                .map(|method| method.parse().unwrap())
+                .collect();
+        if methods.is_empty() {
+            return help_resp();
+        }
+        methods.push(iron::method::Options);
+
+        Ok(Response::with((iron::modifiers::Header(iron::headers::Allow(methods)),
+                           iron::status::Ok)))
+    }
+
+    // Calls the route handler, within the limits.
+    fn call(&self, route: &Route, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        if route.params().first() == Some(&"PLAYER_ID") {
+            if let Err(refusal) = self.limiter.check_player(ids[0]) {
+                return refusal_resp(refusal);
             }
-            iron::method::Get => {
-                let response = match &*req.url.path[0] {
-                    "wait" => {
-                        check_len!(req.url.path, 3);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        let event_id = parse_id!("event", &*req.url.path[2]) as usize;
-                        // Result is an Event
-                        try_manager!(self.manager.wait(player_id, event_id))
-                    }
-                    "hand" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        // Result is a cards::Hand = u32
-                        try_manager!(self.manager.see_hand(player_id))
-                    }
-                    "trick" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        // Result is a trick::Trick
-                        try_manager!(self.manager.see_trick(player_id))
-                    }
-                    "last_trick" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        // Result is a trick::Trick
-                        try_manager!(self.manager.see_last_trick(player_id))
-                    }
-                    "scores" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        // Result is a [i32; 2]
-                        try_manager!(self.manager.see_scores(player_id))
-                    }
-                    "pos" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        // Result is a pos::PlayerPos = usize
-                        try_manager!(self.manager.see_pos(player_id))
-                    }
-                    _ => {
-                        trace!("Requesting invalid path: GET {:?}", &req.url.path);
-                        return help_resp();
-                    }
-                };
-
-                Ok(Response::with((content_type, iron::status::Ok, response)))
+        }
 
+        let _pending = if route.blocking {
+            match self.limiter.begin_pending(req.remote_addr.ip()) {
+                Ok(pending) => Some(pending),
+                Err(refusal) => return refusal_resp(refusal),
             }
-            iron::method::Post => {
-                // Read the JSON body
-                // ...
-
-                let response = match &*req.url.path[0] {
-                    "join" => {
-                        check_len!(req.url.path, 1);
-                        // Result is a NewPartyInfo
-                        try_manager!(self.manager.join())
-                    }
-                    "leave" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        my_try!(self.manager.leave(player_id));
-                        // Result is a string - but who cares?
-                        r#""ok""#.to_string()
-                    }
-                    "pass" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        // Result is an event
-                        try_manager!(self.manager.pass(player_id))
-                    }
-                    "coinche" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        // Result is an event
-                        try_manager!(self.manager.coinche(player_id))
-                    }
-                    "bid" => {
-                        trace!("Request: POST /bid");
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        trace!("bid from {}", player_id);
-                        // Parse the body
-
-                        let contract = read_body!(req.get::<bodyparser::Struct<ContractBody>>(),
-                                                  "contract");
-                        trace!("Bidding {:?}", contract);
-                        // Result is an event
-                        try_manager!(self.manager.bid(player_id, contract))
-                    }
-                    "play" => {
-                        check_len!(req.url.path, 2);
-                        let player_id = parse_id!("player", &*req.url.path[1]);
-                        // Parse the body
-                        let card = read_body!(req.get::<bodyparser::Struct<CardBody>>(), "card");
-
-                        // Result is an event
-                        try_manager!(self.manager.play_card(player_id, card))
-                    }
-                    _ => {
-                        trace!("Requesting invalid path: POST {:?}", &req.url.path);
-                        return help_resp();
-                    }
-                };
-
-                Ok(Response::with((content_type, iron::status::Ok, response)))
+        } else {
+            None
+        };
+
+        (route.handler)(self, req, ids)
+    }
+
+    // Encodes the result, or returns the error after counting it.
+    fn reply<T: Encodable>(&self,
+                           req: &Request,
+                           result: Result<T, error::Error>)
+                           -> IronResult<Response> {
+        self.reply_as(req, None, result)
+    }
+
+    // Same as `reply`, naming the result for the readable encoding. See `readable::to_readable`.
+    fn reply_as<T: Encodable>(&self,
+                              req: &Request,
+                              key: Option<&str>,
+                              result: Result<T, error::Error>)
+                              -> IronResult<Response> {
+        match result {
+            Ok(ref value) if readable::is_requested(req) => {
+                let json = readable::to_readable(key, readable::encode(value));
+                Ok(Response::with((content_type(JSON), iron::status::Ok, json.to_string())))
+            }
+            Ok(value) => json_resp(&value),
+            Err(err) => {
+                self.metrics.count_error(&err);
+                if let error::Error::BadPlayerId = err {
+                    self.limiter.bad_player_id(req.remote_addr.ip());
+                }
+                error_resp(&err)
             }
-            _ => {
-                trace!("Requesting invalid path: {:?} {:?}",
-                       req.method,
-                       &req.url.path);
-                return help_resp();
+        }
+    }
+
+    // The duplicate tournament, if any.
+    fn duplicate(&self) -> Result<&Arc<DuplicateTournament>, error::Error> {
+        self.duplicate.as_ref().ok_or(error::Error::NoTournament)
+    }
+
+    // The round-robin or knockout tournament, if any.
+    fn scheduler(&self) -> Result<&Arc<Scheduler>, error::Error> {
+        self.scheduler.as_ref().ok_or(error::Error::NoTournament)
+    }
+
+    fn index(&self, _: &mut Request, _: &[u32]) -> IronResult<Response> {
+        Ok(Response::with((content_type("text/html; charset=utf-8"),
+                           iron::status::Ok,
+                           web::INDEX)))
+    }
+
+    fn static_file(&self, req: &mut Request, _: &[u32]) -> IronResult<Response> {
+        match web::asset(&req.url.path[1]) {
+            Some((mime, content)) => {
+                Ok(Response::with((content_type(mime), iron::status::Ok, content)))
             }
+            None => help_resp(),
         }
     }
+
+    fn help(&self, _: &mut Request, _: &[u32]) -> IronResult<Response> {
+        Ok(Response::with((content_type(JSON), iron::status::Ok, help_message())))
+    }
+
+    fn openapi(&self, _: &mut Request, _: &[u32]) -> IronResult<Response> {
+        Ok(Response::with((content_type(JSON), iron::status::Ok, openapi::document())))
+    }
+
+    fn metrics(&self, _: &mut Request, _: &[u32]) -> IronResult<Response> {
+        Ok(Response::with((content_type("text/plain; version=0.0.4"),
+                           iron::status::Ok,
+                           self.metrics.render())))
+    }
+
+    fn join(&self, req: &mut Request, _: &[u32]) -> IronResult<Response> {
+        let body = read_optional_body!(parse_body::<JoinBody>(req), "join");
+        // Result is a NewPartyInfo
+        self.reply(req, self.manager.join(body))
+    }
+
+    fn lobby(&self, _: &mut Request, _: &[u32]) -> IronResult<Response> {
+        json_resp(&self.manager.lobby())
+    }
+
+    fn watch(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.manager.watch(ids[0], ids[1] as usize))
+    }
+
+    fn create_table(&self, req: &mut Request, _: &[u32]) -> IronResult<Response> {
+        let body = read_optional_body!(parse_body::<NewTableBody>(req), "table");
+        let client = req.remote_addr.ip();
+        // Result is a TableInfo
+        self.reply(req, self.manager.create_table(body, client))
+    }
+
+    fn enqueue(&self, req: &mut Request, _: &[u32]) -> IronResult<Response> {
+        let body = read_optional_body!(parse_body::<JoinBody>(req), "join");
+        // Result is a QueueStatus
+        self.reply(req, self.manager.enqueue(body))
+    }
+
+    fn queue_status(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.manager.queue_status(ids[0]))
+    }
+
+    fn ratings(&self, _: &mut Request, _: &[u32]) -> IronResult<Response> {
+        json_resp(&self.manager.ratings().list())
+    }
+
+    fn wait(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.manager.wait(ids[0], ids[1] as usize))
+    }
+
+    fn leave(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        // Result is a string - but who cares?
+        self.reply(req, self.manager.leave(ids[0]).map(|_| "ok"))
+    }
+
+    fn hand(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        // Result is a cards::Hand = u32
+        self.reply_as(req, Some("hand"), self.manager.see_hand(ids[0]))
+    }
+
+    fn scores(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.manager.see_scores(ids[0]))
+    }
+
+    fn pos(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        // Result is a pos::PlayerPos = usize
+        self.reply(req, self.manager.see_pos(ids[0]))
+    }
+
+    fn chat(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        let body = read_body!(parse_body::<ChatBody>(req), "message");
+        self.reply(req, self.manager.chat(ids[0], body).map(|_| "ok"))
+    }
+
+    fn emote(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        let body = read_body!(parse_body::<EmoteBody>(req), "emote");
+        self.reply(req, self.manager.emote(ids[0], body).map(|_| "ok"))
+    }
+
+    fn pass(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.manager.pass(ids[0]))
+    }
+
+    fn coinche(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.manager.coinche(ids[0]))
+    }
+
+    fn bid(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        let contract = read_body!(parse_body::<ContractBody>(req), "contract");
+        trace!("Bidding {:?}", contract);
+        self.reply(req, self.manager.bid(ids[0], contract))
+    }
+
+    fn play(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        let card = read_body!(parse_body::<CardBody>(req), "card");
+        self.reply(req, self.manager.play_card(ids[0], card))
+    }
+
+    fn trick(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.manager.see_trick(ids[0]))
+    }
+
+    fn last_trick(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.manager.see_last_trick(ids[0]))
+    }
+
+    fn duplicate_join(&self, req: &mut Request, _: &[u32]) -> IronResult<Response> {
+        // Result is a Registration
+        self.reply(req, self.duplicate().and_then(|t| t.join()))
+    }
+
+    fn duplicate_next(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        // Result is a NewPartyInfo
+        self.reply(req, self.duplicate().and_then(|t| t.next_table(ids[0])))
+    }
+
+    fn leaderboard(&self, req: &mut Request, _: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.duplicate().and_then(|t| t.leaderboard()))
+    }
+
+    fn register(&self, req: &mut Request, _: &[u32]) -> IronResult<Response> {
+        let body = read_body!(parse_body::<RegisterBody>(req), "team");
+        // Result is a Registration
+        self.reply(req, self.scheduler().and_then(|t| t.register(&body.team)))
+    }
+
+    fn next_match(&self, req: &mut Request, ids: &[u32]) -> IronResult<Response> {
+        // Result is a NewPartyInfo
+        self.reply(req, self.scheduler().and_then(|t| t.next_match(ids[0])))
+    }
+
+    fn standings(&self, req: &mut Request, _: &[u32]) -> IronResult<Response> {
+        self.reply(req, self.scheduler().map(|t| t.standings()))
+    }
 }
 
 impl Server {
     pub fn new(port: u16) -> Server {
         Server {
+            address: "localhost".to_string(),
             port: port,
-            manager: Arc::new(GameManager::new()),
+            threads: None,
+            tls: None,
+            manager: GameManager::new(),
+            duplicate: None,
+            tournament: None,
+            admin: None,
+            tcp_port: None,
+            shutdown_deadline: 0,
+            http_timeouts: (HTTP_TIMEOUT_SECONDS, HTTP_TIMEOUT_SECONDS),
+            cors: None,
+            limits: Limits::default(),
         }
     }
 
+    /// Listens on the given address instead of `localhost`.
+    ///
+    /// Use `0.0.0.0` to accept connections from other machines.
+    pub fn with_address(mut self, address: &str) -> Self {
+        self.address = address.to_string();
+        self
+    }
+
+    /// Serves requests with the given number of threads.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Serves HTTPS instead of HTTP, with the given certificate and private key files.
+    ///
+    /// The admin console and the binary protocol are not affected.
+    pub fn with_tls(mut self, certificate: PathBuf, key: PathBuf) -> Self {
+        self.tls = Some((certificate, key));
+        self
+    }
+
+    /// Removes ticket holders from the queue after `seconds` without news from them.
+    pub fn with_queue_timeout(mut self, seconds: i64) -> Self {
+        self.manager = self.manager.with_queue_timeout(seconds);
+        self
+    }
+
+    /// Rates players using the given ratings.
+    pub fn with_ratings(mut self, ratings: Ratings) -> Self {
+        self.manager = self.manager.with_ratings(ratings);
+        self
+    }
+
+    /// Ends parties once a team reaches `target` points.
+    pub fn with_match_target(mut self, target: i32) -> Self {
+        self.manager = self.manager.with_match_target(Some(target));
+        self
+    }
+
+    /// Runs a duplicate tournament for `n_pairs` pairs, with `deals_per_round` deals each round.
+    ///
+    /// `n_pairs` must be even and at least 4.
+    pub fn with_duplicate(mut self, n_pairs: usize, deals_per_round: usize) -> Self {
+        self.duplicate = Some((n_pairs, deals_per_round));
+        self
+    }
+
+    /// Runs a round-robin or knockout tournament for `n_teams` pairs.
+    ///
+    /// Each match is played until a team reaches `target` points.
+    pub fn with_tournament(mut self, format: Format, n_teams: usize, target: i32) -> Self {
+        self.tournament = Some((format, n_teams, target));
+        self
+    }
+
+    /// Serves the admin console on the given address and port.
+    ///
+    /// Every admin request must carry the given token.
+    pub fn with_admin(mut self, address: &str, port: u16, token: String) -> Self {
+        self.admin = Some((address.to_string(), port, token));
+        self
+    }
+
+    /// Also serves the binary protocol on the given port. See `binary`.
+    pub fn with_tcp(mut self, port: u16) -> Self {
+        self.tcp_port = Some(port);
+        self
+    }
+
+    /// On shutdown, lets the running deals finish for up to `seconds`.
+    ///
+    /// By default, parties are cancelled right away.
+    pub fn with_shutdown_deadline(mut self, seconds: u64) -> Self {
+        self.shutdown_deadline = seconds;
+        self
+    }
+
+    /// Drops connections that take more than `read` seconds to send a request,
+    /// or more than `write` seconds to receive a response. 0 disables a timeout.
+    ///
+    /// Both default to `HTTP_TIMEOUT_SECONDS`.
+    pub fn with_http_timeouts(mut self, read: u64, write: u64) -> Self {
+        self.http_timeouts = (read, write);
+        self
+    }
+
+    /// Limits the requests of each client. See `Limits`.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Allows cross-origin requests, so web clients can be hosted elsewhere.
+    pub fn with_cors(mut self, cors: Cors) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+
+    /// Runs the server, until it receives SIGINT or SIGTERM.
+    ///
+    /// It then stops accepting new players, lets the running deals finish
+    /// until the shutdown deadline, and cancels the remaining parties.
     pub fn run(self) {
+        // Threads started from now on leave these signals to us.
+        let signals = chan_signal::notify(&[Signal::INT, Signal::TERM]);
+
+        let address = self.address;
         let port = self.port;
-        println!("Listening on port {}", port);
+        let scheme = if self.tls.is_some() { "https" } else { "http" };
+        println!("Listening on {}://{}:{}", scheme, address, port);
 
-        let router = Router { manager: self.manager.clone() };
+        let manager = Arc::new(self.manager);
+        let duplicate = self.duplicate.map(|(n_pairs, deals_per_round)| {
+            Arc::new(DuplicateTournament::new(manager.clone(), n_pairs, deals_per_round))
+        });
+        let scheduler = self.tournament.map(|(format, n_teams, target)| {
+            Arc::new(Scheduler::new(manager.clone(), format, n_teams, target))
+        });
 
-        Iron::new(router).http(("localhost", port)).unwrap();
+        let admin = self.admin.map(|(admin_address, admin_port, token)| {
+            println!("Admin console on {}:{}", admin_address, admin_port);
+            let admin = AdminRouter::new(manager.clone(), token);
+            Iron::new(admin).http((&admin_address[..], admin_port)).unwrap()
+        });
+
+        // Shared by both protocols.
+        let limiter = Arc::new(Limiter::new(self.limits));
+        if let Some(tcp_port) = self.tcp_port {
+            println!("Binary protocol on {}:{}", address, tcp_port);
+            tcp::listen(manager.clone(), limiter.clone(), &address, tcp_port).unwrap();
+        }
+
+        let router = Router {
+            metrics: manager.metrics().clone(),
+            manager: manager.clone(),
+            duplicate: duplicate,
+            scheduler: scheduler,
+            cors: self.cors,
+            limiter: limiter,
+        };
+
+        let chain = Iron::new(router);
+        let addr = (&address[..], port);
+        // Same default as iron.
+        let threads = self.threads.unwrap_or(8 * num_cpus::get());
+        let protocol = match self.tls {
+            Some((certificate, key)) => {
+                iron::Protocol::Https {
+                    certificate: certificate,
+                    key: key,
+                }
+            }
+            None => iron::Protocol::Http,
+        };
+        let (read, write) = self.http_timeouts;
+        let timeouts = iron::Timeouts {
+            read: timeout(read),
+            write: timeout(write),
+            ..Default::default()
+        };
+        let mut listening = chain.listen_with(addr, threads, protocol, Some(timeouts)).unwrap();
+
+        if let Some(signal) = signals.recv() {
+            println!("Received {:?}, shutting down", signal);
+        }
+        shutdown(&manager, self.shutdown_deadline);
+
+        // Don't wait for the pending requests.
+        listening.close().unwrap();
+        if let Some(mut admin) = admin {
+            admin.close().unwrap();
+        }
+    }
+}
 
+// 0 disables a timeout.
+fn timeout(seconds: u64) -> Option<Duration> {
+    if seconds == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(seconds))
+    }
+}
+
+// Lets the running deals finish until the deadline, then cancels every party.
+fn shutdown(manager: &GameManager, deadline_seconds: u64) {
+    manager.begin_shutdown();
+    end_parties(manager, deadline_seconds);
+
+    if let Err(err) = manager.persist() {
+        println!("Could not save the server state: {}", err);
+    }
+
+    // Leave some time for the last events to reach the players.
+    thread::sleep(Duration::from_secs(1));
+}
+
+// Waits until the parties are over, or until the deadline. Then cancels the others.
+fn end_parties(manager: &GameManager, deadline_seconds: u64) {
+    let deadline = time::precise_time_ns() + deadline_seconds * 1_000_000_000;
+    while manager.running_parties() > 0 && time::precise_time_ns() < deadline {
+        thread::sleep(Duration::from_millis(100));
+    }
+    manager.cancel_all(SHUTDOWN_MESSAGE);
+}
+
+#[cfg(test)]
+mod tests {
+    use time;
+
+    use EventType;
+    use super::{end_parties, ROUTES};
+    use super::super::game_manager::{GameManager, PartySettings, SHUTDOWN_MESSAGE};
+
+    fn last_event(manager: &GameManager, party_id: u32) -> EventType {
+        manager.admin_events(party_id).ok().unwrap().pop().unwrap().event
+    }
+
+    #[test]
+    fn parties_are_cancelled_after_the_deadline() {
+        let manager = GameManager::new();
+        let (party_id, _) = manager.create_party(PartySettings::default());
+        manager.begin_shutdown();
+
+        let start = time::precise_time_ns();
+        end_parties(&manager, 1);
+        assert!(time::precise_time_ns() - start >= 1_000_000_000);
+
+        assert_eq!(manager.running_parties(), 0);
+        match last_event(&manager, party_id) {
+            EventType::PartyCancelled(msg) => assert_eq!(msg, SHUTDOWN_MESSAGE),
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn ended_parties_dont_wait_for_the_deadline() {
+        let manager = GameManager::new();
+        let (party_id, infos) = manager.create_party(PartySettings::default());
+        manager.begin_shutdown();
+        manager.leave(infos[2].player_id).ok().unwrap();
+
+        let start = time::precise_time_ns();
+        end_parties(&manager, 60);
+        assert!(time::precise_time_ns() - start < 1_000_000_000);
+
+        // The party was not cancelled a second time.
+        match last_event(&manager, party_id) {
+            EventType::PartyCancelled(msg) => assert!(msg != SHUTDOWN_MESSAGE),
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
 
+    #[test]
+    fn blocking_routes() {
+        let blocking: Vec<String> =
+            ROUTES.iter().filter(|route| route.blocking).map(|route| route.name()).collect();
+        assert_eq!(blocking,
+                   vec!["POST /join",
+                        "GET /watch",
+                        "GET /wait",
+                        "POST /duplicate/next",
+                        "POST /tournament/next"]);
     }
 }
@@ -0,0 +1,309 @@
+//! Rate limiting and abuse protection.
+//!
+//! Requests are counted per IP and per player, over one-minute windows.
+//! Clients guessing player IDs are banned for a while.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Mutex;
+
+use time;
+
+/// Limits applied to every client. `0` disables a limit.
+#[derive(Clone,Debug)]
+pub struct Limits {
+    /// Requests per minute from a single IP.
+    pub requests_per_ip: u32,
+    /// Requests per minute for a single player ID.
+    pub requests_per_player: u32,
+    /// Blocking requests (like `/join` and `/wait`) pending at once from a single IP.
+    /// Each binary protocol connection counts as one.
+    pub pending_per_ip: usize,
+    /// Unknown player IDs an IP can send before being banned.
+    pub ban_after: u32,
+    /// Duration of a ban, in seconds.
+    pub ban_seconds: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            requests_per_ip: 600,
+            requests_per_player: 300,
+            pending_per_ip: 16,
+            ban_after: 20,
+            ban_seconds: 600,
+        }
+    }
+}
+
+/// Why a request was refused.
+#[derive(Clone,Debug)]
+pub enum Refusal {
+    /// Too many requests in the current minute.
+    RateLimited,
+    /// Too many blocking requests pending.
+    TooManyPending,
+    /// The IP is banned. Contains the seconds left.
+    Banned(i64),
+}
+
+impl Refusal {
+    /// Returns a stable, machine-readable code, like the API errors.
+    pub fn code(&self) -> &'static str {
+        match self {
+            &Refusal::RateLimited => "rate_limited",
+            &Refusal::TooManyPending => "too_many_pending",
+            &Refusal::Banned(_) => "banned",
+        }
+    }
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Refusal::RateLimited => write!(f, "too many requests, slow down"),
+            &Refusal::TooManyPending => write!(f, "too many pending requests"),
+            &Refusal::Banned(seconds) => write!(f, "banned for {} more seconds", seconds),
+        }
+    }
+}
+
+// Requests counted in the current minute.
+#[derive(Default)]
+struct Window {
+    minute: i64,
+    count: u32,
+}
+
+impl Window {
+    // Counts a request. Returns `false` if it goes over the limit.
+    fn hit(&mut self, now: i64, limit: u32) -> bool {
+        if self.minute != now / 60 {
+            self.minute = now / 60;
+            self.count = 0;
+        }
+        self.count += 1;
+        limit == 0 || self.count <= limit
+    }
+}
+
+#[derive(Default)]
+struct IpState {
+    window: Window,
+    pending: usize,
+    bad_ids: u32,
+    banned_until: i64,
+}
+
+impl IpState {
+    // Nothing worth remembering anymore.
+    fn is_idle(&self, now: i64) -> bool {
+        self.window.minute < now / 60 && self.pending == 0 && self.banned_until <= now
+    }
+}
+
+/// Applies the limits to incoming requests.
+pub struct Limiter {
+    limits: Limits,
+    ips: Mutex<HashMap<IpAddr, IpState>>,
+    players: Mutex<HashMap<u32, Window>>,
+    // Last time idle clients were forgotten.
+    last_prune: Mutex<i64>,
+}
+
+/// A pending blocking request. Released on drop.
+pub struct Pending<'a> {
+    limiter: &'a Limiter,
+    ip: IpAddr,
+}
+
+impl<'a> Drop for Pending<'a> {
+    fn drop(&mut self) {
+        let mut ips = self.limiter.ips.lock().unwrap();
+        if let Some(state) = ips.get_mut(&self.ip) {
+            state.pending -= 1;
+        }
+    }
+}
+
+fn now() -> i64 {
+    time::get_time().sec
+}
+
+impl Limiter {
+    pub fn new(limits: Limits) -> Self {
+        Limiter {
+            limits: limits,
+            ips: Mutex::new(HashMap::new()),
+            players: Mutex::new(HashMap::new()),
+            last_prune: Mutex::new(now()),
+        }
+    }
+
+    // Forgets about idle clients, once a minute.
+    fn prune(&self, now: i64) {
+        {
+            let mut last_prune = self.last_prune.lock().unwrap();
+            if now - *last_prune < 60 {
+                return;
+            }
+            *last_prune = now;
+        }
+
+        self.ips.lock().unwrap().retain(|_, state| !state.is_idle(now));
+        self.players.lock().unwrap().retain(|_, window| window.minute >= now / 60);
+    }
+
+    /// Counts a request from the given IP.
+    pub fn check_ip(&self, ip: IpAddr) -> Result<(), Refusal> {
+        let now = now();
+        self.prune(now);
+
+        let mut ips = self.ips.lock().unwrap();
+        let state = ips.entry(ip).or_insert_with(IpState::default);
+        if state.banned_until > now {
+            return Err(Refusal::Banned(state.banned_until - now));
+        }
+        if !state.window.hit(now, self.limits.requests_per_ip) {
+            return Err(Refusal::RateLimited);
+        }
+        Ok(())
+    }
+
+    /// Counts a request for the given player.
+    pub fn check_player(&self, player_id: u32) -> Result<(), Refusal> {
+        let mut players = self.players.lock().unwrap();
+        let window = players.entry(player_id).or_insert_with(Window::default);
+        if !window.hit(now(), self.limits.requests_per_player) {
+            return Err(Refusal::RateLimited);
+        }
+        Ok(())
+    }
+
+    /// Starts a blocking request from the given IP, if it has room for one more.
+    pub fn begin_pending(&self, ip: IpAddr) -> Result<Pending, Refusal> {
+        let mut ips = self.ips.lock().unwrap();
+        let state = ips.entry(ip).or_insert_with(IpState::default);
+        let limit = self.limits.pending_per_ip;
+        if limit != 0 && state.pending >= limit {
+            return Err(Refusal::TooManyPending);
+        }
+        state.pending += 1;
+        Ok(Pending {
+            limiter: self,
+            ip: ip,
+        })
+    }
+
+    /// Records a request with an unknown player ID. Bans the IP after too many.
+    pub fn bad_player_id(&self, ip: IpAddr) {
+        if self.limits.ban_after == 0 {
+            return;
+        }
+
+        let now = now();
+        let mut ips = self.ips.lock().unwrap();
+        let state = ips.entry(ip).or_insert_with(IpState::default);
+        state.bad_ids += 1;
+        if state.bad_ids >= self.limits.ban_after {
+            info!("Banning {} for {} seconds", ip, self.limits.ban_seconds);
+            state.bad_ids = 0;
+            state.banned_until = now + self.limits.ban_seconds as i64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::{Limiter, Limits, Refusal, Window};
+
+    fn ip(n: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, n))
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            requests_per_ip: 0,
+            requests_per_player: 0,
+            pending_per_ip: 2,
+            ban_after: 3,
+            ban_seconds: 60,
+        }
+    }
+
+    #[test]
+    fn window() {
+        let mut window = Window::default();
+        assert!(window.hit(120, 2));
+        assert!(window.hit(150, 2));
+        assert!(!window.hit(179, 2));
+        // A new minute starts over.
+        assert!(window.hit(180, 2));
+
+        // No limit.
+        for _ in 0..1000 {
+            assert!(window.hit(180, 0));
+        }
+    }
+
+    #[test]
+    fn pending() {
+        let limiter = Limiter::new(limits());
+        let first = limiter.begin_pending(ip(1)).ok().unwrap();
+        let _second = limiter.begin_pending(ip(1)).ok().unwrap();
+        match limiter.begin_pending(ip(1)) {
+            Err(Refusal::TooManyPending) => (),
+            _ => panic!("a third request was accepted"),
+        }
+        // Other IPs have their own count.
+        assert!(limiter.begin_pending(ip(2)).is_ok());
+
+        drop(first);
+        assert!(limiter.begin_pending(ip(1)).is_ok());
+    }
+
+    #[test]
+    fn bans() {
+        let limiter = Limiter::new(limits());
+        limiter.bad_player_id(ip(1));
+        limiter.bad_player_id(ip(1));
+        assert!(limiter.check_ip(ip(1)).is_ok());
+
+        limiter.bad_player_id(ip(1));
+        match limiter.check_ip(ip(1)) {
+            Err(Refusal::Banned(seconds)) => assert!(seconds > 0 && seconds <= 60),
+            _ => panic!("the IP was not banned"),
+        }
+        assert!(limiter.check_ip(ip(2)).is_ok());
+
+        // Bans can be disabled.
+        let limiter = Limiter::new(Limits { ban_after: 0, ..limits() });
+        for _ in 0..10 {
+            limiter.bad_player_id(ip(1));
+        }
+        assert!(limiter.check_ip(ip(1)).is_ok());
+    }
+
+    #[test]
+    fn rate_limits() {
+        let limiter = Limiter::new(Limits {
+            requests_per_ip: 1000,
+            requests_per_player: 1000,
+            ..limits()
+        });
+        // Even across a minute change, one of the windows goes over the limit.
+        let mut refused = 0;
+        for _ in 0..2001 {
+            if limiter.check_player(7).is_err() {
+                refused += 1;
+            }
+        }
+        assert!(refused > 0);
+        assert!(limiter.check_player(8).is_ok());
+        assert!(limiter.check_ip(ip(1)).is_ok());
+    }
+}
@@ -0,0 +1,346 @@
+//! Matchmaking queue.
+//!
+//! Players are served in order of arrival: a newcomer is grouped with
+//! the oldest waiters that are compatible with him, and with each other.
+
+use std::collections::HashMap;
+
+use eventual::Complete;
+use rand::{thread_rng, Rng};
+use time;
+
+use {JoinBody, NewPartyInfo};
+
+/// Default time a ticket holder can go without checking his position
+/// before being removed from the queue.
+pub const TICKET_TIMEOUT_SECONDS: i64 = 30;
+
+/// What a player is looking for.
+#[derive(Clone,Debug,Default)]
+pub struct Criteria {
+    /// Rule variant label.
+    pub variant: Option<String>,
+    /// Score to reach to win the match.
+    pub target: Option<i32>,
+    /// Language spoken at the table.
+    pub language: Option<String>,
+    /// Maximum rating difference with any other player.
+    pub rating_band: Option<f64>,
+}
+
+impl<'a> From<&'a JoinBody> for Criteria {
+    fn from(body: &'a JoinBody) -> Self {
+        Criteria {
+            variant: body.variant.clone(),
+            target: body.target,
+            language: body.language.clone(),
+            rating_band: body.rating_band,
+        }
+    }
+}
+
+/// A player waiting for a party.
+pub struct Waiter {
+    pub ticket: u32,
+    pub name: Option<String>,
+    pub rating: f64,
+    pub criteria: Criteria,
+    /// Promise to complete for a blocking join.
+    /// Ticket holders have none: they poll their status instead.
+    pub promise: Option<Complete<NewPartyInfo, ()>>,
+    /// Last time we heard from this player.
+    pub last_time: time::Tm,
+}
+
+// Two optional criteria match if either is unspecified.
+fn same<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+    match (a, b) {
+        (&Some(ref a), &Some(ref b)) => a == b,
+        _ => true,
+    }
+}
+
+impl Waiter {
+    /// Returns `true` if both players accept to play together.
+    pub fn is_compatible(&self, other: &Waiter) -> bool {
+        let diff = (self.rating - other.rating).abs();
+
+        same(&self.criteria.variant, &other.criteria.variant) &&
+        same(&self.criteria.target, &other.criteria.target) &&
+        same(&self.criteria.language, &other.criteria.language) &&
+        self.criteria.rating_band.map_or(true, |band| diff <= band) &&
+        other.criteria.rating_band.map_or(true, |band| diff <= band)
+    }
+}
+
+/// FIFO queue of players waiting for a party.
+pub struct Queue {
+    waiters: Vec<Waiter>,
+    // Parties found for ticket holders, until they check their status.
+    matched: HashMap<u32, (NewPartyInfo, time::Tm)>,
+    // Time before idle ticket holders are removed.
+    timeout: time::Duration,
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        Queue::with_timeout(TICKET_TIMEOUT_SECONDS)
+    }
+
+    /// Removes ticket holders after `seconds` without news from them.
+    pub fn with_timeout(seconds: i64) -> Self {
+        Queue {
+            waiters: Vec::new(),
+            matched: HashMap::new(),
+            timeout: time::Duration::seconds(seconds),
+        }
+    }
+
+    /// Number of players waiting.
+    pub fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    /// Creates a ticket not currently in use.
+    pub fn make_ticket(&self) -> u32 {
+        loop {
+            let ticket = thread_rng().next_u32();
+            if !self.matched.contains_key(&ticket) &&
+               !self.waiters.iter().any(|w| w.ticket == ticket) {
+                return ticket;
+            }
+        }
+    }
+
+    /// Adds a player at the end of the queue.
+    pub fn push(&mut self, waiter: Waiter) {
+        self.waiters.push(waiter);
+    }
+
+    /// Returns the position of the given ticket in the queue, starting at 1.
+    ///
+    /// Also counts as a sign of life from the ticket holder.
+    pub fn position(&mut self, ticket: u32) -> Option<usize> {
+        match self.waiters.iter().position(|w| w.ticket == ticket) {
+            Some(i) => {
+                self.waiters[i].last_time = time::now();
+                Some(i + 1)
+            }
+            None => None,
+        }
+    }
+
+    /// Removes and returns the party found for the given ticket, if any.
+    pub fn take_match(&mut self, ticket: u32) -> Option<NewPartyInfo> {
+        self.matched.remove(&ticket).map(|(info, _)| info)
+    }
+
+    /// Tells a waiter the party he'll play in.
+    pub fn notify(&mut self, waiter: Waiter, info: NewPartyInfo) {
+        match waiter.promise {
+            Some(promise) => promise.complete(info),
+            None => {
+                self.matched.insert(waiter.ticket, (info, time::now()));
+            }
+        }
+    }
+
+    /// Finds the three oldest waiters compatible with the newcomer and
+    /// with each other, and removes them from the queue.
+    pub fn find_group(&mut self, newcomer: &Waiter) -> Option<Vec<Waiter>> {
+        let mut group: Vec<usize> = Vec::new();
+        for (i, waiter) in self.waiters.iter().enumerate() {
+            if waiter.is_compatible(newcomer) &&
+               group.iter().all(|&j| self.waiters[j].is_compatible(waiter)) {
+                group.push(i);
+                if group.len() == 3 {
+                    break;
+                }
+            }
+        }
+
+        if group.len() < 3 {
+            return None;
+        }
+
+        // Remove from the back, so indices stay valid.
+        let mut result: Vec<Waiter> = group.iter().rev().map(|&i| self.waiters.remove(i)).collect();
+        result.reverse();
+        Some(result)
+    }
+
+    /// Groups the waiters who can play together, oldest first, and removes them from the queue.
+    ///
+    /// Newcomers are grouped as they arrive, but a group can still be missed:
+    /// when the oldest compatible waiters don't get along, for instance.
+    pub fn find_groups(&mut self) -> Vec<Vec<Waiter>> {
+        let mut groups = Vec::new();
+
+        let mut i = 0;
+        while i < self.waiters.len() {
+            let waiter = self.waiters.remove(i);
+            match self.find_group(&waiter) {
+                Some(mut group) => {
+                    group.push(waiter);
+                    groups.push(group);
+                    // Some of the group may have been before us.
+                    i = 0;
+                }
+                None => {
+                    self.waiters.insert(i, waiter);
+                    i += 1;
+                }
+            }
+        }
+
+        groups
+    }
+
+    /// Removes and returns every waiter. The parties already found are kept.
+    pub fn drain(&mut self) -> Vec<Waiter> {
+        self.waiters.drain(..).collect()
+    }
+
+    /// Forgets about ticket holders who stopped checking their status.
+    ///
+    /// Returns the seats of those who never took the party found for them.
+    pub fn prune(&mut self) -> Vec<NewPartyInfo> {
+        let now = time::now();
+        let timeout = self.timeout;
+
+        self.waiters.retain(|w| w.promise.is_some() || now - w.last_time < timeout);
+
+        let expired: Vec<u32> = self.matched
+                                    .iter()
+                                    .filter(|&(_, &(_, since))| now - since >= timeout)
+                                    .map(|(&ticket, _)| ticket)
+                                    .collect();
+        expired.into_iter()
+               .filter_map(|ticket| self.matched.remove(&ticket))
+               .map(|(info, _)| info)
+               .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time;
+
+    use libcoinche::pos;
+    use NewPartyInfo;
+    use super::{Criteria, Queue, Waiter};
+
+    fn waiter(ticket: u32, rating: f64, criteria: Criteria) -> Waiter {
+        Waiter {
+            ticket: ticket,
+            name: None,
+            rating: rating,
+            criteria: criteria,
+            promise: None,
+            last_time: time::now(),
+        }
+    }
+
+    fn variant(name: &str) -> Criteria {
+        Criteria { variant: Some(name.to_string()), ..Default::default() }
+    }
+
+    fn tickets(waiters: &[Waiter]) -> Vec<u32> {
+        waiters.iter().map(|w| w.ticket).collect()
+    }
+
+    #[test]
+    fn compatibility() {
+        let anything = waiter(1, 1500.0, Criteria::default());
+        let classic = waiter(2, 1500.0, variant("classic"));
+        let sans_atout = waiter(3, 1500.0, variant("sans-atout"));
+        assert!(anything.is_compatible(&classic));
+        assert!(anything.is_compatible(&sans_atout));
+        assert!(!classic.is_compatible(&sans_atout));
+
+        let picky = waiter(4,
+                           1500.0,
+                           Criteria { rating_band: Some(100.0), ..Default::default() });
+        assert!(picky.is_compatible(&waiter(5, 1600.0, Criteria::default())));
+        assert!(!picky.is_compatible(&waiter(6, 1601.0, Criteria::default())));
+        // Both players must accept.
+        assert!(!waiter(7, 1601.0, Criteria::default()).is_compatible(&picky));
+    }
+
+    #[test]
+    fn oldest_compatible_waiters_first() {
+        let mut queue = Queue::new();
+        queue.push(waiter(1, 1500.0, variant("classic")));
+        queue.push(waiter(2, 1500.0, variant("sans-atout")));
+        queue.push(waiter(3, 1500.0, Criteria::default()));
+        assert!(queue.find_group(&waiter(10, 1500.0, variant("classic"))).is_none());
+
+        queue.push(waiter(4, 1500.0, variant("classic")));
+        queue.push(waiter(5, 1500.0, variant("classic")));
+        let group = queue.find_group(&waiter(11, 1500.0, variant("classic"))).unwrap();
+        assert_eq!(tickets(&group), vec![1, 3, 4]);
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.position(2), Some(1));
+        assert_eq!(queue.position(5), Some(2));
+        assert_eq!(queue.position(1), None);
+    }
+
+    #[test]
+    fn missed_groups_are_found() {
+        let mut queue = Queue::new();
+        // Nobody checked these waiters together when they arrived.
+        queue.push(waiter(1, 1500.0, Criteria::default()));
+        queue.push(waiter(2, 1500.0, variant("classic")));
+        queue.push(waiter(3, 1500.0, variant("sans-atout")));
+        queue.push(waiter(4, 1500.0, variant("sans-atout")));
+        queue.push(waiter(5, 1500.0, variant("sans-atout")));
+        assert!(queue.find_group(&waiter(6, 1500.0, variant("classic"))).is_none());
+
+        let groups = queue.find_groups();
+        assert_eq!(groups.len(), 1);
+        let mut group = tickets(&groups[0]);
+        group.sort();
+        assert_eq!(group, vec![1, 3, 4, 5]);
+        assert_eq!(queue.len(), 1);
+        assert!(queue.find_groups().is_empty());
+    }
+
+    #[test]
+    fn idle_tickets_expire() {
+        let mut queue = Queue::with_timeout(0);
+        queue.push(waiter(1, 1500.0, Criteria::default()));
+        queue.notify(waiter(2, 1500.0, Criteria::default()),
+                     NewPartyInfo::new(42, pos::PlayerPos::P1));
+
+        let expired = queue.prune();
+        assert_eq!(queue.len(), 0);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].player_id, 42);
+        assert!(queue.take_match(2).is_none());
+    }
+
+    #[test]
+    fn matches_wait_for_their_ticket() {
+        let mut queue = Queue::new();
+        queue.notify(waiter(2, 1500.0, Criteria::default()),
+                     NewPartyInfo::new(42, pos::PlayerPos::P1));
+
+        assert!(queue.prune().is_empty());
+        assert_eq!(queue.take_match(2).map(|info| info.player_id), Some(42));
+        assert!(queue.take_match(2).is_none());
+    }
+
+    #[test]
+    fn drain_keeps_matches() {
+        let mut queue = Queue::new();
+        queue.push(waiter(1, 1500.0, Criteria::default()));
+        queue.push(waiter(3, 1500.0, Criteria::default()));
+        queue.notify(waiter(2, 1500.0, Criteria::default()),
+                     NewPartyInfo::new(42, pos::PlayerPos::P1));
+
+        assert_eq!(tickets(&queue.drain()), vec![1, 3]);
+        assert_eq!(queue.len(), 0);
+        assert_eq!(queue.take_match(2).map(|info| info.player_id), Some(42));
+    }
+}
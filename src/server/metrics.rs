@@ -0,0 +1,308 @@
+//! Server metrics, in the Prometheus text format.
+//!
+//! Everything is kept in atomic counters, so recording a metric never
+//! takes a lock.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use EventType;
+
+use super::error::Error;
+use super::http::ROUTES;
+use super::tcp;
+
+// Event types, as reported in the `type` label.
+const EVENT_TYPES: [&'static str; 12] = ["YourTurn",
+                                         "PartyCancelled",
+                                         "FromPlayer",
+                                         "BidOver",
+                                         "BidCancelled",
+                                         "TrickOver",
+                                         "NewGame",
+                                         "NewGameRelative",
+                                         "GameOver",
+                                         "MatchOver",
+                                         "Chat",
+                                         "Unknown"];
+
+fn event_index(event: &EventType) -> usize {
+    match event {
+        &EventType::YourTurn => 0,
+        &EventType::PartyCancelled(_) => 1,
+        &EventType::FromPlayer(_, _) => 2,
+        &EventType::BidOver(_) => 3,
+        &EventType::BidCancelled => 4,
+        &EventType::TrickOver { .. } => 5,
+        &EventType::NewGame { .. } => 6,
+        &EventType::NewGameRelative { .. } => 7,
+        &EventType::GameOver { .. } => 8,
+        &EventType::MatchOver { .. } => 9,
+        &EventType::Chat { .. } => 10,
+        &EventType::Unknown(_) => 11,
+    }
+}
+
+// Error variants, as reported in the `error` label.
+const ERROR_TYPES: [&'static str; 21] = ["BadPlayerId",
+                                         "BadEventId",
+                                         "BadPartyId",
+                                         "BadTableId",
+                                         "BadPassword",
+                                         "QueueTable",
+                                         "TooManyTables",
+                                         "NoTournament",
+                                         "TournamentFull",
+                                         "TournamentOver",
+                                         "BadTicket",
+                                         "PartyOver",
+                                         "ShuttingDown",
+                                         "BadMessage",
+                                         "ChatTooFast",
+                                         "ChatRestricted",
+                                         "PlayInAuction",
+                                         "BidInGame",
+                                         "NoLastTrick",
+                                         "Bid",
+                                         "Play"];
+
+fn error_index(error: &Error) -> usize {
+    match error {
+        &Error::BadPlayerId => 0,
+        &Error::BadEventId => 1,
+        &Error::BadPartyId => 2,
+        &Error::BadTableId => 3,
+        &Error::BadPassword => 4,
+        &Error::QueueTable => 5,
+        &Error::TooManyTables => 6,
+        &Error::NoTournament => 7,
+        &Error::TournamentFull => 8,
+        &Error::TournamentOver => 9,
+        &Error::BadTicket => 10,
+        &Error::PartyOver => 11,
+        &Error::ShuttingDown => 12,
+        &Error::BadMessage => 13,
+        &Error::ChatTooFast => 14,
+        &Error::ChatRestricted => 15,
+        &Error::PlayInAuction => 16,
+        &Error::BidInGame => 17,
+        &Error::NoLastTrick => 18,
+        &Error::Bid(_) => 19,
+        &Error::Play(_) => 20,
+    }
+}
+
+// Requests count and total time for a route.
+struct RouteStats {
+    count: AtomicUsize,
+    micros: AtomicUsize,
+}
+
+/// Metrics collected by the server.
+pub struct Metrics {
+    active_parties: AtomicUsize,
+    waiting_players: AtomicUsize,
+    observers: AtomicUsize,
+
+    events: Vec<AtomicUsize>,
+    errors: Vec<AtomicUsize>,
+    // Route labels, in the order they are rendered.
+    route_names: Vec<String>,
+    // Built once, then only read: no lock needed.
+    routes: HashMap<String, RouteStats>,
+}
+
+fn counters(n: usize) -> Vec<AtomicUsize> {
+    (0..n).map(|_| AtomicUsize::new(0)).collect()
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        // Routes reported in the `route` label. Anything else is reported as `other`.
+        let mut route_names: Vec<String> = ROUTES.iter().map(|route| route.name()).collect();
+        route_names.push("OPTIONS".to_string());
+        route_names.push(tcp::METRICS_ROUTE.to_string());
+        route_names.push("other".to_string());
+
+        let mut routes = HashMap::new();
+        for route in route_names.iter() {
+            routes.insert(route.clone(),
+                          RouteStats {
+                              count: AtomicUsize::new(0),
+                              micros: AtomicUsize::new(0),
+                          });
+        }
+
+        Metrics {
+            active_parties: AtomicUsize::new(0),
+            waiting_players: AtomicUsize::new(0),
+            observers: AtomicUsize::new(0),
+            events: counters(EVENT_TYPES.len()),
+            errors: counters(ERROR_TYPES.len()),
+            route_names: route_names,
+            routes: routes,
+        }
+    }
+
+    pub fn party_started(&self) {
+        self.active_parties.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a party is over, either cancelled or won.
+    pub fn party_ended(&self) {
+        self.active_parties.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Records the current size of the matchmaking queue.
+    pub fn set_waiting(&self, n: usize) {
+        self.waiting_players.store(n, Ordering::Relaxed);
+    }
+
+    pub fn add_observer(&self) {
+        self.observers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that `n` observers were notified, or dropped.
+    pub fn remove_observers(&self, n: usize) {
+        self.observers.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    pub fn count_event(&self, event: &EventType) {
+        self.events[event_index(event)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn count_error(&self, error: &Error) {
+        self.errors[error_index(error)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a request on the given route, which took `micros` microseconds.
+    ///
+    /// Unknown routes are counted as `other`.
+    pub fn record_request(&self, route: &str, micros: usize) {
+        let stats = self.routes.get(route).unwrap_or(&self.routes["other"]);
+        stats.count.fetch_add(1, Ordering::Relaxed);
+        stats.micros.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Renders every metric in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        write_gauge(&mut out,
+                    "coinched_active_parties",
+                    "Number of running parties.",
+                    &self.active_parties);
+        write_gauge(&mut out,
+                    "coinched_waiting_players",
+                    "Number of players in the matchmaking queue.",
+                    &self.waiting_players);
+        write_gauge(&mut out,
+                    "coinched_pending_observers",
+                    "Number of requests waiting for an event.",
+                    &self.observers);
+
+        writeln!(out, "# HELP coinched_events_total Number of events emitted.").unwrap();
+        writeln!(out, "# TYPE coinched_events_total counter").unwrap();
+        for (name, count) in EVENT_TYPES.iter().zip(self.events.iter()) {
+            writeln!(out,
+                     "coinched_events_total{{type=\"{}\"}} {}",
+                     name,
+                     count.load(Ordering::Relaxed))
+                .unwrap();
+        }
+
+        writeln!(out, "# HELP coinched_errors_total Number of errors returned.").unwrap();
+        writeln!(out, "# TYPE coinched_errors_total counter").unwrap();
+        for (name, count) in ERROR_TYPES.iter().zip(self.errors.iter()) {
+            writeln!(out,
+                     "coinched_errors_total{{error=\"{}\"}} {}",
+                     name,
+                     count.load(Ordering::Relaxed))
+                .unwrap();
+        }
+
+        writeln!(out,
+                 "# HELP coinched_request_duration_seconds Time spent handling requests.")
+            .unwrap();
+        writeln!(out, "# TYPE coinched_request_duration_seconds summary").unwrap();
+        for route in self.route_names.iter() {
+            let stats = &self.routes[route];
+            writeln!(out,
+                     "coinched_request_duration_seconds_sum{{route=\"{}\"}} {}",
+                     route,
+                     stats.micros.load(Ordering::Relaxed) as f64 / 1e6)
+                .unwrap();
+            writeln!(out,
+                     "coinched_request_duration_seconds_count{{route=\"{}\"}} {}",
+                     route,
+                     stats.count.load(Ordering::Relaxed))
+                .unwrap();
+        }
+
+        out
+    }
+}
+
+fn write_gauge(out: &mut String, name: &str, help: &str, value: &AtomicUsize) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} gauge", name).unwrap();
+    writeln!(out, "{} {}", name, value.load(Ordering::Relaxed)).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use libcoinche::pos;
+    use EventType;
+    use super::super::error::Error;
+    use super::Metrics;
+
+    fn line<'a>(out: &'a str, prefix: &str) -> &'a str {
+        out.lines().find(|line| line.starts_with(prefix)).unwrap()
+    }
+
+    #[test]
+    fn active_parties() {
+        let metrics = Metrics::new();
+        metrics.party_started();
+        metrics.party_started();
+        metrics.party_ended();
+        assert_eq!(line(&metrics.render(), "coinched_active_parties "),
+                   "coinched_active_parties 1");
+    }
+
+    #[test]
+    fn counters_use_their_label() {
+        let metrics = Metrics::new();
+        metrics.count_error(&Error::TooManyTables);
+        metrics.count_error(&Error::TooManyTables);
+        metrics.count_error(&Error::PartyOver);
+        metrics.count_event(&EventType::MatchOver {
+            winner: pos::Team::T02,
+            scores: [1000, 420],
+        });
+
+        let out = metrics.render();
+        assert_eq!(line(&out, "coinched_errors_total{error=\"TooManyTables\"}"),
+                   "coinched_errors_total{error=\"TooManyTables\"} 2");
+        assert_eq!(line(&out, "coinched_errors_total{error=\"PartyOver\"}"),
+                   "coinched_errors_total{error=\"PartyOver\"} 1");
+        assert_eq!(line(&out, "coinched_errors_total{error=\"QueueTable\"}"),
+                   "coinched_errors_total{error=\"QueueTable\"} 0");
+        assert_eq!(line(&out, "coinched_events_total{type=\"MatchOver\"}"),
+                   "coinched_events_total{type=\"MatchOver\"} 1");
+    }
+
+    #[test]
+    fn unknown_routes_count_as_other() {
+        let metrics = Metrics::new();
+        metrics.record_request("GET /nowhere", 1500);
+        metrics.record_request("GET /lobby", 2000000);
+
+        let out = metrics.render();
+        assert_eq!(line(&out, "coinched_request_duration_seconds_count{route=\"other\"}"),
+                   "coinched_request_duration_seconds_count{route=\"other\"} 1");
+        assert_eq!(line(&out, "coinched_request_duration_seconds_sum{route=\"GET /lobby\"}"),
+                   "coinched_request_duration_seconds_sum{route=\"GET /lobby\"} 2");
+    }
+}
@@ -1,4 +1,18 @@
+pub mod admin;
+pub mod config;
+pub mod cors;
 pub mod error;
 pub mod game_manager;
+pub mod limits;
+pub mod matchmaking;
+pub mod metrics;
+pub mod openapi;
+pub mod rating;
+pub mod readable;
+pub mod routes;
+pub mod scheduler;
+pub mod tcp;
+pub mod tournament;
+pub mod web;
 
 pub mod http;
@@ -0,0 +1,172 @@
+//! OpenAPI description of the HTTP API, built from the route table.
+
+use std::collections::BTreeMap;
+
+use rustc_serialize::json::Json;
+
+use super::http::ROUTES;
+use API_PREFIX;
+use super::routes::{self, Route};
+
+// Schemas of the bodies and responses, referenced by name in the route table.
+const SCHEMAS: &'static str = include_str!("schemas.json");
+
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+fn string(value: &str) -> Json {
+    Json::String(value.to_string())
+}
+
+fn schema_ref(name: &str) -> Json {
+    object(vec![("$ref", string(&format!("#/components/schemas/{}", name)))])
+}
+
+// `/hand/[PLAYER_ID]` becomes `/hand/{player_id}`.
+fn path(href: &str) -> String {
+    href.split('/')
+        .map(|segment| {
+            if segment.starts_with('[') {
+                let name = segment.trim_matches(|c| c == '[' || c == ']');
+                format!("{{{}}}", name.to_lowercase())
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn content(content_type: &str, schema: Option<&str>) -> Json {
+    let schema = match schema {
+        Some(name) => schema_ref(name),
+        None => object(vec![]),
+    };
+    object(vec![(content_type, object(vec![("schema", schema)]))])
+}
+
+fn parameter(name: &str) -> Json {
+    let kind = if routes::is_id(name) { "integer" } else { "string" };
+    object(vec![("name", string(&name.to_lowercase())),
+                ("in", string("path")),
+                ("required", Json::Boolean(true)),
+                ("schema", object(vec![("type", string(kind))]))])
+}
+
+fn operation(route: &Route) -> Json {
+    let mut fields = vec![("summary", string(route.help)),
+                          ("tags", Json::Array(vec![string(route.tag)]))];
+
+    let params = route.params();
+    if !params.is_empty() {
+        fields.push(("parameters", Json::Array(params.into_iter().map(parameter).collect())));
+    }
+
+    if let Some(body) = route.body {
+        fields.push(("requestBody",
+                     object(vec![("content", content(routes::JSON, Some(body)))])));
+    }
+
+    let success = object(vec![("description", string("Success")),
+                              ("content", content(route.content_type, route.response))]);
+    let failure = object(vec![("description", string("Error")),
+                              ("content", content(routes::JSON, Some("Error")))]);
+    fields.push(("responses", object(vec![("200", success), ("default", failure)])));
+
+    object(fields)
+}
+
+/// Returns the OpenAPI document, in JSON.
+pub fn document() -> String {
+    let mut paths = BTreeMap::new();
+    for route in ROUTES.iter() {
+        paths.entry(path(route.href))
+             .or_insert_with(BTreeMap::new)
+             .insert(route.method.to_lowercase(), operation(route));
+    }
+    let paths = paths.into_iter()
+                     .map(|(path, operations)| (path, Json::Object(operations)))
+                     .collect();
+
+    let schemas = Json::from_str(SCHEMAS).unwrap();
+
+    object(vec![("openapi", string("3.0.0")),
+                ("info",
+                 object(vec![("title", string("coinched")),
+                             ("version", string(env!("CARGO_PKG_VERSION")))])),
+                ("servers",
+                 Json::Array(vec![object(vec![("url", string(&format!("/{}", API_PREFIX)))])])),
+                ("paths", Json::Object(paths)),
+                ("components", object(vec![("schemas", schemas)]))])
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_serialize::json::Json;
+
+    use super::super::http::ROUTES;
+    use super::{document, path, SCHEMAS};
+
+    // Checks every `$ref` in the value points to an existing schema.
+    fn check_refs(value: &Json, schemas: &Json) {
+        match value {
+            &Json::Object(ref fields) => {
+                for (key, field) in fields {
+                    if key == "$ref" {
+                        let name = field.as_string()
+                                        .unwrap()
+                                        .trim_left_matches("#/components/schemas/");
+                        assert!(schemas.find(name).is_some(), "missing schema {}", name);
+                    } else {
+                        check_refs(field, schemas);
+                    }
+                }
+            }
+            &Json::Array(ref values) => {
+                for value in values {
+                    check_refs(value, schemas);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(path("/hand/[PLAYER_ID]"), "/hand/{player_id}");
+        assert_eq!(path("/lobby"), "/lobby");
+    }
+
+    #[test]
+    fn every_route_is_described() {
+        let document = Json::from_str(&document()).unwrap();
+        let paths = document.find("paths").unwrap();
+        for route in ROUTES.iter() {
+            let operations = paths.find(&path(route.href));
+            assert!(operations.and_then(|ops| ops.find(&route.method.to_lowercase())).is_some(),
+                    "{} {} is not described",
+                    route.method,
+                    route.href);
+        }
+    }
+
+    #[test]
+    fn every_schema_exists() {
+        let schemas = Json::from_str(SCHEMAS).unwrap();
+        for route in ROUTES.iter() {
+            for name in route.body.iter().chain(route.response.iter()) {
+                assert!(schemas.find(name).is_some(),
+                        "missing schema {} for {} {}",
+                        name,
+                        route.method,
+                        route.href);
+            }
+        }
+        assert!(schemas.find("Error").is_some());
+
+        let document = Json::from_str(&document()).unwrap();
+        check_refs(&document, &schemas);
+    }
+}
@@ -0,0 +1,265 @@
+//! Elo ratings for named players.
+//!
+//! Partners are rated as a team: the team rating is the average of both
+//! players ratings, and both partners gain (or lose) the same amount.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+
+use rustc_serialize::json;
+
+use libcoinche::pos;
+
+/// Rating given to new or anonymous players.
+pub const DEFAULT_RATING: f64 = 1500.0;
+
+// Maximum rating change after a single match.
+const K_FACTOR: f64 = 32.0;
+
+/// Rating of a single player.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct Rating {
+    pub name: String,
+    pub rating: f64,
+    pub matches: usize,
+    pub wins: usize,
+}
+
+/// Keeps track of the players ratings.
+///
+/// If a file is given, ratings are loaded from it, and saved after each update.
+pub struct Ratings {
+    path: Option<PathBuf>,
+    table: RwLock<HashMap<String, Rating>>,
+    // Held while writing the file.
+    save_lock: Mutex<()>,
+}
+
+/// Returns `true` if a party with these players is rated: at least one gave a name.
+pub fn is_rated(names: &[Option<String>; 4]) -> bool {
+    names.iter().any(|name| name.is_some())
+}
+
+// Probability for a team rated `rating` to beat a team rated `other`.
+fn expected_score(rating: f64, other: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((other - rating) / 400.0))
+}
+
+impl Ratings {
+    /// Creates an in-memory rating table.
+    pub fn new() -> Self {
+        Ratings {
+            path: None,
+            table: RwLock::new(HashMap::new()),
+            save_lock: Mutex::new(()),
+        }
+    }
+
+    /// Loads the ratings from the given file, and keeps them saved there.
+    ///
+    /// Starts with an empty table if the file doesn't exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut table = HashMap::new();
+        if path.exists() {
+            let mut content = String::new();
+            try!(try!(fs::File::open(&path)).read_to_string(&mut content));
+            let ratings: Vec<Rating> = try!(json::decode(&content).map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}", err))
+            }));
+            for rating in ratings {
+                table.insert(rating.name.clone(), rating);
+            }
+        }
+
+        Ok(Ratings {
+            path: Some(path),
+            table: RwLock::new(table),
+            save_lock: Mutex::new(()),
+        })
+    }
+
+    /// Returns the rating of the given player.
+    ///
+    /// Anonymous or unknown players get the default rating.
+    pub fn get(&self, name: Option<&str>) -> f64 {
+        name.and_then(|name| self.table.read().unwrap().get(name).map(|r| r.rating))
+            .unwrap_or(DEFAULT_RATING)
+    }
+
+    /// Returns every rating, best first.
+    pub fn list(&self) -> Vec<Rating> {
+        let mut list: Vec<Rating> = self.table.read().unwrap().values().cloned().collect();
+        list.sort_by(|a, b| b.rating.partial_cmp(&a.rating).unwrap());
+        list
+    }
+
+    /// Updates the ratings after a match, or after a game for parties without target.
+    ///
+    /// `names` are the players at each seat. Anonymous players are not rated,
+    /// but still count with the default rating in their team.
+    pub fn record_match(&self, names: &[Option<String>; 4], winner: pos::Team) {
+        if !is_rated(names) {
+            return;
+        }
+
+        {
+            let mut table = self.table.write().unwrap();
+
+            // T02 sits at P0 and P2, T13 at P1 and P3.
+            let teams = {
+                let rating_of = |name: &Option<String>| {
+                    name.as_ref()
+                        .and_then(|name| table.get(name).map(|r| r.rating))
+                        .unwrap_or(DEFAULT_RATING)
+                };
+                [(rating_of(&names[0]) + rating_of(&names[2])) / 2.0,
+                 (rating_of(&names[1]) + rating_of(&names[3])) / 2.0]
+            };
+
+            for (i, name) in names.iter().enumerate() {
+                let name = match name {
+                    &Some(ref name) => name,
+                    &None => continue,
+                };
+                let team = i % 2;
+                let won = team == winner as usize;
+                let delta = K_FACTOR *
+                            (if won { 1.0 } else { 0.0 } - expected_score(teams[team],
+                                                                          teams[1 - team]));
+
+                let rating = table.entry(name.clone()).or_insert(Rating {
+                    name: name.clone(),
+                    rating: DEFAULT_RATING,
+                    matches: 0,
+                    wins: 0,
+                });
+                rating.rating += delta;
+                rating.matches += 1;
+                if won {
+                    rating.wins += 1;
+                }
+            }
+        }
+
+        if let Err(err) = self.save() {
+            error!("Could not save ratings: {}", err);
+        }
+    }
+
+    /// Writes the ratings to the file, if any.
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        // Concurrent saves would share the temporary file.
+        let _lock = self.save_lock.lock().unwrap();
+        let content = json::encode(&self.list()).unwrap();
+
+        // Write to a temporary file first, so we never leave a half-written file.
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file = try!(fs::File::create(&tmp_path));
+            try!(file.write_all(content.as_bytes()));
+        }
+        fs::rename(&tmp_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use libcoinche::pos;
+    use super::{expected_score, is_rated, Ratings, DEFAULT_RATING, K_FACTOR};
+
+    fn names(players: [Option<&str>; 4]) -> [Option<String>; 4] {
+        [players[0].map(String::from),
+         players[1].map(String::from),
+         players[2].map(String::from),
+         players[3].map(String::from)]
+    }
+
+    #[test]
+    fn expected_scores() {
+        assert_eq!(expected_score(1500.0, 1500.0), 0.5);
+        let favorite = expected_score(1700.0, 1500.0);
+        assert!(favorite > 0.75 && favorite < 0.77);
+        assert!((favorite + expected_score(1500.0, 1700.0) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rated_parties() {
+        assert!(!is_rated(&names([None, None, None, None])));
+        assert!(is_rated(&names([None, None, Some("c"), None])));
+    }
+
+    #[test]
+    fn winners_take_from_losers() {
+        let ratings = Ratings::new();
+        let players = names([Some("a"), Some("b"), Some("c"), Some("d")]);
+        ratings.record_match(&players, pos::Team::T02);
+
+        let half = K_FACTOR / 2.0;
+        assert_eq!(ratings.get(Some("a")), DEFAULT_RATING + half);
+        assert_eq!(ratings.get(Some("c")), DEFAULT_RATING + half);
+        assert_eq!(ratings.get(Some("b")), DEFAULT_RATING - half);
+        assert_eq!(ratings.get(Some("d")), DEFAULT_RATING - half);
+
+        let list = ratings.list();
+        assert_eq!(list.len(), 4);
+        assert_eq!((list[0].matches, list[0].wins), (1, 1));
+        assert_eq!((list[3].matches, list[3].wins), (1, 0));
+    }
+
+    #[test]
+    fn upsets_move_ratings_more() {
+        let ratings = Ratings::new();
+        let players = names([Some("a"), Some("b"), Some("c"), Some("d")]);
+        ratings.record_match(&players, pos::Team::T02);
+        let favorite = ratings.get(Some("a"));
+
+        // The underdogs win this time, and gain more than the favorites did.
+        ratings.record_match(&players, pos::Team::T13);
+        assert!(ratings.get(Some("b")) - (DEFAULT_RATING - K_FACTOR / 2.0) > K_FACTOR / 2.0);
+        assert!(ratings.get(Some("a")) < favorite - K_FACTOR / 2.0);
+    }
+
+    #[test]
+    fn anonymous_players_are_not_rated() {
+        let ratings = Ratings::new();
+        ratings.record_match(&names([None, None, None, None]), pos::Team::T02);
+        assert!(ratings.list().is_empty());
+
+        ratings.record_match(&names([Some("a"), None, None, None]), pos::Team::T13);
+        let list = ratings.list();
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].name, "a");
+        assert_eq!(list[0].rating, DEFAULT_RATING - K_FACTOR / 2.0);
+        assert_eq!(ratings.get(None), DEFAULT_RATING);
+    }
+
+    #[test]
+    fn saved_ratings_load_back() {
+        let file = format!("coinched-ratings-{}.json", ::rand::random::<u32>());
+        let path = env::temp_dir().join(file);
+
+        let ratings = Ratings::load(&path).unwrap();
+        assert!(ratings.list().is_empty());
+        ratings.record_match(&names([Some("a"), Some("b"), None, None]), pos::Team::T02);
+
+        // Saved after each update.
+        let loaded = Ratings::load(&path).unwrap();
+        assert_eq!(loaded.get(Some("a")), ratings.get(Some("a")));
+        assert_eq!(loaded.get(Some("b")), ratings.get(Some("b")));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,258 @@
+//! Readable encoding for cards, hands and suits.
+//!
+//! By default, the API uses the libcoinche encoding: cards are bitmasks,
+//! hands are 32-bit sets and suits are integers. Clients can ask for
+//! readable values instead, like `"QH"`, `["7S", "KD"]` or `"hearts"`,
+//! with `?cards=readable` or an `Accept: application/json; cards=readable`
+//! header. Request bodies may use either encoding.
+//!
+//! The conversion works on the JSON tree, based on field names, so the
+//! types themselves keep a single encoding.
+
+use rustc_serialize::{json, Decodable, Encodable};
+use rustc_serialize::json::Json;
+use libcoinche::cards;
+use iron::prelude::*;
+
+// Each suit, with its letter in card names, and its full name.
+const SUITS: [(cards::Suit, &'static str, &'static str); 4] = [
+    (cards::Suit::Heart, "H", "hearts"),
+    (cards::Suit::Spade, "S", "spades"),
+    (cards::Suit::Diamond, "D", "diamonds"),
+    (cards::Suit::Club, "C", "clubs"),
+];
+
+// Each rank, with its name in card names.
+const RANKS: [(cards::Rank, &'static str); 8] = [
+    (cards::Rank::Rank7, "7"),
+    (cards::Rank::Rank8, "8"),
+    (cards::Rank::Rank9, "9"),
+    (cards::Rank::RankJ, "J"),
+    (cards::Rank::RankQ, "Q"),
+    (cards::Rank::RankK, "K"),
+    (cards::Rank::RankX, "10"),
+    (cards::Rank::RankA, "A"),
+];
+
+/// Returns `true` if the request asks for the readable encoding.
+pub fn is_requested(req: &Request) -> bool {
+    let in_query = match req.url.query {
+        Some(ref query) => query.split('&').any(|param| param == "cards=readable"),
+        None => false,
+    };
+    let in_accept = match req.headers.get_raw("Accept") {
+        Some(values) => {
+            values.iter().any(|value| String::from_utf8_lossy(value).contains("cards=readable"))
+        }
+        None => false,
+    };
+    in_query || in_accept
+}
+
+fn decode<T: Decodable>(json: &Json) -> Option<T> {
+    Decodable::decode(&mut json::Decoder::new(json.clone())).ok()
+}
+
+/// Encodes the value as a JSON tree, with the default encoding.
+pub fn encode<T: Encodable>(value: &T) -> Json {
+    Json::from_str(&json::encode(value).unwrap()).unwrap()
+}
+
+fn suit_name(suit: cards::Suit) -> &'static str {
+    SUITS.iter().find(|&&(s, _, _)| s == suit).unwrap().2
+}
+
+fn card_name(card: cards::Card) -> String {
+    let rank = RANKS.iter().find(|&&(r, _)| r == card.rank()).unwrap().1;
+    let suit = SUITS.iter().find(|&&(s, _, _)| s == card.suit()).unwrap().1;
+    format!("{}{}", rank, suit)
+}
+
+fn parse_suit(name: &str) -> Option<cards::Suit> {
+    SUITS.iter().find(|&&(_, _, n)| n == name).map(|&(suit, _, _)| suit)
+}
+
+// Parses `QH`, `10S`... `X` is also accepted for tens.
+fn parse_card(name: &str) -> Option<cards::Card> {
+    // The suit is the last character, which may not be ASCII.
+    let last = match name.char_indices().last() {
+        Some((last, _)) if last > 0 => last,
+        _ => return None,
+    };
+    let (rank, suit) = name.split_at(last);
+    let rank = if rank == "X" { "10" } else { rank };
+    let rank = match RANKS.iter().find(|&&(_, r)| r == rank) {
+        Some(&(rank, _)) => rank,
+        None => return None,
+    };
+    SUITS.iter()
+         .find(|&&(_, s, _)| s == suit)
+         .map(|&(suit, _, _)| cards::Card::new(suit, rank))
+}
+
+fn readable_card(json: Json) -> Json {
+    match decode::<cards::Card>(&json) {
+        Some(card) => Json::String(card_name(card)),
+        None => json,
+    }
+}
+
+fn readable_hand(json: Json) -> Json {
+    match decode::<cards::Hand>(&json) {
+        Some(hand) => {
+            let names = hand.list().into_iter().map(|card| Json::String(card_name(card)));
+            Json::Array(names.collect())
+        }
+        None => json,
+    }
+}
+
+/// Converts cards, hands and suits to the readable encoding.
+///
+/// `key` is the name of the value, if any: `hand` for a bare hand.
+pub fn to_readable(key: Option<&str>, json: Json) -> Json {
+    match (key, json) {
+        (Some("card"), json) => readable_card(json),
+        (Some("hand"), json) => readable_hand(json),
+        // Tricks: one card per seat, or nothing.
+        (Some("cards"), Json::Array(list)) => {
+            Json::Array(list.into_iter().map(readable_card).collect())
+        }
+        // New games: the player's hand.
+        (Some("cards"), json) => readable_hand(json),
+        (Some("hands"), Json::Array(list)) => {
+            Json::Array(list.into_iter().map(readable_hand).collect())
+        }
+        (Some("suit"), json) |
+        (Some("trump"), json) => {
+            match decode::<cards::Suit>(&json) {
+                Some(suit) => Json::String(suit_name(suit).to_string()),
+                None => json,
+            }
+        }
+        (_, Json::Object(map)) => {
+            Json::Object(map.into_iter()
+                            .map(|(key, value)| {
+                                let value = to_readable(Some(&key[..]), value);
+                                (key, value)
+                            })
+                            .collect())
+        }
+        (_, Json::Array(list)) => {
+            Json::Array(list.into_iter().map(|value| to_readable(None, value)).collect())
+        }
+        (_, json) => json,
+    }
+}
+
+/// Converts readable cards and suits back to the default encoding.
+///
+/// Values already in the default encoding are left untouched.
+pub fn from_readable(key: Option<&str>, json: Json) -> Json {
+    match (key, json) {
+        (Some("card"), Json::String(name)) => {
+            match parse_card(&name) {
+                Some(card) => encode(&card),
+                None => Json::String(name),
+            }
+        }
+        (Some("suit"), Json::String(name)) => {
+            match parse_suit(&name) {
+                Some(suit) => encode(&suit),
+                None => Json::String(name),
+            }
+        }
+        (_, Json::Object(map)) => {
+            Json::Object(map.into_iter()
+                            .map(|(key, value)| {
+                                let value = from_readable(Some(&key[..]), value);
+                                (key, value)
+                            })
+                            .collect())
+        }
+        (_, json) => json,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rustc_serialize::json::Json;
+    use libcoinche::cards;
+
+    use super::{card_name, encode, from_readable, parse_card, parse_suit, to_readable};
+
+    fn object(fields: Vec<(&str, Json)>) -> Json {
+        let map: BTreeMap<String, Json> =
+            fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect();
+        Json::Object(map)
+    }
+
+    fn string(value: &str) -> Json {
+        Json::String(value.to_string())
+    }
+
+    #[test]
+    fn card_names() {
+        let queen = cards::Card::new(cards::Suit::Heart, cards::Rank::RankQ);
+        let ten = cards::Card::new(cards::Suit::Spade, cards::Rank::RankX);
+        assert_eq!(card_name(queen), "QH");
+        assert_eq!(card_name(ten), "10S");
+
+        assert_eq!(parse_card("QH"), Some(queen));
+        assert_eq!(parse_card("10S"), Some(ten));
+        assert_eq!(parse_card("XS"), Some(ten));
+        assert_eq!(parse_card("Q"), None);
+        assert_eq!(parse_card("QZ"), None);
+        assert_eq!(parse_card("1H"), None);
+        assert_eq!(parse_card("Q♥"), None);
+        assert_eq!(parse_card("10é"), None);
+        assert_eq!(parse_card("éH"), None);
+        assert_eq!(parse_card("é"), None);
+        assert_eq!(parse_card(""), None);
+
+        assert_eq!(parse_suit("clubs"), Some(cards::Suit::Club));
+        assert_eq!(parse_suit("C"), None);
+    }
+
+    #[test]
+    fn readable_values() {
+        let card = cards::Card::new(cards::Suit::Diamond, cards::Rank::Rank7);
+        let mut hand = cards::Hand::new();
+        hand.add(card);
+
+        let json = object(vec![("card", encode(&card)),
+                               ("hand", encode(&hand)),
+                               ("trump", encode(&cards::Suit::Heart)),
+                               ("cards", Json::Array(vec![encode(&card), Json::Null])),
+                               ("scores", Json::Array(vec![Json::I64(80), Json::I64(0)]))]);
+        let expected = object(vec![("card", string("7D")),
+                                   ("hand", Json::Array(vec![string("7D")])),
+                                   ("trump", string("hearts")),
+                                   ("cards", Json::Array(vec![string("7D"), Json::Null])),
+                                   ("scores", Json::Array(vec![Json::I64(80), Json::I64(0)]))]);
+        assert_eq!(to_readable(None, json), expected);
+
+        // Nested values, like events in a list.
+        let nested = Json::Array(vec![object(vec![("card", encode(&card))])]);
+        assert_eq!(to_readable(None, nested),
+                   Json::Array(vec![object(vec![("card", string("7D"))])]));
+    }
+
+    #[test]
+    fn round_trip() {
+        let card = cards::Card::new(cards::Suit::Club, cards::Rank::RankA);
+        let body = object(vec![("card", encode(&card)), ("suit", encode(&cards::Suit::Spade))]);
+
+        let readable = to_readable(None, body.clone());
+        assert_eq!(readable,
+                   object(vec![("card", string("AC")), ("suit", string("spades"))]));
+        assert_eq!(from_readable(None, readable), body);
+
+        // The default encoding, and unknown names, are left untouched.
+        assert_eq!(from_readable(None, body.clone()), body);
+        let unknown = object(vec![("card", string("ZZ"))]);
+        assert_eq!(from_readable(None, unknown.clone()), unknown);
+    }
+}
@@ -0,0 +1,271 @@
+//! Route registry for the HTTP API.
+//!
+//! Each route declares its method, its path and its handler. The router
+//! dispatches requests with it, and the `OPTIONS` answers, the help message,
+//! the OpenAPI description, the metrics labels and the limits on blocking
+//! requests are all derived from it.
+//! The table itself lives in `server::http`, next to the handlers.
+
+use iron::prelude::*;
+
+use super::http::Router;
+
+/// Handles a request, given the IDs found in its path, in order.
+pub type Handler = fn(&Router, &mut Request, &[u32]) -> IronResult<Response>;
+
+/// Content type of most responses.
+pub const JSON: &'static str = "application/json";
+
+/// A route, with its handler and its documentation.
+pub struct Route {
+    pub method: &'static str,
+    /// Path, with parameters in brackets, like `/hand/[PLAYER_ID]`.
+    pub href: &'static str,
+    pub help: &'static str,
+    /// Group of routes this one belongs to.
+    pub tag: &'static str,
+    /// Schema of the JSON body, if any.
+    pub body: Option<&'static str>,
+    /// Content type of the response.
+    pub content_type: &'static str,
+    /// Schema of the response, for JSON responses.
+    pub response: Option<&'static str>,
+    /// If true, the request waits until something happens.
+    /// Such requests pending at once from a single IP are limited.
+    pub blocking: bool,
+    pub handler: Handler,
+}
+
+fn is_param(segment: &str) -> bool {
+    segment.starts_with('[')
+}
+
+/// Returns `true` if the path parameter is an ID, given to the handler.
+///
+/// Every parameter is an ID, except file names.
+pub fn is_id(param: &str) -> bool {
+    param != "FILE"
+}
+
+impl Route {
+    // Path segments, like in `iron::Url::path`: `/` is a single empty segment.
+    fn segments(&self) -> Vec<&'static str> {
+        self.href.split('/').skip(1).collect()
+    }
+
+    // Segments before the parameters.
+    fn fixed(&self) -> Vec<&'static str> {
+        self.segments().into_iter().take_while(|segment| !is_param(segment)).collect()
+    }
+
+    /// Name of the route, as reported in the metrics: method and fixed path segments.
+    pub fn name(&self) -> String {
+        format!("{} /{}", self.method, self.fixed().join("/"))
+    }
+
+    /// Path parameters, without the brackets.
+    pub fn params(&self) -> Vec<&'static str> {
+        self.segments()
+            .into_iter()
+            .filter(|segment| is_param(segment))
+            .map(|segment| segment.trim_matches(|c| c == '[' || c == ']'))
+            .collect()
+    }
+
+    /// Returns `true` if the path matches this route, whatever the method.
+    pub fn matches(&self, path: &[String]) -> bool {
+        let segments = self.segments();
+        segments.len() == path.len() &&
+        segments.iter()
+                .zip(path)
+                .all(|(segment, value)| is_param(segment) || *segment == &value[..])
+    }
+
+    // Returns `true` if the path starts with the fixed segments.
+    fn same_name(&self, path: &[String]) -> bool {
+        let fixed = self.fixed();
+        path.len() >= fixed.len() &&
+        fixed.iter().zip(path).all(|(segment, value)| *segment == &value[..])
+    }
+}
+
+/// Result of looking up a request.
+pub enum Lookup {
+    Found(&'static Route),
+    /// The path looks like this route, but with the wrong number of parameters.
+    BadParameters(&'static Route),
+    NotFound,
+}
+
+/// Finds the route for the given method and path.
+pub fn lookup(routes: &'static [Route], method: &str, path: &[String]) -> Lookup {
+    let candidates = || routes.iter().filter(move |route| route.method == method);
+    if let Some(route) = candidates().find(|route| route.matches(path)) {
+        return Lookup::Found(route);
+    }
+
+    match candidates().find(|route| route.same_name(path)) {
+        Some(route) => Lookup::BadParameters(route),
+        None => Lookup::NotFound,
+    }
+}
+
+/// Returns the methods allowed on the given path, `OPTIONS` excluded.
+pub fn allowed(routes: &'static [Route], path: &[String]) -> Vec<&'static str> {
+    routes.iter()
+          .filter(|route| route.matches(path))
+          .map(|route| route.method)
+          .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use iron::prelude::*;
+
+    use super::super::http::Router;
+    use super::{allowed, lookup, Lookup, Route, JSON};
+
+    fn handler(_: &Router, _: &mut Request, _: &[u32]) -> IronResult<Response> {
+        Ok(Response::new())
+    }
+
+    // Only the method and the path matter here.
+    static ROUTES: [Route; 6] = [
+        Route {
+            method: "GET",
+            href: "/",
+            help: "",
+            tag: "Test",
+            body: None,
+            content_type: JSON,
+            response: None,
+            blocking: false,
+            handler: handler,
+        },
+        Route {
+            method: "POST",
+            href: "/join",
+            help: "",
+            tag: "Test",
+            body: None,
+            content_type: JSON,
+            response: None,
+            blocking: false,
+            handler: handler,
+        },
+        Route {
+            method: "GET",
+            href: "/hand/[PLAYER_ID]",
+            help: "",
+            tag: "Test",
+            body: None,
+            content_type: JSON,
+            response: None,
+            blocking: false,
+            handler: handler,
+        },
+        Route {
+            method: "POST",
+            href: "/hand/[PLAYER_ID]",
+            help: "",
+            tag: "Test",
+            body: None,
+            content_type: JSON,
+            response: None,
+            blocking: false,
+            handler: handler,
+        },
+        Route {
+            method: "GET",
+            href: "/wait/[PLAYER_ID]/[EVENT_ID]",
+            help: "",
+            tag: "Test",
+            body: None,
+            content_type: JSON,
+            response: None,
+            blocking: false,
+            handler: handler,
+        },
+        Route {
+            method: "GET",
+            href: "/web/[FILE]",
+            help: "",
+            tag: "Test",
+            body: None,
+            content_type: JSON,
+            response: None,
+            blocking: false,
+            handler: handler,
+        },
+    ];
+
+    fn path(path: &[&str]) -> Vec<String> {
+        path.iter().map(|segment| segment.to_string()).collect()
+    }
+
+    fn found(lookup: Lookup) -> Option<&'static str> {
+        match lookup {
+            Lookup::Found(route) => Some(route.href),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn found_routes() {
+        let routes = &ROUTES;
+        assert_eq!(found(lookup(routes, "GET", &path(&[""]))), Some("/"));
+        assert_eq!(found(lookup(routes, "POST", &path(&["join"]))), Some("/join"));
+        assert_eq!(found(lookup(routes, "GET", &path(&["hand", "3"]))),
+                   Some("/hand/[PLAYER_ID]"));
+        assert_eq!(found(lookup(routes, "GET", &path(&["wait", "3", "12"]))),
+                   Some("/wait/[PLAYER_ID]/[EVENT_ID]"));
+        assert_eq!(found(lookup(routes, "GET", &path(&["web", "app.js"]))),
+                   Some("/web/[FILE]"));
+    }
+
+    #[test]
+    fn bad_parameters() {
+        let routes = &ROUTES;
+        match lookup(routes, "GET", &path(&["wait", "3"])) {
+            Lookup::BadParameters(route) => assert_eq!(route.href, "/wait/[PLAYER_ID]/[EVENT_ID]"),
+            _ => panic!("expected bad parameters"),
+        }
+        match lookup(routes, "POST", &path(&["join", "3"])) {
+            Lookup::BadParameters(route) => assert_eq!(route.href, "/join"),
+            _ => panic!("expected bad parameters"),
+        }
+    }
+
+    #[test]
+    fn not_found() {
+        let routes = &ROUTES;
+        let not_found = |method, segments: &[&str]| {
+            match lookup(routes, method, &path(segments)) {
+                Lookup::NotFound => true,
+                _ => false,
+            }
+        };
+        assert!(not_found("GET", &["nothing"]));
+        assert!(not_found("GET", &["join"]));
+        assert!(not_found("DELETE", &["hand", "3"]));
+    }
+
+    #[test]
+    fn allowed_methods() {
+        let routes = &ROUTES;
+        assert_eq!(allowed(routes, &path(&["hand", "3"])), vec!["GET", "POST"]);
+        assert_eq!(allowed(routes, &path(&["join"])), vec!["POST"]);
+        assert!(allowed(routes, &path(&["nothing"])).is_empty());
+    }
+
+    #[test]
+    fn names_and_params() {
+        let root = &ROUTES[0];
+        assert_eq!(root.name(), "GET /");
+        assert!(root.params().is_empty());
+
+        let wait = &ROUTES[4];
+        assert_eq!(wait.name(), "GET /wait");
+        assert_eq!(wait.params(), vec!["PLAYER_ID", "EVENT_ID"]);
+    }
+}
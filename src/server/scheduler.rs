@@ -0,0 +1,680 @@
+//! Tournament scheduler for registered pairs.
+//!
+//! Pairs register under a team name, then play a series of rounds,
+//! either round-robin (everyone meets everyone once) or single elimination.
+//! Each match is a party played until a team reaches the target score.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use eventual::{Future, Complete, Async};
+
+use libcoinche::pos;
+use {Event, EventType, NewPartyInfo, Registration};
+
+use super::error::Error;
+use super::game_manager::{leaving_seat, GameManager, ManagerResult, PartySettings,
+                          SHUTDOWN_MESSAGE};
+
+/// How the matches are scheduled.
+#[derive(Clone,Copy,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub enum Format {
+    /// Every team plays every other team once.
+    RoundRobin,
+    /// Single elimination: losers leave the tournament.
+    Knockout,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "round-robin" | "roundrobin" => Ok(Format::RoundRobin),
+            "knockout" | "elimination" => Ok(Format::Knockout),
+            _ => Err(format!("unknown tournament format: `{}`", s)),
+        }
+    }
+}
+
+/// Standing of a team in the tournament.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct TeamStanding {
+    pub team: String,
+    pub played: usize,
+    pub wins: usize,
+    pub losses: usize,
+    /// Sum of the final match scores, for and against.
+    pub points_for: i32,
+    pub points_against: i32,
+    /// For a knockout tournament, `true` once the team lost a match.
+    pub eliminated: bool,
+}
+
+/// Current tournament standings.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct Standings {
+    pub format: Format,
+    /// Current round, starting at 1. 0 if the tournament didn't start yet.
+    pub round: usize,
+    pub over: bool,
+    /// Winner of the tournament, once it's over.
+    pub champion: Option<String>,
+    pub teams: Vec<TeamStanding>,
+}
+
+// A registered team.
+struct Team {
+    name: String,
+    // Tickets of the players in this team.
+    players: Vec<u32>,
+    standing: TeamStanding,
+}
+
+/// Times a match can be played again after an unexpected end.
+///
+/// Past this, the team ahead on points wins.
+pub const MAX_REPLAYS: usize = 3;
+
+// A match in the current round.
+struct Match {
+    teams: [usize; 2],
+    winner: Option<usize>,
+    // Times the match was played again.
+    replays: usize,
+}
+
+struct State {
+    teams: Vec<Team>,
+    // Maps tickets to the player's team.
+    tickets: HashMap<u32, usize>,
+
+    // Matches of the current round.
+    matches: Vec<Match>,
+    // Remaining rounds, for round-robin.
+    schedule: Vec<Vec<[usize; 2]>>,
+    round: usize,
+    over: bool,
+
+    // Players waiting for their next match.
+    pending: HashMap<u32, Complete<NewPartyInfo, ()>>,
+    // Seats assigned in the current round, not yet claimed.
+    seats: HashMap<u32, NewPartyInfo>,
+}
+
+impl State {
+    fn is_started(&self) -> bool {
+        self.round > 0
+    }
+
+    fn complete_teams(&self) -> usize {
+        self.teams.iter().filter(|team| team.players.len() == 2).count()
+    }
+
+    // Gives a seat to a player, now or when he asks for it.
+    fn give_seat(&mut self, ticket: u32, info: NewPartyInfo) {
+        match self.pending.remove(&ticket) {
+            Some(promise) => promise.complete(info),
+            None => {
+                self.seats.insert(ticket, info);
+            }
+        }
+    }
+
+    // Ends the tournament, and tells everyone still waiting that there is no match for them.
+    fn end(&mut self) {
+        trace!("Tournament over");
+        self.over = true;
+        // Dropping the promise aborts the future.
+        self.pending.clear();
+    }
+
+    fn record(&mut self, index: usize, winner: usize, scores: [i32; 2]) {
+        let teams = self.matches[index].teams;
+        self.matches[index].winner = Some(winner);
+
+        for (i, &team) in teams.iter().enumerate() {
+            let standing = &mut self.teams[team].standing;
+            standing.played += 1;
+            standing.points_for += scores[i];
+            standing.points_against += scores[1 - i];
+            if team == winner {
+                standing.wins += 1;
+            } else {
+                standing.losses += 1;
+            }
+        }
+    }
+
+    // Teams still in the competition, in registration order.
+    fn contenders(&self) -> Vec<usize> {
+        (0..self.teams.len()).filter(|&t| !self.teams[t].standing.eliminated).collect()
+    }
+}
+
+/// Round-robin schedule for `n_teams` teams, using the circle method.
+///
+/// Returns the pairings of each round. With an odd number of teams, one team sits out
+/// each round.
+pub fn round_robin(n_teams: usize) -> Vec<Vec<[usize; 2]>> {
+    // Add a ghost team for byes if needed.
+    let n = if n_teams % 2 == 0 { n_teams } else { n_teams + 1 };
+    let mut circle: Vec<usize> = (0..n).collect();
+
+    let mut rounds = Vec::new();
+    for _ in 0..n - 1 {
+        let round = (0..n / 2)
+                        .map(|i| [circle[i], circle[n - 1 - i]])
+                        .filter(|m| m[0] < n_teams && m[1] < n_teams)
+                        .collect();
+        rounds.push(round);
+
+        // The first team stays, the others rotate.
+        let last = circle.pop().unwrap();
+        circle.insert(1, last);
+    }
+
+    rounds
+}
+
+struct Inner {
+    manager: Arc<GameManager>,
+    format: Format,
+    n_teams: usize,
+    target: i32,
+    state: Mutex<State>,
+}
+
+/// Runs a round-robin or knockout tournament on top of a `GameManager`.
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+impl Scheduler {
+    /// Prepares a tournament for `n_teams` pairs.
+    ///
+    /// Each match is won by the first team to reach `target` points.
+    pub fn new(manager: Arc<GameManager>, format: Format, n_teams: usize, target: i32) -> Self {
+        assert!(n_teams >= 2);
+
+        Scheduler {
+            inner: Arc::new(Inner {
+                manager: manager,
+                format: format,
+                n_teams: n_teams,
+                target: target,
+                state: Mutex::new(State {
+                    teams: Vec::new(),
+                    tickets: HashMap::new(),
+                    matches: Vec::new(),
+                    schedule: Vec::new(),
+                    round: 0,
+                    over: false,
+                    pending: HashMap::new(),
+                    seats: HashMap::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Registers a player in the given team.
+    ///
+    /// The tournament starts once every team has two players.
+    pub fn register(&self, team_name: &str) -> ManagerResult<Registration> {
+        let (ticket, complete) = try!(self.add_player(team_name));
+        if complete {
+            next_round(&self.inner);
+        }
+
+        Ok(Registration { ticket: ticket })
+    }
+
+    // Adds a player to the given team, and returns his ticket.
+    // Also returns `true` once every team is complete.
+    fn add_player(&self, team_name: &str) -> ManagerResult<(u32, bool)> {
+        let mut state = self.inner.state.lock().unwrap();
+        if state.is_started() {
+            return Err(Error::TournamentFull);
+        }
+
+        let team = match state.teams.iter().position(|team| team.name == team_name) {
+            Some(team) => team,
+            None => {
+                if state.teams.len() == self.inner.n_teams {
+                    return Err(Error::TournamentFull);
+                }
+                state.teams.push(Team {
+                    name: team_name.to_string(),
+                    players: Vec::new(),
+                    standing: TeamStanding {
+                        team: team_name.to_string(),
+                        played: 0,
+                        wins: 0,
+                        losses: 0,
+                        points_for: 0,
+                        points_against: 0,
+                        eliminated: false,
+                    },
+                });
+                state.teams.len() - 1
+            }
+        };
+
+        if state.teams[team].players.len() == 2 {
+            return Err(Error::TournamentFull);
+        }
+
+        let mut ticket = ::rand::random::<u32>();
+        while state.tickets.contains_key(&ticket) {
+            ticket = ::rand::random::<u32>();
+        }
+        state.tickets.insert(ticket, team);
+        state.teams[team].players.push(ticket);
+
+        trace!("Tournament: registered {} in team {}", ticket, team_name);
+
+        let complete = state.complete_teams() == self.inner.n_teams;
+        if complete && self.inner.format == Format::RoundRobin {
+            state.schedule = round_robin(self.inner.n_teams);
+            state.schedule.reverse();
+        }
+
+        Ok((ticket, complete))
+    }
+
+    /// Waits until the player's next match is ready, and returns his seat.
+    ///
+    /// Fails with `TournamentOver` if the player has no more match to play.
+    pub fn next_match(&self, ticket: u32) -> ManagerResult<NewPartyInfo> {
+        let future = {
+            let mut state = self.inner.state.lock().unwrap();
+            let team = match state.tickets.get(&ticket) {
+                Some(&team) => team,
+                None => return Err(Error::BadTicket),
+            };
+
+            if let Some(info) = state.seats.remove(&ticket) {
+                return Ok(info);
+            }
+            if state.over || state.teams[team].standing.eliminated {
+                return Err(Error::TournamentOver);
+            }
+
+            let (promise, future) = Future::pair();
+            state.pending.insert(ticket, promise);
+            future
+        };
+
+        // An aborted future means there is nothing left to play.
+        future.await().map_err(|_| Error::TournamentOver)
+    }
+
+    /// Returns the current standings.
+    pub fn standings(&self) -> Standings {
+        let state = self.inner.state.lock().unwrap();
+
+        let mut teams: Vec<TeamStanding> = state.teams
+                                                .iter()
+                                                .map(|team| team.standing.clone())
+                                                .collect();
+        teams.sort_by(|a, b| {
+            (a.eliminated, b.wins, b.points_for - b.points_against)
+                .cmp(&(b.eliminated, a.wins, a.points_for - a.points_against))
+        });
+
+        let champion = if state.over {
+            teams.first().map(|standing| standing.team.clone())
+        } else {
+            None
+        };
+
+        Standings {
+            format: self.inner.format,
+            round: state.round,
+            over: state.over,
+            champion: champion,
+            teams: teams,
+        }
+    }
+}
+
+// Starts the next round, or ends the tournament.
+fn next_round(inner: &Arc<Inner>) {
+    let pairings = {
+        let mut state = inner.state.lock().unwrap();
+        if state.over {
+            return;
+        }
+
+        let pairings = match inner.format {
+            Format::RoundRobin => state.schedule.pop(),
+            Format::Knockout => {
+                let contenders = state.contenders();
+                if contenders.len() < 2 {
+                    None
+                } else {
+                    // The last team gets a bye if needed.
+                    Some(contenders.chunks(2)
+                                   .filter(|c| c.len() == 2)
+                                   .map(|c| [c[0], c[1]])
+                                   .collect())
+                }
+            }
+        };
+
+        let pairings: Vec<[usize; 2]> = match pairings {
+            Some(pairings) => pairings,
+            None => {
+                state.end();
+                return;
+            }
+        };
+
+        state.round += 1;
+        state.seats.clear();
+        state.matches = pairings.iter()
+                                .map(|&teams| {
+                                    Match {
+                                        teams: teams,
+                                        winner: None,
+                                        replays: 0,
+                                    }
+                                })
+                                .collect();
+        trace!("Tournament round {}: {:?}", state.round, pairings);
+        pairings
+    };
+
+    for (index, teams) in pairings.into_iter().enumerate() {
+        start_match(inner, index, teams);
+    }
+    // Teams with a bye just keep waiting for the next round.
+}
+
+// Creates the party for a match, and tells the players where to sit.
+fn start_match(inner: &Arc<Inner>, index: usize, teams: [usize; 2]) {
+    if inner.state.lock().unwrap().over {
+        return;
+    }
+
+    // The manager has its own locks: create the party without holding ours.
+    let (party_id, infos) = inner.manager.create_party(PartySettings {
+        target: Some(inner.target),
+        ranked: true,
+        ..Default::default()
+    });
+
+    let mut state = inner.state.lock().unwrap();
+    if state.over {
+        return;
+    }
+
+    // First team at P0/P2, second team at P1/P3.
+    for (i, &team) in teams.iter().enumerate() {
+        let players = state.teams[team].players.clone();
+        for (j, &ticket) in players.iter().enumerate() {
+            state.give_seat(ticket, infos[i + 2 * j].clone());
+        }
+    }
+
+    watch_match(inner, index, party_id);
+}
+
+// How a match ended.
+#[derive(Debug,PartialEq)]
+enum Outcome {
+    // A team won, either by reaching the target or by forfeit.
+    Winner(pos::Team),
+    // The party was cancelled without anyone leaving: the match is played again.
+    Replay,
+    // The party was forgotten before we could read how it ended.
+    Vanished,
+    // The server is shutting down: the tournament ends without a result.
+    Shutdown,
+}
+
+// Reads the events of a match with `watch`, until it ends.
+// Returns how it ended, and the points scored by each team.
+fn follow_match<F>(mut watch: F) -> (Outcome, [i32; 2])
+    where F: FnMut(usize) -> ManagerResult<Event>
+{
+    let mut event_id = 0;
+    let mut scores = [0; 2];
+    loop {
+        let event = match watch(event_id) {
+            Ok(event) => event,
+            Err(err) => {
+                trace!("Tournament: lost the match: {}", err);
+                return (Outcome::Vanished, scores);
+            }
+        };
+        event_id = event.id + 1;
+
+        match event.event {
+            EventType::GameOver { scores: game_scores, .. } => {
+                scores[0] += game_scores[0];
+                scores[1] += game_scores[1];
+            }
+            EventType::MatchOver { winner, .. } => return (Outcome::Winner(winner), scores),
+            EventType::PartyCancelled(msg) => {
+                let outcome = if msg == SHUTDOWN_MESSAGE {
+                    Outcome::Shutdown
+                } else {
+                    match leaving_seat(&msg) {
+                        // The team of the player who left loses by forfeit.
+                        Some(seat) if seat as usize % 2 == 0 => Outcome::Winner(pos::Team::T13),
+                        Some(_) => Outcome::Winner(pos::Team::T02),
+                        None => Outcome::Replay,
+                    }
+                };
+                return (outcome, scores);
+            }
+            _ => (),
+        }
+    }
+}
+
+// The team ahead on points. The first team wins a tie.
+fn leader(scores: [i32; 2]) -> pos::Team {
+    if scores[1] > scores[0] {
+        pos::Team::T13
+    } else {
+        pos::Team::T02
+    }
+}
+
+// Follows a match, and records its result.
+fn watch_match(inner: &Arc<Inner>, index: usize, party_id: u32) {
+    let inner = inner.clone();
+
+    thread::spawn(move || {
+        let (outcome, scores) = follow_match(|event_id| inner.manager.watch(party_id, event_id));
+
+        let winner = match outcome {
+            Outcome::Winner(winner) => winner,
+            Outcome::Replay | Outcome::Vanished => {
+                let (teams, replays) = {
+                    let mut state = inner.state.lock().unwrap();
+                    let m = &mut state.matches[index];
+                    m.replays += 1;
+                    (m.teams, m.replays)
+                };
+                if replays <= MAX_REPLAYS {
+                    trace!("Tournament: replaying match {}", index);
+                    start_match(&inner, index, teams);
+                    return;
+                }
+                trace!("Tournament: match {} played too many times", index);
+                leader(scores)
+            }
+            Outcome::Shutdown => {
+                inner.state.lock().unwrap().end();
+                return;
+            }
+        };
+
+        let round_over = {
+            let mut state = inner.state.lock().unwrap();
+            if state.over {
+                return;
+            }
+
+            let teams = state.matches[index].teams;
+            let (winner, loser) = match winner {
+                pos::Team::T02 => (teams[0], teams[1]),
+                pos::Team::T13 => (teams[1], teams[0]),
+            };
+            trace!("Tournament: {} beat {}",
+                   state.teams[winner].name,
+                   state.teams[loser].name);
+
+            state.record(index, winner, scores);
+            if inner.format == Format::Knockout {
+                state.teams[loser].standing.eliminated = true;
+                // Nothing left to wait for.
+                let tickets = state.teams[loser].players.clone();
+                for ticket in tickets {
+                    state.pending.remove(&ticket);
+                }
+            }
+
+            state.matches.iter().all(|m| m.winner.is_some())
+        };
+
+        if round_over {
+            next_round(&inner);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use libcoinche::pos;
+
+    use {Event, EventType};
+    use super::super::error::Error;
+    use super::super::game_manager::SHUTDOWN_MESSAGE;
+    use super::{follow_match, leader, round_robin, Outcome};
+
+    // Follows a match made of the given events. The party is then forgotten.
+    fn outcome(events: Vec<EventType>) -> (Outcome, [i32; 2]) {
+        follow_match(|event_id| {
+            events.get(event_id)
+                  .map(|event| {
+                      Event {
+                          event: event.clone(),
+                          id: event_id,
+                      }
+                  })
+                  .ok_or(Error::BadPartyId)
+        })
+    }
+
+    fn game_over(scores: [i32; 2]) -> EventType {
+        EventType::GameOver {
+            points: scores,
+            winner: leader(scores),
+            scores: scores,
+        }
+    }
+
+    #[test]
+    fn match_outcomes() {
+        let won = outcome(vec![game_over([160, 0]),
+                               game_over([0, 250]),
+                               EventType::MatchOver {
+                                   winner: pos::Team::T13,
+                                   scores: [160, 250],
+                               }]);
+        assert_eq!(won, (Outcome::Winner(pos::Team::T13), [160, 250]));
+
+        // Forfeits.
+        let left = EventType::PartyCancelled("player left: 1".to_string());
+        assert_eq!(outcome(vec![left]).0, Outcome::Winner(pos::Team::T02));
+        let kicked = EventType::PartyCancelled("player kicked: 2".to_string());
+        assert_eq!(outcome(vec![kicked]).0, Outcome::Winner(pos::Team::T13));
+
+        let cancelled = EventType::PartyCancelled("admin says no".to_string());
+        assert_eq!(outcome(vec![cancelled]).0, Outcome::Replay);
+        let shutdown = EventType::PartyCancelled(SHUTDOWN_MESSAGE.to_string());
+        assert_eq!(outcome(vec![shutdown]).0, Outcome::Shutdown);
+
+        // The party disappears before its end.
+        assert_eq!(outcome(vec![game_over([80, 82])]), (Outcome::Vanished, [80, 82]));
+        assert_eq!(outcome(vec![]).0, Outcome::Vanished);
+    }
+
+    #[test]
+    fn leaders() {
+        assert_eq!(leader([80, 82]), pos::Team::T13);
+        assert_eq!(leader([82, 80]), pos::Team::T02);
+        assert_eq!(leader([0, 0]), pos::Team::T02);
+    }
+
+    #[test]
+    fn round_robin_even() {
+        let rounds = round_robin(6);
+        assert_eq!(rounds.len(), 5);
+
+        let mut met = vec![vec![0; 6]; 6];
+        for round in &rounds {
+            assert_eq!(round.len(), 3);
+            let mut playing = vec![false; 6];
+            for m in round {
+                for &team in m {
+                    // Nobody plays twice in a round.
+                    assert!(!playing[team]);
+                    playing[team] = true;
+                }
+                met[m[0]][m[1]] += 1;
+                met[m[1]][m[0]] += 1;
+            }
+        }
+
+        for a in 0..6 {
+            for b in 0..6 {
+                assert_eq!(met[a][b], if a == b { 0 } else { 1 });
+            }
+        }
+    }
+
+    #[test]
+    fn round_robin_odd() {
+        let rounds = round_robin(5);
+        assert_eq!(rounds.len(), 5);
+
+        let mut byes = vec![0; 5];
+        let mut met = vec![vec![0; 5]; 5];
+        for round in &rounds {
+            // One team sits out each round.
+            assert_eq!(round.len(), 2);
+            let mut playing = vec![false; 5];
+            for m in round {
+                assert!(m[0] < 5 && m[1] < 5);
+                playing[m[0]] = true;
+                playing[m[1]] = true;
+                met[m[0]][m[1]] += 1;
+                met[m[1]][m[0]] += 1;
+            }
+            for team in 0..5 {
+                if !playing[team] {
+                    byes[team] += 1;
+                }
+            }
+        }
+
+        assert_eq!(byes, vec![1; 5]);
+        for a in 0..5 {
+            for b in 0..5 {
+                assert_eq!(met[a][b], if a == b { 0 } else { 1 });
+            }
+        }
+    }
+
+    #[test]
+    fn two_teams() {
+        assert_eq!(round_robin(2), vec![vec![[0, 1]]]);
+    }
+}
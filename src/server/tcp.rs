@@ -0,0 +1,209 @@
+//! Binary protocol listener. See `binary`.
+//!
+//! Each connection gets its own thread, and its requests are handled in order:
+//! a `Wait` request blocks the connection until the event happens.
+//!
+//! Connections follow the same `Limits` as the HTTP API. Each one counts as a
+//! pending request for as long as it stays open.
+
+use std::io;
+use std::net::{IpAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use time;
+
+use super::error;
+use super::game_manager::GameManager;
+use super::limits::{Limiter, Refusal};
+use binary::{self, Request, Response, FrameError};
+use {Event, Error};
+
+// Name of the binary protocol in the `route` label of the metrics.
+pub const METRICS_ROUTE: &'static str = "TCP";
+
+/// Maximum number of connections open at once, from every client.
+pub const MAX_CONNECTIONS: usize = 1024;
+
+/// Connections without a request for this long are closed, in seconds.
+pub const IDLE_SECONDS: u64 = 600;
+
+/// Time to send a response, in seconds.
+pub const WRITE_TIMEOUT_SECONDS: u64 = 30;
+
+// An open connection, counted until dropped.
+struct Connection {
+    open: Arc<AtomicUsize>,
+}
+
+impl Connection {
+    fn open(open: &Arc<AtomicUsize>) -> Option<Self> {
+        if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            open.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(Connection { open: open.clone() })
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn refusal_response(refusal: Refusal) -> Response {
+    Response::Error(Error {
+        code: refusal.code().to_string(),
+        error: refusal.to_string(),
+    })
+}
+
+// The player the request is about, if any.
+fn player_id(request: &Request) -> Option<u32> {
+    match request {
+        &Request::Join(_) |
+        &Request::Enqueue(_) |
+        &Request::QueueStatus(_) => None,
+        &Request::Wait(player_id, _) |
+        &Request::Leave(player_id) |
+        &Request::Hand(player_id) |
+        &Request::Scores(player_id) |
+        &Request::Pos(player_id) |
+        &Request::Pass(player_id) |
+        &Request::Coinche(player_id) |
+        &Request::Bid(player_id, _) |
+        &Request::Play(player_id, _) |
+        &Request::Chat(player_id, _) |
+        &Request::Emote(player_id, _) => Some(player_id),
+    }
+}
+
+fn event_response(event: Event) -> Response {
+    Response::Event(event.id, event.event)
+}
+
+// Runs the request within the limits, turning errors into `Response::Error`.
+fn handle(manager: &GameManager, limiter: &Limiter, ip: IpAddr, request: Request) -> Response {
+    let checked = limiter.check_ip(ip).and_then(|_| match player_id(&request) {
+        Some(player_id) => limiter.check_player(player_id),
+        None => Ok(()),
+    });
+    if let Err(refusal) = checked {
+        return refusal_response(refusal);
+    }
+
+    let result = match request {
+        Request::Join(body) => manager.join(body).map(Response::Party),
+        Request::Enqueue(body) => manager.enqueue(body).map(Response::Queue),
+        Request::QueueStatus(ticket) => manager.queue_status(ticket).map(Response::Queue),
+        Request::Wait(player_id, event_id) => manager.wait(player_id, event_id).map(event_response),
+        Request::Leave(player_id) => manager.leave(player_id).map(|_| Response::Ok),
+        Request::Hand(player_id) => manager.see_hand(player_id).map(Response::Hand),
+        Request::Scores(player_id) => manager.see_scores(player_id).map(Response::Scores),
+        Request::Pos(player_id) => manager.see_pos(player_id).map(Response::Pos),
+        Request::Pass(player_id) => manager.pass(player_id).map(event_response),
+        Request::Coinche(player_id) => manager.coinche(player_id).map(event_response),
+        Request::Bid(player_id, contract) => manager.bid(player_id, contract).map(event_response),
+        Request::Play(player_id, card) => manager.play_card(player_id, card).map(event_response),
+        Request::Chat(player_id, body) => manager.chat(player_id, body).map(|_| Response::Ok),
+        Request::Emote(player_id, body) => manager.emote(player_id, body).map(|_| Response::Ok),
+    };
+
+    match result {
+        Ok(response) => response,
+        Err(err) => {
+            manager.metrics().count_error(&err);
+            if let error::Error::BadPlayerId = err {
+                limiter.bad_player_id(ip);
+            }
+            Response::Error(Error {
+                code: err.code(),
+                error: err.to_string(),
+            })
+        }
+    }
+}
+
+// Serves a single client, until it disconnects.
+fn serve(manager: &GameManager,
+         limiter: &Limiter,
+         ip: IpAddr,
+         mut stream: TcpStream)
+         -> Result<(), FrameError> {
+    try!(stream.set_read_timeout(Some(Duration::from_secs(IDLE_SECONDS))));
+    try!(stream.set_write_timeout(Some(Duration::from_secs(WRITE_TIMEOUT_SECONDS))));
+
+    // The connection holds a pending request while it is open.
+    let _pending = match limiter.begin_pending(ip) {
+        Ok(pending) => pending,
+        Err(refusal) => return binary::write_frame(&mut stream, &refusal_response(refusal)),
+    };
+
+    loop {
+        let request: Request = match binary::read_frame(&mut stream) {
+            Ok(request) => request,
+            // The client closed the connection.
+            Err(FrameError::Io(ref err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(())
+            }
+            Err(err) => return Err(err),
+        };
+        trace!("TCP request: {:?}", request);
+
+        let start = time::precise_time_ns();
+        let response = handle(manager, limiter, ip, request);
+        let micros = (time::precise_time_ns() - start) / 1000;
+        manager.metrics().record_request(METRICS_ROUTE, micros as usize);
+
+        try!(binary::write_frame(&mut stream, &response));
+    }
+}
+
+/// Accepts binary protocol clients on the given address, in a background thread.
+///
+/// Up to `MAX_CONNECTIONS` clients are served at once; the others are closed right away.
+pub fn listen(manager: Arc<GameManager>,
+              limiter: Arc<Limiter>,
+              address: &str,
+              port: u16)
+              -> io::Result<thread::JoinHandle<()>> {
+    let listener = try!(TcpListener::bind((address, port)));
+    let open = Arc::new(AtomicUsize::new(0));
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    trace!("Could not accept TCP client: {}", err);
+                    continue;
+                }
+            };
+            let ip = match stream.peer_addr() {
+                Ok(addr) => addr.ip(),
+                Err(err) => {
+                    trace!("Could not get the TCP client address: {}", err);
+                    continue;
+                }
+            };
+            let connection = match Connection::open(&open) {
+                Some(connection) => connection,
+                None => {
+                    trace!("Too many TCP clients, closing {}", ip);
+                    continue;
+                }
+            };
+
+            let manager = manager.clone();
+            let limiter = limiter.clone();
+            thread::spawn(move || {
+                let _connection = connection;
+                if let Err(err) = serve(&manager, &limiter, ip, stream) {
+                    trace!("TCP client dropped: {:?}", err);
+                }
+            });
+        }
+    }))
+}
@@ -0,0 +1,495 @@
+//! Duplicate tournament: pairs play the same deals at different tables.
+//!
+//! The tournament uses a complete Howell movement: every pair meets every
+//! other pair once, so there are `n_pairs - 1` rounds. All tables play the
+//! same deals in a given round, and new deals are used for each round.
+//!
+//! The pairs switch sides from one round to the next, so every pair plays
+//! both directions, and is compared with most of the field.
+//!
+//! Each pair is scored against the pairs who held the same cards
+//! (matchpoints): 2 points for each better result, 1 for each tie.
+
+use std::cmp::{min, max};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use eventual::{Future, Complete, Async};
+
+use libcoinche;
+use {EventType, NewPartyInfo, Registration};
+
+use super::error::Error;
+use super::game_manager::{Deal, GameManager, ManagerResult, PartySettings, SHUTDOWN_MESSAGE};
+use super::scheduler::round_robin;
+
+/// Standing of a single pair in the tournament.
+#[derive(Clone,Debug,PartialEq,RustcEncodable,RustcDecodable)]
+pub struct Standing {
+    /// Pair number, in order of registration.
+    pub pair: usize,
+    /// Number of deals this pair completed.
+    pub deals_played: usize,
+    /// Total matchpoints.
+    pub matchpoints: i32,
+}
+
+/// Current leaderboard, sorted by decreasing matchpoints.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct Leaderboard {
+    /// Current round, starting at 1.
+    pub round: usize,
+    /// Number of rounds in the tournament.
+    pub rounds: usize,
+    /// Number of deals played at every table.
+    pub deals: usize,
+    /// `true` once every table played every deal.
+    pub complete: bool,
+    pub standings: Vec<Standing>,
+}
+
+// Pairs sitting at each table, for each round: [North-South, East-West].
+//
+// The lower numbered pair sits North-South at even tables in even rounds,
+// and at odd tables in odd rounds.
+fn movement(n_pairs: usize) -> Vec<Vec<[usize; 2]>> {
+    round_robin(n_pairs)
+        .into_iter()
+        .enumerate()
+        .map(|(round, pairings)| {
+            pairings.into_iter()
+                    .enumerate()
+                    .map(|(table, pairs)| {
+                        let (low, high) = (min(pairs[0], pairs[1]), max(pairs[0], pairs[1]));
+                        if (table + round) % 2 == 0 {
+                            [low, high]
+                        } else {
+                            [high, low]
+                        }
+                    })
+                    .collect()
+        })
+        .collect()
+}
+
+// Computes the standing of each pair, sorted by decreasing matchpoints.
+//
+// `seatings[round][table]` has the pairs at the table, and
+// `results[round][table][deal]` the scores of the deal ([NS, EW]), once played.
+// Rounds without results are ignored.
+fn standings(n_pairs: usize,
+             seatings: &[Vec<[usize; 2]>],
+             results: &[Vec<Vec<Option<[i32; 2]>>>])
+             -> Vec<Standing> {
+    let mut standings: Vec<Standing> = (0..n_pairs)
+                                           .map(|pair| {
+                                               Standing {
+                                                   pair: pair,
+                                                   deals_played: 0,
+                                                   matchpoints: 0,
+                                               }
+                                           })
+                                           .collect();
+
+    for (seating, results) in seatings.iter().zip(results) {
+        for (t, pairs) in seating.iter().enumerate() {
+            for (deal, result) in results[t].iter().enumerate() {
+                let scores = match *result {
+                    Some(scores) => scores,
+                    None => continue,
+                };
+
+                for side in 0..2 {
+                    // Compare with the results of the same cards at other tables.
+                    let mut matchpoints = 0;
+                    for (other, other_results) in results.iter().enumerate() {
+                        match other_results[deal] {
+                            Some(other_scores) if other != t => {
+                                if scores[side] > other_scores[side] {
+                                    matchpoints += 2;
+                                } else if scores[side] == other_scores[side] {
+                                    matchpoints += 1;
+                                }
+                            }
+                            _ => (),
+                        }
+                    }
+
+                    let standing = &mut standings[pairs[side]];
+                    standing.deals_played += 1;
+                    standing.matchpoints += matchpoints;
+                }
+            }
+        }
+    }
+
+    standings.sort_by(|a, b| b.matchpoints.cmp(&a.matchpoints));
+    standings
+}
+
+struct State {
+    // Tickets of the registered players, in order of arrival.
+    // Player `i` belongs to pair `i / 2`.
+    players: Vec<u32>,
+
+    // Current round, starting at 1. 0 if the tournament didn't start yet.
+    round: usize,
+    over: bool,
+
+    // For each round so far, the scores of each deal at each table.
+    results: Vec<Vec<Vec<Option<[i32; 2]>>>>,
+    // Tables of the current round still playing.
+    playing: usize,
+
+    // Players waiting for their next table.
+    pending: HashMap<u32, Complete<NewPartyInfo, ()>>,
+    // Seats assigned in the current round, not yet claimed.
+    seats: HashMap<u32, NewPartyInfo>,
+}
+
+impl State {
+    // Gives a seat to a player, now or when he asks for it.
+    fn give_seat(&mut self, ticket: u32, info: NewPartyInfo) {
+        match self.pending.remove(&ticket) {
+            Some(promise) => promise.complete(info),
+            None => {
+                self.seats.insert(ticket, info);
+            }
+        }
+    }
+
+    fn end(&mut self) {
+        trace!("Duplicate tournament over");
+        self.over = true;
+        // Dropping the promises aborts the futures.
+        self.pending.clear();
+    }
+}
+
+struct Inner {
+    manager: Arc<GameManager>,
+    n_pairs: usize,
+    deals_per_round: usize,
+    deals: Vec<Deal>,
+    // Pairs at each table, for each round.
+    movement: Vec<Vec<[usize; 2]>>,
+    state: Mutex<State>,
+}
+
+/// Runs a duplicate tournament on top of a `GameManager`.
+pub struct DuplicateTournament {
+    inner: Arc<Inner>,
+}
+
+impl DuplicateTournament {
+    /// Prepares a tournament for `n_pairs` pairs, playing `deals_per_round` deals each round.
+    ///
+    /// `n_pairs` must be even and at least 4. There are `n_pairs - 1` rounds.
+    pub fn new(manager: Arc<GameManager>, n_pairs: usize, deals_per_round: usize) -> Self {
+        assert!(n_pairs >= 4 && n_pairs % 2 == 0);
+        assert!(deals_per_round > 0);
+
+        let n_deals = deals_per_round * (n_pairs - 1);
+
+        DuplicateTournament {
+            inner: Arc::new(Inner {
+                manager: manager,
+                n_pairs: n_pairs,
+                deals_per_round: deals_per_round,
+                deals: (0..n_deals).map(|_| libcoinche::deal_hands()).collect(),
+                movement: movement(n_pairs),
+                state: Mutex::new(State {
+                    players: Vec::new(),
+                    round: 0,
+                    over: false,
+                    results: Vec::new(),
+                    playing: 0,
+                    pending: HashMap::new(),
+                    seats: HashMap::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Joins the tournament.
+    ///
+    /// Players are grouped in pairs in order of arrival.
+    /// The first round starts once every seat is taken.
+    pub fn join(&self) -> ManagerResult<Registration> {
+        let (ticket, full) = {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.players.len() == 2 * self.inner.n_pairs {
+                return Err(Error::TournamentFull);
+            }
+
+            let mut ticket = ::rand::random::<u32>();
+            while state.players.contains(&ticket) {
+                ticket = ::rand::random::<u32>();
+            }
+            state.players.push(ticket);
+            trace!("Duplicate tournament: player {} registered", ticket);
+
+            (ticket, state.players.len() == 2 * self.inner.n_pairs)
+        };
+
+        if full {
+            // Everyone is here, let's go!
+            next_round(&self.inner);
+        }
+
+        Ok(Registration { ticket: ticket })
+    }
+
+    /// Waits until the player's table for the next round is ready, and returns his seat.
+    ///
+    /// Fails with `TournamentOver` once every round was played.
+    pub fn next_table(&self, ticket: u32) -> ManagerResult<NewPartyInfo> {
+        let future = {
+            let mut state = self.inner.state.lock().unwrap();
+            if !state.players.contains(&ticket) {
+                return Err(Error::BadTicket);
+            }
+
+            if let Some(info) = state.seats.remove(&ticket) {
+                return Ok(info);
+            }
+            if state.over {
+                return Err(Error::TournamentOver);
+            }
+
+            let (promise, future) = Future::pair();
+            state.pending.insert(ticket, promise);
+            future
+        };
+
+        // An aborted future means there is nothing left to play.
+        future.await().map_err(|_| Error::TournamentOver)
+    }
+
+    /// Computes the current leaderboard.
+    pub fn leaderboard(&self) -> ManagerResult<Leaderboard> {
+        let state = self.inner.state.lock().unwrap();
+        if state.round == 0 {
+            return Err(Error::NoTournament);
+        }
+
+        // Deals played at every table so far.
+        let deals = state.results
+                         .iter()
+                         .map(|tables| {
+                             (0..self.inner.deals_per_round)
+                                 .filter(|&deal| tables.iter().all(|t| t[deal].is_some()))
+                                 .count()
+                         })
+                         .fold(0, |a, b| a + b);
+
+        Ok(Leaderboard {
+            round: state.round,
+            rounds: self.inner.movement.len(),
+            deals: deals,
+            complete: deals == self.inner.deals.len(),
+            standings: standings(self.inner.n_pairs, &self.inner.movement, &state.results),
+        })
+    }
+}
+
+// Starts the next round, or ends the tournament.
+fn next_round(inner: &Arc<Inner>) {
+    let (round, seating) = {
+        let mut state = inner.state.lock().unwrap();
+        if state.over {
+            return;
+        }
+        if state.round == inner.movement.len() {
+            state.end();
+            return;
+        }
+
+        let seating = inner.movement[state.round].clone();
+        state.round += 1;
+        state.seats.clear();
+        state.results.push(vec![vec![None; inner.deals_per_round]; seating.len()]);
+        state.playing = seating.len();
+        trace!("Duplicate tournament round {}: {:?}", state.round, seating);
+
+        (state.round - 1, seating)
+    };
+
+    let start = round * inner.deals_per_round;
+    // The manager has its own locks: create the parties without holding ours.
+    let settings = PartySettings {
+        deals: inner.deals[start..start + inner.deals_per_round].to_vec(),
+        ranked: true,
+        ..Default::default()
+    };
+    let parties: Vec<(u32, [NewPartyInfo; 4])> =
+        seating.iter().map(|_| inner.manager.create_party(settings.clone())).collect();
+
+    let mut state = inner.state.lock().unwrap();
+    for (table, (pairs, &(party_id, ref infos))) in seating.iter().zip(&parties).enumerate() {
+        trace!("Duplicate tournament table {}: party {}", table, party_id);
+
+        // P0 and P2 from the NS pair, P1 and P3 from the EW pair.
+        for (seat, info) in infos.iter().enumerate() {
+            let player = 2 * pairs[seat % 2] + seat / 2;
+            let ticket = state.players[player];
+            state.give_seat(ticket, info.clone());
+        }
+
+        watch_table(inner, round, table, party_id);
+    }
+}
+
+// Follows the events of a table, and records the result of each deal.
+fn watch_table(inner: &Arc<Inner>, round: usize, table: usize, party_id: u32) {
+    let inner = inner.clone();
+
+    thread::spawn(move || {
+        let mut event_id = 0;
+        let mut deal = 0;
+        let mut shutdown = false;
+        while let Ok(event) = inner.manager.watch(party_id, event_id) {
+            event_id = event.id + 1;
+
+            let scores = match event.event {
+                EventType::GameOver { scores, .. } => scores,
+                // Nobody wanted to play this deal.
+                EventType::BidCancelled => [0, 0],
+                EventType::PartyCancelled(msg) => {
+                    shutdown = msg == SHUTDOWN_MESSAGE;
+                    break;
+                }
+                _ => continue,
+            };
+
+            let mut state = inner.state.lock().unwrap();
+            if deal < inner.deals_per_round {
+                state.results[round][table][deal] = Some(scores);
+            }
+            deal += 1;
+        }
+        trace!("Duplicate tournament table {} is over", table);
+
+        let round_over = {
+            let mut state = inner.state.lock().unwrap();
+            state.playing -= 1;
+            if shutdown {
+                state.end();
+            }
+            state.playing == 0
+        };
+        if round_over {
+            next_round(&inner);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{movement, standings, Standing};
+
+    #[test]
+    fn pairs_meet_every_other_pair() {
+        for &n_pairs in &[4, 6, 8, 10] {
+            let movement = movement(n_pairs);
+            assert_eq!(movement.len(), n_pairs - 1);
+
+            let mut met = vec![vec![false; n_pairs]; n_pairs];
+            for seating in &movement {
+                assert_eq!(seating.len(), n_pairs / 2);
+
+                // Everyone plays exactly once per round.
+                let mut seated: Vec<usize> = seating.iter().flat_map(|p| p.to_vec()).collect();
+                seated.sort();
+                assert_eq!(seated, (0..n_pairs).collect::<Vec<_>>());
+
+                for pairs in seating {
+                    assert!(!met[pairs[0]][pairs[1]]);
+                    met[pairs[0]][pairs[1]] = true;
+                    met[pairs[1]][pairs[0]] = true;
+                }
+            }
+
+            for a in 0..n_pairs {
+                for b in 0..n_pairs {
+                    assert_eq!(met[a][b], a != b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pairs_play_both_directions() {
+        for &n_pairs in &[4, 6, 8, 10] {
+            let mut ns = vec![0; n_pairs];
+            let mut ew = vec![0; n_pairs];
+            for seating in movement(n_pairs) {
+                for pairs in seating {
+                    ns[pairs[0]] += 1;
+                    ew[pairs[1]] += 1;
+                }
+            }
+            for pair in 0..n_pairs {
+                assert!(ns[pair] > 0 && ew[pair] > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn pairs_are_compared_with_most_of_the_field() {
+        for &n_pairs in &[4, 6, 8, 10] {
+            let mut compared = vec![vec![false; n_pairs]; n_pairs];
+            for seating in movement(n_pairs) {
+                // Pairs sitting on the same side hold the same cards.
+                for side in 0..2 {
+                    for a in &seating {
+                        for b in &seating {
+                            compared[a[side]][b[side]] = true;
+                        }
+                    }
+                }
+            }
+            for pair in 0..n_pairs {
+                let others = (0..n_pairs).filter(|&other| other != pair && compared[pair][other]);
+                assert!(others.count() >= n_pairs - 2);
+            }
+        }
+    }
+
+    #[test]
+    fn matchpoints() {
+        let seatings = vec![vec![[0, 2], [3, 1]]];
+        let results = vec![vec![vec![Some([120, 40]), Some([0, 160])],
+                                vec![Some([90, 70]), Some([0, 160])]]];
+
+        let standings = standings(4, &seatings, &results);
+        let find = |pair| standings.iter().find(|s| s.pair == pair).unwrap().clone();
+
+        // First deal: 120 beats 90 for NS, and 70 beats 40 for EW.
+        // Second deal: identical results, everyone ties.
+        assert_eq!(find(0),
+                   Standing {
+                       pair: 0,
+                       deals_played: 2,
+                       matchpoints: 3,
+                   });
+        assert_eq!(find(2).matchpoints, 1);
+        assert_eq!(find(3).matchpoints, 1);
+        assert_eq!(find(1).matchpoints, 3);
+        assert_eq!(standings[0].matchpoints, 3);
+    }
+
+    #[test]
+    fn unplayed_deals_are_not_compared() {
+        let seatings = vec![vec![[0, 2], [3, 1]]];
+        let results = vec![vec![vec![Some([120, 40])], vec![None]]];
+
+        let standings = standings(4, &seatings, &results);
+        for standing in &standings {
+            assert_eq!(standing.matchpoints, 0);
+            let played = if standing.pair == 0 || standing.pair == 2 { 1 } else { 0 };
+            assert_eq!(standing.deals_played, played);
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! HTML5 web client, embedded in the binary.
+//!
+//! The page only talks to the JSON API, like any other client.
+
+/// The page served at `/`.
+pub const INDEX: &'static str = include_str!("web/index.html");
+
+// Files served under `/static/`, with their content type.
+const ASSETS: [(&'static str, &'static str, &'static str); 2] =
+    [("coinche.js", "application/javascript", include_str!("web/coinche.js")),
+     ("coinche.css", "text/css", include_str!("web/coinche.css"))];
+
+/// Returns the content type and content of the given static file.
+pub fn asset(name: &str) -> Option<(&'static str, &'static str)> {
+    ASSETS.iter()
+          .find(|&&(file, _, _)| file == name)
+          .map(|&(_, mime, content)| (mime, content))
+}
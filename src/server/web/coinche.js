@@ -0,0 +1,368 @@
+// Web client for coinched.
+//
+// Joins through the matchmaking queue, then follows the party with
+// long-polling on `/wait`. Rules are checked by the server: rejected
+// actions are reported, and the player is asked again.
+(function() {
+  'use strict';
+
+  // Protocol version spoken by this client.
+  var PROTOCOL = 1;
+
+  // Suits, as encoded by libcoinche.
+  var SUITS = [
+    { value: 1, symbol: '♥', red: true },
+    { value: 1 << 8, symbol: '♠', red: false },
+    { value: 1 << 16, symbol: '♦', red: true },
+    { value: 1 << 24, symbol: '♣', red: false }
+  ];
+  // Ranks, in bit order inside a suit.
+  var RANKS = ['7', '8', '9', 'J', 'Q', 'K', '10', 'A'];
+  var TARGETS = ['80', '90', '100', '110', '120', '130', '140', '150', '160',
+                 'Capot', 'Generale'];
+
+  var game = {
+    playerId: null,
+    pos: 0,
+    eventId: 0,
+    hand: 0,
+    contract: null,
+    bid: null,
+    coinched: false,
+    turn: null,
+    trick: [null, null, null, null],
+    tricks: [0, 0],
+    scores: [0, 0],
+    over: false
+  };
+
+  function $(id) {
+    return document.getElementById(id);
+  }
+
+  // Sends a JSON request. `done` gets the parsed response,
+  // `failed` gets the error object sent by the server.
+  function request(method, url, body, done, failed) {
+    var xhr = new XMLHttpRequest();
+    xhr.open(method, url);
+    xhr.setRequestHeader('Content-Type', 'application/json');
+    xhr.onload = function() {
+      var data = null;
+      try {
+        data = JSON.parse(xhr.responseText);
+      } catch (e) {
+        data = { code: 'invalid_response', error: xhr.responseText };
+      }
+      if (xhr.status === 200) {
+        done(data);
+      } else {
+        (failed || showError)(data);
+      }
+    };
+    xhr.onerror = function() {
+      (failed || showError)({ code: 'network', error: 'cannot reach the server' });
+    };
+    xhr.send(body === null ? null : JSON.stringify(body));
+  }
+
+  function showError(err) {
+    status((err && err.error) || 'unknown error');
+  }
+
+  function status(text) {
+    $('status').textContent = text;
+  }
+
+  function log(text) {
+    var li = document.createElement('li');
+    li.textContent = text;
+    $('log').insertBefore(li, $('log').firstChild);
+  }
+
+  // Position relative to the player: 0 is us, 1 is on our left...
+  function relative(pos) {
+    return (pos - game.pos + 4) % 4;
+  }
+
+  function seatName(pos) {
+    return ['You', 'Left', 'Partner', 'Right'][relative(pos)];
+  }
+
+  function suitOf(card) {
+    for (var i = 0; i < SUITS.length; i++) {
+      if (card & (0xff * SUITS[i].value)) {
+        return SUITS[i];
+      }
+    }
+    return SUITS[0];
+  }
+
+  function suitSymbol(value) {
+    for (var i = 0; i < SUITS.length; i++) {
+      if (SUITS[i].value === value) {
+        return SUITS[i].symbol;
+      }
+    }
+    return '?';
+  }
+
+  function cardName(card) {
+    var bit = Math.round(Math.log(card) / Math.LN2);
+    return RANKS[bit % 8] + suitOf(card).symbol;
+  }
+
+  function cardElement(card) {
+    var span = document.createElement('span');
+    span.className = 'card' + (suitOf(card).red ? ' red' : '');
+    span.textContent = cardName(card);
+    return span;
+  }
+
+  // Lists the cards in a 32-bit hand.
+  function handCards(hand) {
+    var cards = [];
+    for (var i = 0; i < 32; i++) {
+      // `>>> 0` keeps the club bits unsigned.
+      var card = (1 << i) >>> 0;
+      if ((hand >>> 0) & card) {
+        cards.push(card);
+      }
+    }
+    return cards;
+  }
+
+  function render() {
+    var positions = ['bottom', 'left', 'top', 'right'];
+    for (var pos = 0; pos < 4; pos++) {
+      var seat = $('seat-' + positions[relative(pos)]);
+      seat.innerHTML = '';
+      seat.className = 'seat' + (game.turn === pos ? ' turn' : '');
+      seat.appendChild(document.createTextNode(seatName(pos)));
+      seat.appendChild(document.createElement('br'));
+      if (game.trick[pos] !== null) {
+        seat.appendChild(cardElement(game.trick[pos]));
+      }
+    }
+
+    var myTurn = game.turn === game.pos;
+    var playing = myTurn && game.contract !== null;
+    var hand = $('hand');
+    hand.innerHTML = '';
+    handCards(game.hand).forEach(function(card) {
+      var element = cardElement(card);
+      if (playing) {
+        element.className += ' playable';
+        element.onclick = function() { play(card); };
+      } else {
+        element.className += ' disabled';
+      }
+      hand.appendChild(element);
+    });
+
+    $('auction').className = myTurn && game.contract === null ? '' : 'hidden';
+    renderAuction();
+
+    var contract = '-';
+    if (game.contract) {
+      contract = game.contract.target + ' ' + suitSymbol(game.contract.trump) +
+                 ' (' + seatName(game.contract.author) + ')';
+    } else if (game.bid) {
+      contract = game.bid.target + ' ' + suitSymbol(game.bid.suit) +
+                 ' (' + seatName(game.bid.pos) + ', bidding)';
+    }
+    $('contract').textContent = contract;
+
+    // Our team is always shown first.
+    var us = game.pos % 2;
+    $('scores').textContent = game.scores[us] + ' - ' + game.scores[1 - us];
+    $('tricks').textContent = game.tricks[us] + ' - ' + game.tricks[1 - us];
+  }
+
+  // Only offers targets above the current bid.
+  function renderAuction() {
+    var select = $('bid-target');
+    var current = game.bid ? TARGETS.indexOf(game.bid.target) : -1;
+    select.innerHTML = '';
+    if (!game.coinched) {
+      TARGETS.slice(current + 1).forEach(function(target) {
+        var option = document.createElement('option');
+        option.value = option.textContent = target;
+        select.appendChild(option);
+      });
+    }
+    $('bid').disabled = select.options.length === 0;
+    $('coinche').disabled = !game.bid || game.coinched ||
+                            game.bid.pos % 2 === game.pos % 2;
+  }
+
+  function handle(event) {
+    switch (event.type) {
+      case 'NewGame':
+        game.hand = event.cards;
+        game.contract = null;
+        game.bid = null;
+        game.coinched = false;
+        game.trick = [null, null, null, null];
+        game.tricks = [0, 0];
+        game.turn = event.first;
+        log('New game, ' + seatName(event.first) + ' starts.');
+        break;
+      case 'YourTurn':
+        game.turn = game.pos;
+        status('Your turn!');
+        break;
+      case 'FromPlayer':
+        fromPlayer(event.pos, event.event);
+        game.turn = (event.pos + 1) % 4;
+        break;
+      case 'BidOver':
+        game.contract = event.contract;
+        game.turn = null;
+        log('Contract: ' + event.contract.target + ' ' + suitSymbol(event.contract.trump) +
+            ' by ' + seatName(event.contract.author) + '.');
+        break;
+      case 'BidCancelled':
+        game.turn = null;
+        log('Everybody passed, new deal.');
+        break;
+      case 'TrickOver':
+        game.tricks[event.winner % 2] += 1;
+        game.trick = [null, null, null, null];
+        game.turn = event.winner;
+        log(seatName(event.winner) + ' won the trick.');
+        break;
+      case 'GameOver':
+        game.scores[0] += event.scores[0];
+        game.scores[1] += event.scores[1];
+        game.turn = null;
+        log('Game over: ' + event.points[0] + ' - ' + event.points[1] + '.');
+        break;
+      case 'MatchOver':
+        game.over = true;
+        game.turn = null;
+        status(event.winner === game.pos % 2 ? 'You won the match!' : 'You lost the match.');
+        break;
+      case 'PartyCancelled':
+        game.over = true;
+        game.turn = null;
+        status('Party cancelled: ' + event.msg);
+        break;
+    }
+    render();
+  }
+
+  function fromPlayer(pos, event) {
+    var who = seatName(pos);
+    switch (event.type) {
+      case 'Bidded':
+        game.bid = { pos: pos, suit: event.suit, target: event.target };
+        game.coinched = false;
+        log(who + ' bid ' + event.target + ' ' + suitSymbol(event.suit) + '.');
+        break;
+      case 'Passed':
+        log(who + ' passed.');
+        break;
+      case 'Coinched':
+        game.coinched = true;
+        log(who + ' coinched!');
+        break;
+      case 'CardPlayed':
+        game.trick[pos] = event.card;
+        if (pos === game.pos) {
+          game.hand = ((game.hand >>> 0) & ~event.card) >>> 0;
+        }
+        break;
+    }
+  }
+
+  // Long-polls the next event, forever.
+  function wait() {
+    if (game.over) {
+      return;
+    }
+    request('GET', '/v1/wait/' + game.playerId + '/' + game.eventId, null, function(event) {
+      game.eventId = event.id + 1;
+      handle(event.event);
+      wait();
+    }, function(err) {
+      showError(err);
+      game.over = true;
+    });
+  }
+
+  // The server checks the rules: a rejected action lets the player try again.
+  function act(action, body) {
+    status('');
+    request('POST', '/v1/' + action + '/' + game.playerId, body, function() {
+      // The event comes back through `/wait`.
+    }, function(err) {
+      status('Rejected: ' + err.error);
+    });
+  }
+
+  function play(card) {
+    act('play', { card: card });
+  }
+
+  function start(party) {
+    if (party.protocol !== PROTOCOL) {
+      $('queue-status').textContent = 'This page is out of date, please reload it.';
+      return;
+    }
+    game.playerId = party.player_id;
+    game.pos = party.player_pos;
+    $('join').className = 'hidden';
+    $('table').className = '';
+    render();
+    wait();
+  }
+
+  function pollQueue(ticket) {
+    request('GET', '/v1/queue/' + ticket, null, function(queue) {
+      if (queue.party) {
+        start(queue.party);
+      } else {
+        $('queue-status').textContent = 'Waiting for players (position ' + queue.position + ')';
+        setTimeout(function() { pollQueue(ticket); }, 1000);
+      }
+    }, function(err) {
+      $('queue-status').textContent = err.error;
+    });
+  }
+
+  function init() {
+    SUITS.forEach(function(suit) {
+      var option = document.createElement('option');
+      option.value = suit.value;
+      option.textContent = suit.symbol;
+      $('bid-suit').appendChild(option);
+    });
+
+    $('join-form').onsubmit = function(e) {
+      e.preventDefault();
+      var name = $('name').value;
+      request('POST', '/v1/queue', name ? { name: name } : {}, function(queue) {
+        pollQueue(queue.ticket);
+      }, function(err) {
+        $('queue-status').textContent = err.error;
+      });
+    };
+
+    $('bid').onclick = function() {
+      act('bid', {
+        target: $('bid-target').value,
+        suit: parseInt($('bid-suit').value, 10)
+      });
+    };
+    $('pass').onclick = function() { act('pass', null); };
+    $('coinche').onclick = function() { act('coinche', null); };
+    $('leave').onclick = function() {
+      request('POST', '/v1/leave/' + game.playerId, null, function() {
+        game.over = true;
+        status('You left the party.');
+      });
+    };
+  }
+
+  init();
+})();